@@ -0,0 +1,167 @@
+//! How this node reaches its peers.
+//!
+//! A snapshot of the endpoint's connection info: for every peer whether the
+//! path is direct, relayed or both, its latency and the addresses in use, plus
+//! the home relay and the addresses this node can be reached on. Public
+//! addresses found through STUN next to the local ones show what the NAT maps
+//! to. While they're watched, snapshots are emitted every [`INTERVAL`].
+
+use std::time::Duration;
+
+use iroh::{
+    endpoint::{ConnectionType, DirectAddrType, RemoteInfo},
+    Endpoint,
+};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::time;
+
+use crate::{
+    error::Error,
+    events,
+    state::{AppState, State},
+};
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Path {
+    Direct,
+    Relay,
+    /// Holepunching is in progress, data goes both ways until it settles.
+    Mixed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerInfo {
+    pub node_id: String,
+    pub path: Path,
+    /// Round trip time in milliseconds.
+    pub rtt_ms: Option<u64>,
+    /// The direct address used, if any.
+    pub addr: Option<String>,
+    /// The relay used, if any.
+    pub relay: Option<String>,
+    /// Every direct address known for the peer.
+    pub known_addrs: Vec<String>,
+    /// Milliseconds since data was last sent to the peer.
+    pub last_used_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddrKind {
+    /// A local interface address.
+    Local,
+    /// The address the NAT maps to, as seen through STUN.
+    Public,
+    /// Opened on the router through UPnP, PCP or NAT-PMP.
+    Portmapped,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalAddr {
+    pub addr: String,
+    pub kind: AddrKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostics {
+    pub node_id: String,
+    pub home_relay: Option<String>,
+    pub local_addrs: Vec<LocalAddr>,
+    /// Peers with an open path, most recently used first.
+    pub peers: Vec<PeerInfo>,
+}
+
+fn peer_info(info: RemoteInfo) -> Option<PeerInfo> {
+    let (path, addr, relay) = match info.conn_type {
+        ConnectionType::Direct(addr) => (Path::Direct, Some(addr.to_string()), None),
+        ConnectionType::Relay(url) => (Path::Relay, None, Some(url.to_string())),
+        ConnectionType::Mixed(addr, url) => {
+            (Path::Mixed, Some(addr.to_string()), Some(url.to_string()))
+        }
+        ConnectionType::None => return None,
+    };
+
+    Some(PeerInfo {
+        node_id: info.node_id.to_string(),
+        path,
+        rtt_ms: info.latency.map(|d| d.as_millis() as u64),
+        addr,
+        relay,
+        known_addrs: info.addrs.iter().map(|a| a.addr.to_string()).collect(),
+        last_used_ms: info.last_used.map(|d| d.as_millis() as u64),
+    })
+}
+
+fn collect(endpoint: &Endpoint) -> Diagnostics {
+    let home_relay = endpoint.home_relay().get().ok().flatten();
+    let local_addrs = endpoint
+        .direct_addresses()
+        .get()
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|a| LocalAddr {
+            addr: a.addr.to_string(),
+            kind: match a.typ {
+                DirectAddrType::Local => AddrKind::Local,
+                DirectAddrType::Stun | DirectAddrType::Stun4LocalPort => AddrKind::Public,
+                DirectAddrType::Portmapped => AddrKind::Portmapped,
+                DirectAddrType::Unknown => AddrKind::Unknown,
+            },
+        })
+        .collect();
+
+    let mut peers = endpoint
+        .remote_info_iter()
+        .filter_map(peer_info)
+        .collect::<Vec<_>>();
+    peers.sort_by_key(|p| p.last_used_ms.unwrap_or(u64::MAX));
+
+    Diagnostics {
+        node_id: endpoint.node_id().to_string(),
+        home_relay: home_relay.map(|url| url.to_string()),
+        local_addrs,
+        peers,
+    }
+}
+
+#[tauri::command]
+pub async fn get_diagnostics(state: State<'_>) -> Result<Diagnostics, Error> {
+    Ok(collect(state.iroh().endpoint()))
+}
+
+/// Emit diagnostics every [`INTERVAL`] until [`unwatch_diagnostics`] is called.
+#[tauri::command]
+pub async fn watch_diagnostics(handle: AppHandle) -> Result<(), Error> {
+    let state = handle.state::<AppState>();
+    let mut task = state.diagnostics.lock().await;
+    if task.is_some() {
+        return Ok(());
+    }
+
+    *task = Some(tokio::spawn(emit_diagnostics(handle.clone())));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unwatch_diagnostics(state: State<'_>) -> Result<(), Error> {
+    if let Some(task) = state.diagnostics.lock().await.take() {
+        task.abort();
+    }
+    Ok(())
+}
+
+async fn emit_diagnostics(handle: AppHandle) {
+    let mut interval = time::interval(INTERVAL);
+    loop {
+        interval.tick().await;
+        let diagnostics = collect(handle.state::<AppState>().iroh().endpoint());
+        handle.emit(events::DIAGNOSTICS, diagnostics).ok();
+    }
+}
@@ -0,0 +1,302 @@
+//! Receiving a whole share as one archive.
+//!
+//! Files are fetched like a regular download and then streamed from the store
+//! into a zip or tar archive in the download folder, without exporting them
+//! one by one. Compressed files are decompressed on the way in.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use iroh_blobs::rpc::client::blobs::Reader;
+use log::{error, info, warn};
+use serde::Deserialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::{io::AsyncReadExt, runtime::Handle};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::error::Error;
+use crate::file_operations::compress;
+use crate::iroh::BlobsClient;
+use crate::progress::Transfer;
+use crate::state::AppState;
+use crate::ticket::AnyTicket;
+use crate::{events, files, snippet, storage, utils};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarZst,
+}
+
+impl ArchiveFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarZst => "tar.zst",
+        }
+    }
+}
+
+/// Download the share behind `ticket` into a single archive in the download
+/// folder. Returns the path of the archive.
+#[tauri::command]
+pub async fn download_archive(
+    ticket: String,
+    format: ArchiveFormat,
+    handle: AppHandle,
+) -> Result<String, Error> {
+    let export_dir = utils::get_download_dir(&handle)?;
+    let state = handle.state::<AppState>();
+    let iroh = match cfg!(debug_assertions) {
+        true => &state.iroh_debug,
+        false => state.iroh(),
+    };
+
+    let ticket = AnyTicket::from_str(&ticket)?
+        .resolve(iroh.endpoint())
+        .await?;
+    let remote_node_addr = ticket.node_addr().clone();
+    let root = ticket.hash();
+    let keep_received = state.settings.lock().await.keep_received_blobs;
+    let files = files::collection::fetch_manifest(&iroh.blobs, ticket).await?;
+
+    let transfer = Arc::new(std::sync::Mutex::new(Transfer::default()));
+    let mut tasks = Vec::new();
+    for file in files.values().cloned() {
+        // Names end up as paths inside the archive
+        utils::join_shared_name(&export_dir, &file.name)?;
+
+        let payload = events::DownloadFileAdded {
+            name: file.name.clone(),
+            icon: file.icon.clone(),
+            size: file.size,
+            kind: file.kind,
+            hash: file.hash,
+        };
+        handle.emit(events::DOWNLOAD_FILE_ADDED, payload).ok();
+        if file.kind.is_file() {
+            if let Ok(mut transfer) = transfer.lock() {
+                transfer.add(&file.name, file.blob_size());
+            }
+        }
+
+        let handle = handle.clone();
+        let remote_node_addr = remote_node_addr.clone();
+        let transfer = Arc::clone(&transfer);
+        tasks.push(tokio::spawn(async move {
+            let name = file.name.clone();
+            let res = match file.kind.is_file() {
+                true => {
+                    let tag = storage::download_tag(root, file.hash);
+                    let state = handle.state::<AppState>();
+                    let iroh = match cfg!(debug_assertions) {
+                        true => &state.iroh_debug,
+                        false => state.iroh(),
+                    };
+                    super::fetch_file(&handle, iroh, &file, remote_node_addr, root, tag, &transfer)
+                        .await
+                        .map(|()| Some(file))
+                }
+                false => {
+                    snippet::receive(&handle, file, remote_node_addr, root, keep_received, false)
+                        .await
+                        .map(|()| None)
+                }
+            };
+            res.map_err(|error| {
+                error!("Failed to download file: {}", error);
+                let payload = events::DownloadFileError { name, error };
+                handle.emit(events::DOWNLOAD_FILE_ERROR, payload).ok();
+            })
+        }));
+    }
+
+    let mut entries = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        match task.await {
+            Ok(Ok(Some(file))) => entries.push(file),
+            Ok(_) => {}
+            Err(e) => error!("Failed to await task: {}", e),
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if keep_received {
+        let hashes = entries.iter().map(|file| file.hash).collect::<Vec<_>>();
+        state.blob_access.lock().await.touch(hashes);
+    }
+
+    let res = match entries.is_empty() {
+        true => Err(Error::from("None of the files could be downloaded")),
+        false => {
+            let path = archive_path(&export_dir, root, format);
+            info!("Writing {} files to {}", entries.len(), path.display());
+            write(&handle, iroh.blobs.clone(), &path, format, entries)
+                .await
+                .map(|()| path.display().to_string())
+                .map_err(Error::from)
+        }
+    };
+
+    if !keep_received {
+        let prefix = storage::download_prefix(root);
+        if let Err(e) = storage::delete_tags_with_prefix(&iroh.blobs, &prefix).await {
+            warn!("Failed to release download: {}", e);
+        }
+    }
+    handle.emit(events::DOWNLOAD_ALL_COMPLETE, ()).ok();
+
+    res
+}
+
+/// A path for the archive of the share `root` that doesn't exist yet.
+fn archive_path(dir: &Path, root: iroh_blobs::Hash, format: ArchiveFormat) -> PathBuf {
+    let stem = format!("sendit-{}", &root.to_hex()[..8]);
+    let ext = format.extension();
+
+    (0..)
+        .map(|n| match n {
+            0 => dir.join(format!("{stem}.{ext}")),
+            n => dir.join(format!("{stem} ({n}).{ext}")),
+        })
+        .find(|path| !path.exists())
+        .expect("Unbounded range")
+}
+
+/// Stream `files` from the store into a new archive at `path`. The archive is
+/// removed again if it can't be completed.
+async fn write(
+    handle: &AppHandle,
+    blobs: BlobsClient,
+    path: &Path,
+    format: ArchiveFormat,
+    files: Vec<files::File>,
+) -> Result<(), String> {
+    let runtime = Handle::current();
+    let (handle, target) = (handle.clone(), path.to_path_buf());
+
+    let task = tokio::task::spawn_blocking(move || {
+        let archive =
+            fs::File::create(&target).map_err(|e| format!("Failed to create archive: {}", e))?;
+
+        let mut added = |file: &files::File| {
+            let payload = events::DownloadFileCompleted {
+                name: file.name.clone(),
+                path: target.display().to_string(),
+            };
+            handle.emit(events::DOWNLOAD_FILE_COMPLETED, payload).ok();
+        };
+        let read = |file: &files::File| open(&runtime, &blobs, file);
+
+        match format {
+            ArchiveFormat::Zip => {
+                let mut zip = ZipWriter::new(archive);
+                for file in &files {
+                    let options =
+                        SimpleFileOptions::default().large_file(file.size > u32::MAX as u64);
+                    zip.start_file(file.name.as_str(), options)
+                        .map_err(|e| format!("Failed to add {}: {}", file.name, e))?;
+                    io::copy(&mut read(file)?, &mut zip)
+                        .map_err(|e| format!("Failed to add {}: {}", file.name, e))?;
+                    added(file);
+                }
+                zip.finish()
+                    .map_err(|e| format!("Failed to finish archive: {}", e))?;
+            }
+            ArchiveFormat::Tar => {
+                let tar = append_tar(tar::Builder::new(archive), &files, read, added)?;
+                tar.into_inner()
+                    .map_err(|e| format!("Failed to finish archive: {}", e))?;
+            }
+            ArchiveFormat::TarZst => {
+                let encoder = zstd::Encoder::new(archive, compress::LEVEL)
+                    .map_err(|e| format!("Failed to create archive: {}", e))?;
+                let tar = append_tar(tar::Builder::new(encoder), &files, read, added)?;
+                tar.into_inner()
+                    .and_then(|encoder| encoder.finish())
+                    .map_err(|e| format!("Failed to finish archive: {}", e))?;
+            }
+        }
+
+        Ok(())
+    });
+    let res = match task.await {
+        Ok(res) => res,
+        Err(e) => Err(format!("Archive task failed: {}", e)),
+    };
+
+    if res.is_err() {
+        fs::remove_file(path).ok();
+    }
+    res
+}
+
+fn append_tar<W: Write>(
+    mut tar: tar::Builder<W>,
+    files: &[files::File],
+    read: impl Fn(&files::File) -> Result<Box<dyn Read>, String>,
+    mut added: impl FnMut(&files::File),
+) -> Result<tar::Builder<W>, String> {
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    for file in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(file.size);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        tar.append_data(&mut header, &file.name, read(file)?)
+            .map_err(|e| format!("Failed to add {}: {}", file.name, e))?;
+        added(file);
+    }
+
+    Ok(tar)
+}
+
+/// Read the contents of `file` from the store, decompressed if needed. Reads
+/// fail unless the contents are exactly `file.size` bytes, entries are written
+/// with that size up front.
+fn open(
+    runtime: &Handle,
+    blobs: &BlobsClient,
+    file: &files::File,
+) -> Result<Box<dyn Read>, String> {
+    let reader = runtime
+        .block_on(blobs.read(file.hash))
+        .map_err(|e| format!("Failed to read {}: {}", file.name, e))?;
+    let reader = BlobReader {
+        runtime: runtime.clone(),
+        reader,
+    };
+
+    match file.compression {
+        Some(compression) => compress::decoder(reader, compression, file.size)
+            .map(|decoder| Box::new(decoder) as Box<dyn Read>)
+            .map_err(|e| format!("Failed to decompress {}: {}", file.name, e)),
+        // The store already checked the blob against its hash
+        None => Ok(Box::new(compress::Verified::new(reader, file.size, None))),
+    }
+}
+
+/// Reads a blob through the async client from a blocking thread.
+struct BlobReader {
+    runtime: Handle,
+    reader: Reader,
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.runtime.block_on(self.reader.read(buf))
+    }
+}
@@ -1,17 +1,34 @@
+pub mod archive;
+pub mod retry;
+
 use iroh_gossip::net::GossipReceiver;
 use log::{error, info, warn};
 use n0_future::stream::StreamExt;
+use std::iter;
 use std::str::FromStr;
-use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Instant};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 use tauri::{AppHandle, Emitter, Listener, Manager};
 
+use crate::error::Error;
+use crate::file_operations::compress;
+use crate::iroh::{BlobsClient, Iroh};
+use crate::progress::{Meter, Transfer};
 use crate::state::AppState;
-use crate::{events, files, state::State, utils};
+use crate::ticket::AnyTicket;
+use crate::{events, files, snippet, storage, stream, utils};
+use bao_tree::{io::BaoContentItem, ChunkRanges};
 use iroh::NodeAddr;
 use iroh_blobs::{
-    get::db::DownloadProgress,
-    store::{ExportFormat, ExportMode},
+    get::{
+        db::DownloadProgress,
+        fsm::{self, BlobContentNext, ConnectedNext, EndBlobNext},
+    },
+    protocol::{GetRequest, RangeSpecSeq},
+    rpc::client::blobs::{DownloadMode, DownloadOptions},
+    store::{BaoBatchWriter, ExportFormat, ExportMode, MapEntryMut, MapMut},
     ticket::BlobTicket,
+    util::SetTagOption,
+    BlobFormat, Hash, HashAndFormat, Tag,
 };
 
 pub async fn subscribe_upload_progress(rx: GossipReceiver) {
@@ -19,14 +36,33 @@ pub async fn subscribe_upload_progress(rx: GossipReceiver) {
 }
 
 #[tauri::command]
-pub async fn download_header(
-    state: State<'_>,
-    ticket: String,
-    handle: AppHandle,
-) -> Result<(), String> {
+pub async fn download_header(ticket: String, handle: AppHandle) -> Result<(), Error> {
     info!("Downloading with ticket: {}", ticket);
+    let export_dir = utils::get_download_dir(&handle)?;
+
+    let ticket = {
+        let state = handle.state::<AppState>();
+        let iroh = match cfg!(debug_assertions) {
+            true => &state.iroh_debug,
+            false => state.iroh(),
+        };
+        AnyTicket::from_str(&ticket)?
+            .resolve(iroh.endpoint())
+            .await?
+    };
+
+    download_share(handle, ticket, export_dir).await
+}
+
+/// Download every file described by the header behind `ticket` into `export_dir`.
+pub async fn download_share(
+    handle: AppHandle,
+    ticket: BlobTicket,
+    export_dir: PathBuf,
+) -> Result<(), Error> {
     let handle = Arc::new(handle);
-    let export_dir = Arc::new(utils::get_download_dir(&handle)?);
+    let export_dir = Arc::new(export_dir);
+    let state = handle.state::<AppState>();
 
     let iroh = match cfg!(debug_assertions) {
         true => &state.iroh_debug,
@@ -34,36 +70,91 @@ pub async fn download_header(
     };
 
     let blobs = &iroh.blobs;
-
-    let ticket =
-        BlobTicket::from_str(&ticket).map_err(|e| format!("Failed to parse ticket: {}", e))?;
     let remote_node_addr = ticket.node_addr().clone();
+    let root = ticket.hash();
+    let keep_received = state.settings.lock().await.keep_received_blobs;
 
     // Download and read the header file
-    let header_content = utils::download_and_read_header(&blobs, ticket).await?;
+    let mut files = files::collection::fetch_manifest(blobs, ticket).await?;
 
     let handles = std::sync::Mutex::new(HashMap::new());
-    let mut files = files::Files::from_str(header_content.as_str())?;
+    let transfer = Arc::new(std::sync::Mutex::new(Transfer::default()));
     let mut tasks = Vec::with_capacity(files.len());
+    let mut icons: HashMap<Hash, Vec<String>> = HashMap::new();
+    let mut thumbnails: Vec<(String, Hash)> = Vec::new();
+    // A share holding nothing but a snippet is a shared clipboard
+    let copy_snippet = files.len() == 1;
 
     for (_, file) in files.drain() {
         let payload = events::DownloadFileAdded {
             name: file.name.clone(),
             icon: file.icon.clone(),
             size: file.size.clone(),
+            kind: file.kind,
+            hash: file.hash,
         };
 
         handle.emit(events::DOWNLOAD_FILE_ADDED, payload).ok();
 
+        // Snippets are too small to count towards the totals
+        if file.kind.is_file() {
+            if let Ok(mut transfer) = transfer.lock() {
+                transfer.add(&file.name, file.blob_size());
+            }
+        }
+
+        // Files can be played from the store while they download
+        if file.kind.is_file() && file.compression.is_none() {
+            state.streams.lock().await.register(
+                file.hash,
+                &file.name,
+                file.size,
+                remote_node_addr.clone(),
+            );
+        }
+
+        if let Some(icon_hash) = file.icon_hash {
+            icons.entry(icon_hash).or_default().push(file.name.clone());
+        }
+        if let Some(thumbnail_hash) = file.thumbnail_hash {
+            thumbnails.push((file.name.clone(), thumbnail_hash));
+        }
+
         let export_dir = Arc::clone(&export_dir);
         let handle = Arc::clone(&handle);
         let filename = file.name.clone();
         let remote_node_addr = remote_node_addr.clone();
+        let transfer = Arc::clone(&transfer);
 
         // Spawn a new task for each file download
         let task = tokio::spawn(async move {
             let name = file.name.clone();
-            let res = download_file(&handle, file, &export_dir, remote_node_addr).await;
+            let res = match file.kind.is_file() {
+                true => {
+                    download_file(
+                        &handle,
+                        file,
+                        &export_dir,
+                        remote_node_addr,
+                        root,
+                        keep_received,
+                        false,
+                        &transfer,
+                    )
+                    .await
+                }
+                false => {
+                    snippet::receive(
+                        &handle,
+                        file,
+                        remote_node_addr,
+                        root,
+                        keep_received,
+                        copy_snippet,
+                    )
+                    .await
+                }
+            };
             if let Err(error) = res {
                 error!("Failed to download file: {}", error);
                 let payload = events::DownloadFileError { name, error };
@@ -81,8 +172,18 @@ pub async fn download_header(
         tasks.push(task);
     }
 
+    // Icons and thumbnails are fetched after the file list is shown
+    tokio::spawn(load_icons(
+        Arc::clone(&handle),
+        icons,
+        thumbnails,
+        remote_node_addr.clone(),
+        root,
+    ));
+
     // Listen for cancel download events
     let handle_for_listener = Arc::clone(&handle);
+    let transfer_for_listener = Arc::clone(&transfer);
     let listener = handle.listen(events::CANCEL_DOWNLOAD, move |event| {
         let filename = event.payload().replace("\"", ""); // Remove quotes
         if let Ok(mut handles) = handles.lock() {
@@ -90,13 +191,23 @@ pub async fn download_header(
                 handle.abort();
                 let payload = events::DownloadFileAborted {
                     name: filename.clone(),
-                    reason: "Cancelled by user".to_string(),
+                    error: Error::Cancelled {
+                        name: filename.clone(),
+                    },
                 };
                 handle_for_listener
                     .as_ref()
                     .emit(events::DOWNLOAD_FILE_ABORTED, payload.clone())
                     .ok();
                 info!("Download cancelled for file: {}", filename);
+
+                if let Ok(mut transfer) = transfer_for_listener.lock() {
+                    let progress = transfer.remove(&filename);
+                    handle_for_listener
+                        .as_ref()
+                        .emit(events::DOWNLOAD_PROGRESS, progress)
+                        .ok();
+                }
             }
         }
     });
@@ -108,6 +219,14 @@ pub async fn download_header(
         }
     }
 
+    // Headers and icons are only needed while downloading
+    if !keep_received {
+        let prefix = storage::download_prefix(root);
+        if let Err(e) = storage::delete_tags_with_prefix(blobs, &prefix).await {
+            warn!("Failed to release download: {}", e);
+        }
+    }
+
     // Emit downloads completion event
     handle
         .as_ref()
@@ -119,12 +238,74 @@ pub async fn download_header(
     Ok(())
 }
 
-async fn download_file(
+/// Fetch icon blobs and emit them per file. Files sharing an icon fetch it once.
+/// Thumbnails follow once every icon is shown.
+async fn load_icons(
+    handle: Arc<AppHandle>,
+    icons: HashMap<Hash, Vec<String>>,
+    thumbnails: Vec<(String, Hash)>,
+    remote_node_addr: NodeAddr,
+    root: Hash,
+) {
+    let state = handle.state::<AppState>();
+
+    let iroh = match cfg!(debug_assertions) {
+        true => &state.iroh_debug,
+        false => state.iroh(),
+    };
+
+    for (hash, names) in icons {
+        let tag = storage::download_tag(root, hash);
+        let res =
+            utils::download_blob_bytes(&iroh.blobs, hash, remote_node_addr.clone(), tag).await;
+        let icon = match res {
+            Ok(png) => utils::png_to_data_url(&png),
+            Err(e) => {
+                warn!("Failed to load icon {}: {}", hash, e);
+                continue;
+            }
+        };
+
+        for name in names {
+            let payload = events::DownloadFileIcon {
+                name,
+                icon: icon.clone(),
+            };
+            handle.emit(events::DOWNLOAD_FILE_ICON, payload).ok();
+        }
+    }
+
+    for (name, hash) in thumbnails {
+        let tag = storage::download_tag(root, hash);
+        let res =
+            utils::download_blob_bytes(&iroh.blobs, hash, remote_node_addr.clone(), tag).await;
+        match res {
+            Ok(jpeg) => {
+                let payload = events::DownloadFileThumbnail {
+                    name,
+                    thumbnail: utils::jpeg_to_data_url(&jpeg),
+                };
+                handle.emit(events::DOWNLOAD_FILE_THUMBNAIL, payload).ok();
+            }
+            Err(e) => warn!("Failed to load thumbnail {}: {}", hash, e),
+        }
+    }
+}
+
+/// Download `file` from the share `root` and export it into `export_dir`.
+///
+/// With `replace_existing` an existing copy is swapped for the new one once
+/// the export finished, otherwise the download fails if it exists.
+pub async fn download_file(
     handle: &AppHandle,
     file: files::File,
     export_dir: &PathBuf,
     remote_node_addr: NodeAddr,
-) -> Result<(), String> {
+    root: Hash,
+    keep_received: bool,
+    replace_existing: bool,
+    transfer: &std::sync::Mutex<Transfer>,
+) -> Result<(), Error> {
     info!("Started downloading file: {}", file.name);
     let state = handle.state::<AppState>();
 
@@ -135,11 +316,13 @@ async fn download_file(
 
     let blobs = &iroh.blobs;
 
-    let dest = export_dir.join(&file.name);
+    let dest = utils::join_shared_name(export_dir, &file.name)?;
 
     // Check if file exists before starting download
-    if dest.exists() {
-        let err = format!("File already exists");
+    if dest.exists() && !replace_existing {
+        let err = Error::FileExists {
+            name: file.name.clone(),
+        };
         handle
             .emit(
                 events::DOWNLOAD_FILE_ERROR,
@@ -152,13 +335,350 @@ async fn download_file(
         return Err(err);
     }
 
+    let tag = storage::download_tag(root, file.hash);
+    fetch_file(
+        handle,
+        iroh,
+        &file,
+        remote_node_addr,
+        root,
+        tag.clone(),
+        transfer,
+    )
+    .await?;
+
+    // Export the downloaded file, shared folders keep their layout
+    if let Some(parent) = dest.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create folder: {}", e))?;
+    }
+
+    // Replacements are exported next to the old copy, which is never left half written
+    let target = match replace_existing {
+        true => {
+            let mut part = dest.clone().into_os_string();
+            part.push(".part");
+            PathBuf::from(part)
+        }
+        false => dest.clone(),
+    };
+
+    // Compressed blobs are exported next to the target and decompressed into it
+    let exported = match file.compression {
+        Some(_) => {
+            let mut zst = target.clone().into_os_string();
+            zst.push(".zst");
+            PathBuf::from(zst)
+        }
+        None => target.clone(),
+    };
+
+    blobs
+        .export(
+            file.hash,
+            exported.clone(),
+            ExportFormat::Blob,
+            ExportMode::Copy,
+        )
+        .await
+        .map_err(|e| format!("Error exporting file: {}", e))?
+        .finish()
+        .await
+        .map_err(|e| format!("Error finishing export: {}", e))?;
+
+    if let Some(compression) = file.compression {
+        let res = compress::decompress(&exported, &target, compression, file.size).await;
+        tokio::fs::remove_file(&exported).await.ok();
+        res?;
+    }
+
+    if target != dest {
+        tokio::fs::rename(&target, &dest)
+            .await
+            .map_err(|e| format!("Failed to replace {}: {}", file.name, e))?;
+    }
+
+    info!("Exported file to: {}", file.name);
+
+    // The exported copy is all the receiver needs, let GC drop the blob
+    if keep_received {
+        state.blob_access.lock().await.touch([file.hash]);
+    } else {
+        storage::delete_tag(blobs, tag).await?;
+    }
+
+    // Emit completion event
+    handle
+        .emit(
+            events::DOWNLOAD_FILE_COMPLETED,
+            events::DownloadFileCompleted {
+                name: file.name.clone(),
+                path: dest.display().to_string(),
+            },
+        )
+        .ok();
+
+    Ok(())
+}
+
+/// Download the blob of `file` from the share `root` under `tag`, reporting
+/// its progress and that of the whole `transfer` to the receive page. Network
+/// errors are retried as set in the [`RetryPolicy`](retry::RetryPolicy).
+pub async fn fetch_file(
+    handle: &AppHandle,
+    iroh: &Iroh,
+    file: &files::File,
+    remote_node_addr: NodeAddr,
+    root: Hash,
+    tag: Tag,
+    transfer: &std::sync::Mutex<Transfer>,
+) -> Result<(), Error> {
+    let policy = handle.state::<AppState>().settings.lock().await.retry;
+
+    // Protects what arrived so far from GC between attempts, so a retry resumes from it
+    let batch = iroh
+        .blobs
+        .batch()
+        .await
+        .map_err(|e| format!("Failed to open batch: {}", e))?;
+    let _partial = batch
+        .temp_tag(HashAndFormat::raw(file.hash))
+        .await
+        .map_err(|e| format!("Failed to protect download: {}", e))?;
+
+    // Shares with a collection serve their files as its children
+    let index = child_index(&iroh.blobs, root, file.hash).await;
+
+    let mut retry = 0;
+    let res = loop {
+        let res = match index {
+            Some(index) => {
+                fetch_child(
+                    handle,
+                    iroh,
+                    file,
+                    remote_node_addr.clone(),
+                    root,
+                    index,
+                    transfer,
+                )
+                .await
+            }
+            None => {
+                fetch_blob(
+                    handle,
+                    &iroh.blobs,
+                    file,
+                    remote_node_addr.clone(),
+                    tag.clone(),
+                    transfer,
+                )
+                .await
+            }
+        };
+
+        match res {
+            Err(error) if error.retryable() && retry < policy.max_retries => {
+                retry += 1;
+                let delay = policy.delay(retry);
+                warn!(
+                    "Retrying {} ({}/{}) in {:?}: {}",
+                    file.name, retry, policy.max_retries, delay, error
+                );
+                let payload = events::DownloadFileRetrying {
+                    name: file.name.clone(),
+                    retry,
+                    max_retries: policy.max_retries,
+                    delay_ms: delay.as_millis() as u64,
+                    error,
+                };
+                handle.emit(events::DOWNLOAD_FILE_RETRYING, payload).ok();
+                tokio::time::sleep(delay).await;
+            }
+            res => break res,
+        }
+    };
+
+    // Children are written to the store directly, tag them like the downloader would
+    let res = match (res, index) {
+        (Ok(()), Some(_)) => iroh
+            .blobs
+            .tags()
+            .set(tag, HashAndFormat::raw(file.hash))
+            .await
+            .map_err(|e| Error::from(format!("Failed to tag {}: {}", file.name, e))),
+        (res, _) => res,
+    };
+
+    if let Ok(mut transfer) = transfer.lock() {
+        let progress = match res {
+            Ok(()) => transfer.finish(&file.name),
+            Err(_) => transfer.remove(&file.name),
+        };
+        handle.emit(events::DOWNLOAD_PROGRESS, progress).ok();
+    }
+    res
+}
+
+/// Position of `hash` in the hash sequence of the share collection `root`, or
+/// `None` for shares made of a bare header, whose files are fetched by hash.
+async fn child_index(blobs: &BlobsClient, root: Hash, hash: Hash) -> Option<usize> {
+    let collection = blobs.get_collection(root).await.ok()?;
+    // The sequence starts with the collection metadata, then the members in order
+    collection
+        .iter()
+        .position(|(_, member)| *member == hash)
+        .map(|position| position + 1)
+}
+
+/// Emit the progress of `file` and of the whole `transfer`. `offset` and `size`
+/// count bytes of the blob, the unit `file` was added to `transfer` with.
+fn report_progress(
+    handle: &AppHandle,
+    file: &files::File,
+    meter: &mut Meter,
+    offset: u64,
+    size: u64,
+    transfer: &std::sync::Mutex<Transfer>,
+) {
+    let payload = events::DownloadFileProgress {
+        name: file.name.clone(),
+        progress: meter.update(offset, size),
+        size: file.size,
+    };
+    handle.emit(events::DOWNLOAD_FILE_PROGRESS, payload).ok();
+
+    if let Ok(mut transfer) = transfer.lock() {
+        let progress = transfer.update(&file.name, offset, size);
+        handle.emit(events::DOWNLOAD_PROGRESS, progress).ok();
+    }
+}
+
+/// Fetch the missing chunks of `file` as child `index` of the hash sequence
+/// `root`. The sender can't answer with anything the collection doesn't hold,
+/// and the data is verified against the hash the collection lists.
+async fn fetch_child(
+    handle: &AppHandle,
+    iroh: &Iroh,
+    file: &files::File,
+    remote_node_addr: NodeAddr,
+    root: Hash,
+    index: usize,
+    transfer: &std::sync::Mutex<Transfer>,
+) -> Result<(), Error> {
+    let failed = |message: String| Error::Transfer {
+        name: file.name.clone(),
+        message,
+    };
+    let store = &iroh.store;
+
+    let missing = stream::missing_chunks(store, file.hash, ChunkRanges::all()).await?;
+    if missing.is_empty() {
+        info!("Found Local: {}", file.name);
+        return Ok(());
+    }
+
+    let connection = iroh
+        .endpoint()
+        .connect(remote_node_addr, iroh_blobs::ALPN)
+        .await
+        .map_err(|e| Error::Connection {
+            message: format!("Failed to connect to sender: {}", e),
+        })?;
+
+    // The root itself is already here, only the child's missing chunks are asked for
+    let ranges = iter::repeat(ChunkRanges::empty())
+        .take(index + 1)
+        .chain([missing]);
+    let request = GetRequest::new(root, RangeSpecSeq::from_ranges(ranges));
+
+    let connected = fsm::start(connection, request)
+        .next()
+        .await
+        .map_err(|e| failed(format!("Failed to request file: {}", e)))?;
+    let ConnectedNext::StartChild(start) = connected
+        .next()
+        .await
+        .map_err(|e| failed(format!("Failed to request file: {}", e)))?
+    else {
+        return Err(failed("Sender returned no data".to_string()));
+    };
+    let (mut content, size) = start
+        .next(file.hash)
+        .next()
+        .await
+        .map_err(|e| failed(format!("Failed to download file: {}", e)))?;
+
+    let io = |e: std::io::Error| failed(format!("Failed to write file: {}", e));
+    let entry = store.get_or_create(file.hash, size).await.map_err(io)?;
+    let mut writer = entry.batch_writer().await.map_err(io)?;
+
+    let mut meter = Meter::default();
+    let mut throttle = utils::Throttle::new(std::time::Duration::from_millis(100));
+    let mut items = Vec::new();
+    let end = loop {
+        match content.next().await {
+            BlobContentNext::More((next, item)) => {
+                let item = item.map_err(|e| failed(format!("Error during download: {}", e)))?;
+                // Parents are written together with the leaf they lead to
+                let offset = match &item {
+                    BaoContentItem::Leaf(leaf) => Some(leaf.offset + leaf.data.len() as u64),
+                    BaoContentItem::Parent(_) => None,
+                };
+                items.push(item);
+                if let Some(offset) = offset {
+                    writer
+                        .write_batch(size, std::mem::take(&mut items))
+                        .await
+                        .map_err(io)?;
+                    if throttle.is_free() && size > 0 {
+                        report_progress(handle, file, &mut meter, offset, size, transfer);
+                    }
+                }
+                content = next;
+            }
+            BlobContentNext::Done(end) => break end,
+        }
+    };
+
+    writer.sync().await.map_err(io)?;
+    drop(writer);
+    store.insert_complete(entry).await.map_err(io)?;
+    info!("All Done: {}", file.name);
+
+    if let EndBlobNext::Closing(closing) = end.next() {
+        closing.next().await.ok();
+    }
+    Ok(())
+}
+
+/// Download the blob of `file` by its hash, for shares without a collection.
+async fn fetch_blob(
+    handle: &AppHandle,
+    blobs: &BlobsClient,
+    file: &files::File,
+    remote_node_addr: NodeAddr,
+    tag: Tag,
+    transfer: &std::sync::Mutex<Transfer>,
+) -> Result<(), Error> {
+    let failed = |message: String| Error::Transfer {
+        name: file.name.clone(),
+        message,
+    };
+    let opts = DownloadOptions {
+        format: BlobFormat::Raw,
+        nodes: vec![remote_node_addr],
+        tag: SetTagOption::Named(tag),
+        mode: DownloadMode::Queued,
+    };
+
     let mut r = blobs
-        .download(file.hash, remote_node_addr)
+        .download_with_opts(file.hash, opts)
         .await
-        .map_err(|e| format!("Failed to download file: {}", e))?;
+        .map_err(|e| failed(format!("Failed to download file: {}", e)))?;
 
-    let mut last_offset = 0;
-    let mut timestamp = Instant::now();
+    let mut meter = Meter::default();
     let mut size: u64 = 0;
     let mut throttle = utils::Throttle::new(std::time::Duration::from_millis(100));
 
@@ -176,26 +696,8 @@ async fn download_file(
                     size = s;
                 }
 
-                DP::Progress { offset, .. } if throttle.is_free() => {
-                    let now = Instant::now();
-                    let elapsed = timestamp.elapsed();
-                    let speed = if elapsed.as_micros() > 0 {
-                        (offset - last_offset) as f32 / elapsed.as_micros() as f32
-                    } else {
-                        0.0
-                    };
-                    timestamp = now;
-                    last_offset = offset;
-
-                    if size > 0 {
-                        let percentage = (offset as f32 / size as f32) * 100.0;
-                        let payload = events::DownloadFileProgress {
-                            name: file.name.clone(),
-                            progress: percentage,
-                            speed,
-                        };
-                        handle.emit(events::DOWNLOAD_FILE_PROGRESS, payload).ok();
-                    }
+                DP::Progress { offset, .. } if throttle.is_free() && size > 0 => {
+                    report_progress(handle, file, &mut meter, offset, size, transfer);
                 }
 
                 DP::AllDone(..) => {
@@ -206,46 +708,9 @@ async fn download_file(
                 e => warn!("Unhandled download event: {:?}", e),
             },
 
-            Err(e) => {
-                handle
-                    .emit(
-                        events::DOWNLOAD_FILE_ERROR,
-                        events::DownloadFileError {
-                            name: file.name.clone(),
-                            error: e.to_string(),
-                        },
-                    )
-                    .ok();
-                return Err(format!("Error during download: {}", e));
-            }
+            Err(e) => return Err(failed(format!("Error during download: {}", e))),
         }
     }
-    // Export the downloaded file
-    blobs
-        .export(
-            file.hash,
-            dest.clone(),
-            ExportFormat::Blob,
-            ExportMode::Copy,
-        )
-        .await
-        .map_err(|e| format!("Error exporting file: {}", e))?
-        .finish()
-        .await
-        .map_err(|e| format!("Error finishing export: {}", e))?;
-
-    info!("Exported file to: {}", file.name);
-
-    // Emit completion event
-    handle
-        .emit(
-            events::DOWNLOAD_FILE_COMPLETED,
-            events::DownloadFileCompleted {
-                name: file.name.clone(),
-                path: dest.display().to_string(),
-            },
-        )
-        .ok();
 
     Ok(())
 }
@@ -0,0 +1,43 @@
+//! Retrying downloads that broke off.
+//!
+//! Only errors in the [`Category::Network`](crate::error::Category) category
+//! are retried, waiting twice as long after every attempt. What already arrived
+//! stays in the store, so the next attempt only fetches the rest.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Attempts after the first one, 0 turns retrying off.
+    pub max_retries: u32,
+    /// Wait before the first retry.
+    pub initial_delay_ms: u64,
+    /// Longest wait between two attempts.
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_delay_ms: 1000,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before the `retry`th retry, starting at 1. Spread out a
+    /// little so files that failed together don't all retry at once.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u64.saturating_pow(retry.saturating_sub(1));
+        let delay = self
+            .initial_delay_ms
+            .saturating_mul(factor)
+            .min(self.max_delay_ms);
+        Duration::from_millis(delay).mul_f64(0.8 + rand::random::<f64>() * 0.4)
+    }
+}
@@ -0,0 +1,211 @@
+//! Errors returned by commands and sent in the `*_ERROR` events.
+//!
+//! Errors are serialized as `{ code, category, retryable, message, context }`.
+//! The `code` of a variant never changes, so the frontend can match on it
+//! instead of on the message. Helpers deeper down still return plain strings,
+//! those become [`Error::Internal`] when they reach a command, so anything that
+//! may be retried has to return a typed error where it fails.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    /// Reaching or talking to another node failed.
+    Network,
+    /// Reading or writing local files or the blob store failed.
+    Io,
+    /// The request itself can't be carried out as given.
+    Validation,
+    /// Another node sent something that can't be understood.
+    Protocol,
+    /// Stopped on request.
+    Cancelled,
+    /// Failed in a way the app has no better description for.
+    Internal,
+}
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// The download folder already holds a file of that name.
+    FileExists {
+        name: String,
+    },
+    /// A shared file of that name already exists.
+    DuplicateName {
+        name: String,
+    },
+    /// The name can't be used as a file name.
+    InvalidName {
+        name: String,
+    },
+    InvalidTicket {
+        reason: String,
+    },
+    /// The `what` given doesn't parse or isn't allowed.
+    InvalidInput {
+        what: &'static str,
+        reason: String,
+    },
+    /// No `what` with the id `id` is known.
+    NotFound {
+        what: &'static str,
+        id: String,
+    },
+    TooLarge {
+        name: String,
+        size: u64,
+        limit: u64,
+    },
+    /// The other node couldn't be reached.
+    Connection {
+        message: String,
+    },
+    /// Transferring `name` broke off.
+    Transfer {
+        name: String,
+        message: String,
+    },
+    /// The other node sent something this version can't read.
+    Protocol {
+        message: String,
+    },
+    Cancelled {
+        name: String,
+    },
+    Io {
+        message: String,
+    },
+    Internal {
+        message: String,
+    },
+}
+
+impl Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::FileExists { .. } => "file_exists",
+            Error::DuplicateName { .. } => "duplicate_name",
+            Error::InvalidName { .. } => "invalid_name",
+            Error::InvalidTicket { .. } => "invalid_ticket",
+            Error::InvalidInput { .. } => "invalid_input",
+            Error::NotFound { .. } => "not_found",
+            Error::TooLarge { .. } => "too_large",
+            Error::Connection { .. } => "connection",
+            Error::Transfer { .. } => "transfer",
+            Error::Protocol { .. } => "protocol",
+            Error::Cancelled { .. } => "cancelled",
+            Error::Io { .. } => "io",
+            Error::Internal { .. } => "internal",
+        }
+    }
+
+    pub fn category(&self) -> Category {
+        match self {
+            Error::FileExists { .. }
+            | Error::DuplicateName { .. }
+            | Error::InvalidName { .. }
+            | Error::InvalidTicket { .. }
+            | Error::InvalidInput { .. }
+            | Error::NotFound { .. }
+            | Error::TooLarge { .. } => Category::Validation,
+            Error::Connection { .. } | Error::Transfer { .. } => Category::Network,
+            Error::Protocol { .. } => Category::Protocol,
+            Error::Cancelled { .. } => Category::Cancelled,
+            Error::Io { .. } => Category::Io,
+            Error::Internal { .. } => Category::Internal,
+        }
+    }
+
+    /// Whether trying the same thing again may work.
+    pub fn retryable(&self) -> bool {
+        self.category() == Category::Network
+    }
+
+    fn context(&self) -> BTreeMap<&'static str, String> {
+        let mut context = BTreeMap::new();
+        match self {
+            Error::FileExists { name }
+            | Error::DuplicateName { name }
+            | Error::InvalidName { name }
+            | Error::Transfer { name, .. }
+            | Error::Cancelled { name } => {
+                context.insert("name", name.clone());
+            }
+            Error::NotFound { what, id } => {
+                context.insert("what", what.to_string());
+                context.insert("id", id.clone());
+            }
+            Error::InvalidInput { what, .. } => {
+                context.insert("what", what.to_string());
+            }
+            Error::TooLarge { name, size, limit } => {
+                context.insert("name", name.clone());
+                context.insert("size", size.to_string());
+                context.insert("limit", limit.to_string());
+            }
+            Error::InvalidTicket { .. }
+            | Error::Connection { .. }
+            | Error::Protocol { .. }
+            | Error::Io { .. }
+            | Error::Internal { .. } => {}
+        }
+        context
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::FileExists { .. } => write!(f, "File already exists"),
+            Error::DuplicateName { .. } => write!(f, "Duplicate file names not allowed."),
+            Error::InvalidName { name } => write!(f, "Invalid file name: {}", name),
+            Error::InvalidTicket { reason } => write!(f, "Invalid ticket: {}", reason),
+            Error::InvalidInput { what, reason } => write!(f, "Invalid {}: {}", what, reason),
+            Error::NotFound { what, id } => write!(f, "Unknown {}: {}", what, id),
+            Error::TooLarge { name, size, limit } => {
+                write!(
+                    f,
+                    "{} is too large ({} of at most {} bytes)",
+                    name, size, limit
+                )
+            }
+            Error::Connection { message }
+            | Error::Transfer { message, .. }
+            | Error::Protocol { message }
+            | Error::Io { message }
+            | Error::Internal { message } => write!(f, "{}", message),
+            Error::Cancelled { .. } => write!(f, "Cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(5))?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("category", &self.category())?;
+        map.serialize_entry("retryable", &self.retryable())?;
+        map.serialize_entry("message", &self.to_string())?;
+        map.serialize_entry("context", &self.context())?;
+        map.end()
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Internal { message }
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Internal {
+            message: message.to_string(),
+        }
+    }
+}
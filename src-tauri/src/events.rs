@@ -1,28 +1,57 @@
+use iroh_blobs::Hash;
 use serde::Serialize;
 
+use crate::error::Error;
+use crate::file_operations::watch::StaleReason;
+use crate::files::ContentKind;
+use crate::offer::rules::Action;
+use crate::progress::Progress;
+
 pub const APP_LOADED: &str = "APP_LOADED";
 
 // DOWNLOAD
 pub const DOWNLOAD_FILE_ADDED: &str = "DOWNLOAD_FILE_ADDED";
+pub const DOWNLOAD_FILE_ICON: &str = "DOWNLOAD_FILE_ICON";
+pub const DOWNLOAD_FILE_THUMBNAIL: &str = "DOWNLOAD_FILE_THUMBNAIL";
 pub const DOWNLOAD_FILE_PROGRESS: &str = "DOWNLOAD_FILE_PROGRESS";
+/// Totals of every file being downloaded, a [`crate::progress::TransferProgress`].
+pub const DOWNLOAD_PROGRESS: &str = "DOWNLOAD_PROGRESS";
 pub const DOWNLOAD_FILE_COMPLETED: &str = "DOWNLOAD_FILE_COMPLETED";
 pub const DOWNLOAD_ALL_COMPLETE: &str = "DOWNLOAD_ALL_COMPLETE";
 pub const DOWNLOAD_FILE_ERROR: &str = "DOWNLOAD_FILE_ERROR";
+pub const DOWNLOAD_FILE_RETRYING: &str = "DOWNLOAD_FILE_RETRYING";
 pub const DOWNLOAD_FILE_ABORTED: &str = "DOWNLOAD_FILE_ABORTED";
 pub const CANCEL_DOWNLOAD: &str = "CANCEL_DOWNLOAD";
+pub const DOWNLOAD_SNIPPET: &str = "DOWNLOAD_SNIPPET";
 
 #[derive(Debug, Clone, Serialize)]
 pub struct DownloadFileAdded {
     pub name: String,
     pub icon: String,
     pub size: u64,
+    pub kind: ContentKind,
+    pub hash: Hash,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadFileIcon {
+    pub name: String,
+    pub icon: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadFileThumbnail {
+    pub name: String,
+    pub thumbnail: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct DownloadFileProgress {
     pub name: String,
-    pub progress: f32,
-    pub speed: f32,
+    /// Bytes sent over the wire, fewer than `size` for compressed files.
+    pub progress: Progress,
+    /// Size of the file once exported.
+    pub size: u64,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -34,13 +63,33 @@ pub struct DownloadFileCompleted {
 #[derive(Debug, Clone, Serialize)]
 pub struct DownloadFileAborted {
     pub name: String,
-    pub reason: String,
+    pub error: Error,
+}
+
+/// A download broke off and is tried again after `delay_ms`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadFileRetrying {
+    pub name: String,
+    /// Starts at 1.
+    pub retry: u32,
+    pub max_retries: u32,
+    pub delay_ms: u64,
+    pub error: Error,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct DownloadFileError {
     pub name: String,
-    pub error: String,
+    pub error: Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadSnippet {
+    pub name: String,
+    pub kind: ContentKind,
+    pub text: String,
+    /// The text was also copied to the clipboard.
+    pub copied: bool,
 }
 
 // UPLOAD
@@ -48,8 +97,9 @@ pub const UPLOAD_FILE_ADDED: &str = "UPLOAD_FILE_ADDED";
 pub const UPLOAD_FILE_PROGRESS: &str = "UPLOAD_FILE_PROGRESS";
 pub const UPLOAD_FILE_COMPLETED: &str = "UPLOAD_FILE_COMPLETED";
 pub const UPLOAD_FILE_REMOVED: &str = "UPLOAD_FILE_REMOVED";
-#[allow(unused)]
+pub const UPLOAD_FILE_STALE: &str = "UPLOAD_FILE_STALE";
 pub const UPLOAD_FILE_ERROR: &str = "UPLOAD_FILE_ERROR";
+pub const UPLOAD_STATS: &str = "UPLOAD_STATS";
 
 #[derive(Debug, Clone, Serialize)]
 pub struct UploadFileAdded {
@@ -57,12 +107,14 @@ pub struct UploadFileAdded {
     pub icon: String,
     pub path: String,
     pub size: u64,
+    /// Size of the stored blob, smaller than `size` for compressed files.
+    pub stored_size: u64,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct UploadFileProgress {
     pub path: String,
-    pub progress: f32,
+    pub progress: Progress,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -70,6 +122,15 @@ pub struct UploadFileRemoved {
     pub name: String,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadFileStale {
+    pub name: String,
+    pub path: String,
+    pub reason: StaleReason,
+    /// New location of the file when it was renamed, to offer re-importing it.
+    pub renamed_to: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct UploadFileCompleted {
     pub name: String,
@@ -78,7 +139,68 @@ pub struct UploadFileCompleted {
 #[derive(Debug, Clone, Serialize)]
 pub struct UploadFileError {
     pub name: String,
-    pub error: String,
+    pub error: Error,
 }
 
 // REMOVE_FILE
+
+// SHARE
+pub const SHARE_UPDATED: &str = "SHARE_UPDATED";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShareUpdated {
+    pub revision: u64,
+    pub ticket: String,
+}
+
+pub const SHARE_CLOSED: &str = "SHARE_CLOSED";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShareClosed {
+    pub id: String,
+}
+
+// MIRROR
+pub const MIRROR_UPDATED: &str = "MIRROR_UPDATED";
+pub const MIRROR_ERROR: &str = "MIRROR_ERROR";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MirrorUpdated {
+    pub id: String,
+    pub revision: u64,
+    pub downloaded: usize,
+    pub deleted: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MirrorError {
+    pub id: String,
+    pub error: Error,
+}
+
+// OFFER
+pub const OFFER_DECISION: &str = "OFFER_DECISION";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OfferDecision {
+    pub sender: String,
+    pub ticket: String,
+    pub action: Action,
+    pub rule: Option<String>,
+    pub file_count: usize,
+    pub total_size: u64,
+}
+
+// DIAGNOSTICS
+/// A [`crate::diagnostics::Diagnostics`] snapshot, while they're watched.
+pub const DIAGNOSTICS: &str = "DIAGNOSTICS";
+
+// SHARE CODE
+pub const SHARE_CODE_CLAIMED: &str = "SHARE_CODE_CLAIMED";
+pub const SHARE_CODE_EXPIRED: &str = "SHARE_CODE_EXPIRED";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShareCodeStatus {
+    pub code: String,
+    pub reason: Option<String>,
+}
@@ -0,0 +1,330 @@
+//! Optional zstd compression of shared files.
+//!
+//! Whether a file is compressed is decided when it is added, from its
+//! extension or, failing that, from whether its first bytes look like text.
+//! The compressed copy is what gets stored and sent, the manifest records the
+//! [`Compression`] so receivers decompress it on export.
+//!
+//! Only the compressed blob is verified by its hash on the way in, so the
+//! decompressed output is checked against the size and content hash in the
+//! manifest while it is written, and decoding stops as soon as it runs over.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use iroh_blobs::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::files::Compression;
+
+pub const LEVEL: i32 = 3;
+/// Smaller files gain too little to be worth it.
+const MIN_SIZE: u64 = 4 * 1024;
+const SNIFF_LEN: usize = 8 * 1024;
+/// The compressed copy is only kept if it saves at least a tenth of the size.
+const MIN_RATIO: f64 = 0.9;
+
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "log", "csv", "tsv", "json", "jsonl", "xml", "html", "htm", "css", "js", "ts", "tsx",
+    "jsx", "md", "rs", "py", "c", "h", "cpp", "hpp", "go", "java", "kt", "rb", "php", "sh", "sql",
+    "yaml", "yml", "toml", "ini", "svg",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionMode {
+    /// Never compress.
+    Off,
+    /// Compress text.
+    #[default]
+    Auto,
+    /// Compress everything where it saves space.
+    Always,
+}
+
+/// A compressed copy of a file, deleted when dropped.
+#[derive(Debug)]
+pub struct Compressed {
+    pub path: PathBuf,
+    pub compression: Compression,
+}
+
+impl Drop for Compressed {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Compress `path` into a temporary file if `mode` calls for it and it pays
+/// off. Returns `None` when the file should be stored as is.
+pub async fn compress(path: &Path, mode: CompressionMode) -> Result<Option<Compressed>, String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let size = path
+            .metadata()
+            .map_err(|e| format!("Failed to get metadata: {:?}", e))?
+            .len();
+        let wanted = match mode {
+            CompressionMode::Off => false,
+            _ if size < MIN_SIZE => false,
+            CompressionMode::Auto => has_text_extension(&path) || looks_like_text(&path),
+            CompressionMode::Always => true,
+        };
+        if !wanted {
+            return Ok(None);
+        }
+
+        let target =
+            std::env::temp_dir().join(format!("sendit-{:016x}.zst", rand::random::<u64>()));
+        let mut source =
+            Hashing::new(fs::File::open(&path).map_err(|e| format!("Failed to open file: {}", e))?);
+        let output = fs::File::create(&target)
+            .map_err(|e| format!("Failed to create compressed file: {}", e))?;
+        // Removes the copy again on every early return
+        let mut compressed = Compressed {
+            path: target,
+            compression: Compression::Zstd {
+                content: Hash::EMPTY,
+                size: 0,
+            },
+        };
+        zstd::stream::copy_encode(&mut source, output, LEVEL)
+            .map_err(|e| format!("Failed to compress file: {}", e))?;
+
+        let compressed_size = compressed
+            .path
+            .metadata()
+            .map_err(|e| format!("Failed to get metadata: {:?}", e))?
+            .len();
+        if compressed_size as f64 > size as f64 * MIN_RATIO {
+            return Ok(None);
+        }
+
+        compressed.compression = Compression::Zstd {
+            content: source.finish(),
+            size: compressed_size,
+        };
+        Ok(Some(compressed))
+    })
+    .await
+    .map_err(|e| format!("Compression task failed: {}", e))?
+}
+
+/// Decompress the blob exported to `source` into `dest`, which must come out
+/// as `size` bytes. `dest` is removed again if it doesn't.
+pub async fn decompress(
+    source: &Path,
+    dest: &Path,
+    compression: Compression,
+    size: u64,
+) -> Result<(), String> {
+    let (source, dest) = (source.to_path_buf(), dest.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        let input =
+            fs::File::open(&source).map_err(|e| format!("Failed to open download: {}", e))?;
+        let mut output =
+            fs::File::create(&dest).map_err(|e| format!("Failed to create file: {}", e))?;
+        let res = decoder(input, compression, size).and_then(|mut decoder| {
+            io::copy(&mut decoder, &mut output)?;
+            output.sync_all()
+        });
+        if let Err(e) = res {
+            drop(output);
+            fs::remove_file(&dest).ok();
+            return Err(format!("Failed to decompress file: {}", e));
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Decompression task failed: {}", e))?
+}
+
+/// Decompress `input`, failing once the output isn't the `size` bytes the
+/// manifest promises.
+pub fn decoder<R: Read>(
+    input: R,
+    compression: Compression,
+    size: u64,
+) -> io::Result<Verified<impl Read>> {
+    match compression {
+        Compression::Zstd { content, .. } => Ok(Verified::new(
+            zstd::Decoder::new(input)?,
+            size,
+            Some(content),
+        )),
+    }
+}
+
+/// Checks the length and, if given, the hash of everything read through it.
+/// Reads fail instead of returning more than `size` bytes, and the last read
+/// fails if the content is short or doesn't match.
+pub struct Verified<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+    left: u64,
+    content: Option<Hash>,
+}
+
+impl<R: Read> Verified<R> {
+    pub fn new(inner: R, size: u64, content: Option<Hash>) -> Self {
+        Self {
+            inner,
+            hasher: blake3::Hasher::new(),
+            left: size,
+            content,
+        }
+    }
+}
+
+impl<R: Read> Read for Verified<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // One byte more than is left shows whether the content runs over
+        let len = buf.len().min(self.left.saturating_add(1) as usize);
+        let n = self.inner.read(&mut buf[..len])?;
+        if n as u64 > self.left {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Content is larger than announced",
+            ));
+        }
+        self.left -= n as u64;
+        self.hasher.update(&buf[..n]);
+
+        if n == 0 {
+            if self.left > 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Content is smaller than announced",
+                ));
+            }
+            let hash = Hash::from_bytes(*self.hasher.finalize().as_bytes());
+            if self.content.is_some_and(|content| content != hash) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Content doesn't match its hash",
+                ));
+            }
+        }
+        Ok(n)
+    }
+}
+
+fn has_text_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| TEXT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// UTF-8 without NUL bytes in the first few KiB.
+fn looks_like_text(path: &Path) -> bool {
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    if file.take(SNIFF_LEN as u64).read_to_end(&mut head).is_err() {
+        return false;
+    }
+
+    // The sniffed bytes may end in the middle of a character
+    let valid = match std::str::from_utf8(&head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    valid && !head.contains(&0)
+}
+
+/// Hashes everything read through it, so the file is only read once.
+struct Hashing<R> {
+    inner: R,
+    hasher: blake3::Hasher,
+}
+
+impl<R: Read> Hashing<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: blake3::Hasher::new(),
+        }
+    }
+
+    fn finish(&self) -> Hash {
+        Hash::from_bytes(*self.hasher.finalize().as_bytes())
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(compressed: &[u8], content: &[u8], size: u64) -> io::Result<Vec<u8>> {
+        let compression = Compression::Zstd {
+            content: Hash::new(content),
+            size: compressed.len() as u64,
+        };
+        let mut out = Vec::new();
+        decoder(compressed, compression, size)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn decodes_what_was_announced() {
+        let content = b"hello hello hello hello".repeat(100);
+        let compressed = zstd::encode_all(content.as_slice(), LEVEL).unwrap();
+
+        let out = decode(&compressed, &content, content.len() as u64).unwrap();
+        assert_eq!(out, content);
+    }
+
+    #[test]
+    fn stops_past_the_announced_size() {
+        let content = vec![0; 1024 * 1024];
+        let compressed = zstd::encode_all(content.as_slice(), LEVEL).unwrap();
+
+        let err = decode(&compressed, &content, 1024).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn fails_when_short() {
+        let content = b"short".to_vec();
+        let compressed = zstd::encode_all(content.as_slice(), LEVEL).unwrap();
+
+        let err = decode(&compressed, &content, 6).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn fails_on_other_content() {
+        let compressed = zstd::encode_all(&b"other"[..], LEVEL).unwrap();
+
+        let err = decode(&compressed, b"right", 5).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn checks_the_length_without_a_hash() {
+        let mut out = Vec::new();
+        let res = Verified::new(&b"abc"[..], 2, None).read_to_end(&mut out);
+        assert!(res.is_err());
+
+        let mut out = Vec::new();
+        Verified::new(&b"abc"[..], 3, None)
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, b"abc");
+    }
+}
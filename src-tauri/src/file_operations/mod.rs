@@ -1,17 +1,22 @@
-use crate::files::{self};
+use crate::error::Error;
+use crate::files::{self, Compression};
+use crate::progress::Meter;
 use crate::state::AppState;
-use crate::{events, state::State, utils};
+use crate::{events, share, state::State, storage, utils};
 use iroh_blobs::rpc::client::blobs::WrapOption;
-use iroh_blobs::{provider::AddProgress, util::SetTagOption};
+use iroh_blobs::{provider::AddProgress, util::SetTagOption, Hash, Tag};
 use n0_future::stream::StreamExt;
 use serde::Serialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 use tauri::{AppHandle, Emitter, Manager};
 use tokio::time::sleep;
 use tracing::{debug, error, info, warn};
 
+pub mod compress;
+pub mod watch;
+
 #[derive(Debug, Serialize)]
 pub struct ValidatedFile {
     name: String,
@@ -21,7 +26,7 @@ pub struct ValidatedFile {
 }
 
 #[tauri::command]
-pub async fn validate_files(paths: Vec<String>) -> Result<Vec<ValidatedFile>, String> {
+pub async fn validate_files(paths: Vec<String>) -> Result<Vec<ValidatedFile>, Error> {
     let mut files = Vec::new();
 
     let tasks = paths
@@ -70,7 +75,7 @@ fn validate_file(path: &str) -> Result<ValidatedFile, String> {
 }
 
 #[tauri::command]
-pub async fn add_file(state: State<'_>, path: String, handle: AppHandle) -> Result<(), String> {
+pub async fn add_file(state: State<'_>, path: String, handle: AppHandle) -> Result<(), Error> {
     info!("Adding file: {}", path);
 
     let original_path = path.clone();
@@ -79,117 +84,279 @@ pub async fn add_file(state: State<'_>, path: String, handle: AppHandle) -> Resu
     {
         let files = state.files().await;
         if files.has_file(&file_name) {
-            let err = format!("Duplicate file names not allowed.",);
+            let err = Error::DuplicateName { name: file_name };
             error!("{}", err);
             return Err(err);
         }
     }
 
-    let icon = utils::get_file_icon(original_path.clone());
-    let icon = match icon {
-        Ok(i) => i,
+    let stamp = path
+        .metadata()
+        .map(|metadata| watch::Stamp::of(&metadata))
+        .map_err(|e| format!("Failed to get metadata: {:?}", e))?;
+
+    let file = import_file(&handle, &original_path, &path, &file_name, true).await?;
+
+    // The blob references the source file, so it must not change while it's shared
+    let watched = state
+        .sources
+        .lock()
+        .await
+        .watch(path, file_name.clone(), stamp)
+        .await;
+    if let Err(error) = watched {
+        storage::release_share_file(&state.iroh().blobs, &file_name).await?;
+
+        let error = Error::from(error);
+        let payload = events::UploadFileError {
+            name: file_name,
+            error: error.clone(),
+        };
+        handle.emit(events::UPLOAD_FILE_ERROR, payload).ok();
+        return Err(error);
+    }
+
+    state.files().await.add_file(file);
+    share::republish_named(&handle).await;
+    share::save(&handle);
+
+    Ok(())
+}
+
+/// Import `path` into the blob store as the shared file `name`, tagging the
+/// file and its icon and reporting progress to the send page.
+///
+/// With `in_place` the store only references `path`, which then must not change
+/// while the file is shared. Otherwise the contents are copied. Compressed
+/// files are always copied.
+pub async fn import_file(
+    handle: &AppHandle,
+    original_path: &str,
+    path: &Path,
+    name: &str,
+    in_place: bool,
+) -> Result<files::File, String> {
+    let state = handle.state::<AppState>();
+    let (icon, icon_hash) = store_icon(&state, original_path, storage::share_icon_tag(name)).await;
+    let thumbnail_hash = store_thumbnail(&state, path, storage::share_thumbnail_tag(name)).await;
+
+    let file_size = path
+        .metadata()
+        .map_err(|e| format!("Failed to get metadata: {:?}", e))?
+        .len();
+    let mut found_size: u64 = 0;
+    let mut meter = Meter::default();
+    let mut throttle = utils::Throttle::new(Duration::from_millis(32));
+
+    let tag = storage::share_file_tag(name);
+    let res = store_file_compressed(&state, path, tag, in_place, |progress| match progress {
+        AddProgress::Found { size, .. } => {
+            info!("Found file: {}", name);
+            found_size = *size;
+
+            let payload = events::UploadFileAdded {
+                name: name.to_string(),
+                icon: icon.clone(),
+                path: original_path.to_string(),
+                size: file_size,
+                stored_size: *size,
+            };
+            handle.emit(events::UPLOAD_FILE_ADDED, payload).ok();
+        }
+        AddProgress::Progress { offset, .. } => {
+            if throttle.is_free() {
+                let progress = meter.update(*offset, found_size);
+                debug!("Progress: {}/{}", progress.done, progress.total);
+                let payload = events::UploadFileProgress {
+                    path: name.to_string(),
+                    progress,
+                };
+                handle.emit(events::UPLOAD_FILE_PROGRESS, payload).ok();
+            }
+        }
+        AddProgress::Done { .. } => {
+            info!("File uploaded: {}", original_path);
+            let payload = events::UploadFileCompleted {
+                name: name.to_string(),
+            };
+            handle.emit(events::UPLOAD_FILE_COMPLETED, payload).ok();
+        }
+        AddProgress::Abort { .. } => {
+            info!("Upload aborted: {}", original_path);
+        }
+        AddProgress::AllDone { .. } => {}
+    })
+    .await;
+    let (hash, size, compression) = res?;
+
+    Ok(files::File {
+        name: name.to_string(),
+        icon: String::new(),
+        icon_hash,
+        size,
+        hash,
+        kind: files::ContentKind::File,
+        thumbnail_hash,
+        compression,
+    })
+}
+
+/// Store the icon of `original_path` as its own blob under `tag`, so identical
+/// icons are only sent once. Returns its data URL and hash.
+pub async fn store_icon(state: &AppState, original_path: &str, tag: Tag) -> (String, Option<Hash>) {
+    let png = match utils::get_file_icon_png(original_path) {
+        Ok(png) => png,
         Err(e) => {
             warn!("{}. Using default value.", e);
-            String::new()
+            return (String::new(), None);
+        }
+    };
+
+    let icon = utils::png_to_data_url(&png);
+    match state.iroh().blobs.add_bytes_named(png, tag).await {
+        Ok(res) => {
+            state.blob_access.lock().await.touch([res.hash]);
+            (icon, Some(res.hash))
+        }
+        Err(e) => {
+            warn!("Failed to store icon: {}", e);
+            (icon, None)
+        }
+    }
+}
+
+/// Store a thumbnail of `path` under `tag` if it is an image. Returns its hash.
+pub async fn store_thumbnail(state: &AppState, path: &Path, tag: Tag) -> Option<Hash> {
+    let source = path.to_path_buf();
+    let jpeg = match tokio::task::spawn_blocking(move || utils::get_thumbnail_jpeg(source)).await {
+        Ok(Ok(jpeg)) => jpeg,
+        Ok(Err(e)) => {
+            debug!("No thumbnail for {}: {}", path.display(), e);
+            return None;
+        }
+        Err(e) => {
+            warn!("Thumbnail task failed: {}", e);
+            return None;
+        }
+    };
+
+    match state.iroh().blobs.add_bytes_named(jpeg, tag).await {
+        Ok(res) => {
+            state.blob_access.lock().await.touch([res.hash]);
+            Some(res.hash)
+        }
+        Err(e) => {
+            warn!("Failed to store thumbnail: {}", e);
+            None
+        }
+    }
+}
+
+/// Like [`store_file`], but compresses `path` first if the compression setting
+/// calls for it. Returns the hash of the blob, the size of the file and the
+/// compression applied to the blob.
+pub async fn store_file_compressed(
+    state: &AppState,
+    path: &Path,
+    tag: Tag,
+    in_place: bool,
+    on_progress: impl FnMut(&AddProgress),
+) -> Result<(Hash, u64, Option<Compression>), String> {
+    let mode = state.settings.lock().await.compression;
+    let compressed = match compress::compress(path, mode).await {
+        Ok(compressed) => compressed,
+        Err(e) => {
+            warn!("{}. Storing {} uncompressed", e, path.display());
+            None
         }
     };
 
+    let Some(compressed) = compressed else {
+        let (hash, size) = store_file(state, path, tag, in_place, on_progress).await?;
+        return Ok((hash, size, None));
+    };
+
+    let size = path
+        .metadata()
+        .map_err(|e| format!("Failed to get metadata: {:?}", e))?
+        .len();
+    // The compressed copy is removed once stored, so the store needs its own
+    let (hash, stored_size) = store_file(state, &compressed.path, tag, false, on_progress).await?;
+    info!(
+        "Stored {} compressed, {} of {} bytes",
+        path.display(),
+        stored_size,
+        size
+    );
+
+    Ok((hash, size, Some(compressed.compression)))
+}
+
+/// Import `path` into the blob store under `tag`, passing every progress event
+/// to `on_progress`. Returns the hash and size of the blob.
+pub async fn store_file(
+    state: &AppState,
+    path: &Path,
+    tag: Tag,
+    in_place: bool,
+    mut on_progress: impl FnMut(&AddProgress),
+) -> Result<(Hash, u64), String> {
     let mut r = state
         .iroh()
         .blobs
-        .add_from_path(path.clone(), true, SetTagOption::Auto, WrapOption::NoWrap)
+        .add_from_path(
+            path.to_path_buf(),
+            in_place,
+            SetTagOption::Named(tag),
+            WrapOption::NoWrap,
+        )
         .await
         .map_err(|e| format!("Failed to add file: {:?}", e))?;
 
     let mut size: u64 = 0;
-    let mut throttle = utils::Throttle::new(Duration::from_millis(32));
-
-    let mut hash = iroh_blobs::Hash::EMPTY;
+    let mut hash = Hash::EMPTY;
     while let Some(progress) = r.next().await {
         match progress {
-            Ok(p) => match p {
-                AddProgress::Found {
-                    size: file_size, ..
-                } => {
-                    info!("Found file: {}", file_name);
-                    size = file_size;
-
-                    let payload = events::UploadFileAdded {
-                        name: file_name.clone(),
-                        icon: icon.clone(),
-                        path: original_path.to_string(),
-                        size,
-                    };
-                    handle.emit(events::UPLOAD_FILE_ADDED, payload).ok();
-                }
-                AddProgress::Progress { offset, .. } => {
-                    if throttle.is_free() {
-                        let progress_percent = (offset as f32 / size as f32) * 100.0;
-                        debug!("Progress: {}", progress_percent);
-                        let payload = events::UploadFileProgress {
-                            path: file_name.clone(),
-                            progress: progress_percent,
-                        };
-                        handle.emit(events::UPLOAD_FILE_PROGRESS, payload).ok();
-                    }
+            Ok(p) => {
+                on_progress(&p);
+                match p {
+                    AddProgress::Found {
+                        size: file_size, ..
+                    } => size = file_size,
+                    AddProgress::Done { hash: _hash, .. } => hash = _hash,
+                    AddProgress::AllDone { .. } => break,
+                    _ => {}
                 }
-                AddProgress::Done { hash: _hash, .. } => {
-                    hash = _hash;
-                    info!("File uploaded: {}", original_path);
-                    let payload = events::UploadFileCompleted {
-                        name: file_name.clone(),
-                    };
-                    handle.emit(events::UPLOAD_FILE_COMPLETED, payload).ok();
-                }
-                AddProgress::Abort { .. } => {
-                    info!("Upload aborted: {}", original_path);
-                }
-                AddProgress::AllDone { .. } => {
-                    break;
-                }
-            },
+            }
             Err(e) => {
                 error!("Failed to add file: {:?}", e);
             }
         }
     }
 
-    let file = files::File {
-        name: file_name,
-        icon,
-        size,
-        hash,
-    };
-    let mut files = state.files().await;
-    files.add_file(file);
-
-    Ok(())
+    state.blob_access.lock().await.touch([hash]);
+    Ok((hash, size))
 }
 
-
 #[tauri::command]
-pub async fn remove_file(path: String, handle: AppHandle) -> Result<(), String> {
+pub async fn remove_file(path: String, handle: AppHandle) -> Result<(), Error> {
     let state = handle.state::<AppState>();
 
     let path = PathBuf::from(path);
     let name = utils::file_name_from_path(&path)?;
     info!("Removing file : {}", name);
 
-    let hash = {
-        let files = state.files().await;
-        files
-            .get(&name)
-            .ok_or_else(|| format!("File not found: {}", name))?
-            .hash
-    };
+    if !state.files().await.has_file(&name) {
+        return Err(Error::NotFound {
+            what: "file",
+            id: name,
+        });
+    }
 
-    state
-        .iroh()
-        .blobs
-        .delete_blob(hash)
-        .await
-        .map_err(|e| format!("Failed to delete blob: {}", e))?;
+    // GC reclaims the blob once nothing else tags it
+    storage::release_share_file(&state.iroh().blobs, &name).await?;
 
+    state.sources.lock().await.unwatch(&name).await;
     {
         let mut files = state.files().await;
         files.remove_file(&name);
@@ -204,12 +371,16 @@ pub async fn remove_file(path: String, handle: AppHandle) -> Result<(), String>
         )
         .ok();
 
+    share::republish_named(&handle).await;
+    share::save(&handle);
     Ok(())
 }
 
 #[tauri::command]
-pub async fn remove_all_files(state: State<'_>, handle: AppHandle) -> Result<(), String> {
+pub async fn remove_all_files(state: State<'_>, handle: AppHandle) -> Result<(), Error> {
     info!("Removing all files");
+    // Stop the live folder first, it locks the files while it syncs
+    state.live_folder.lock().await.take();
     let mut files = state.files().await;
     let handle = Arc::new(handle);
 
@@ -218,15 +389,9 @@ pub async fn remove_all_files(state: State<'_>, handle: AppHandle) -> Result<(),
         .map(|(_, file)| {
             let handle = Arc::clone(&handle);
             let file = file.clone();
-            let hash = file.hash;
             tokio::spawn(async move {
                 let state = handle.state::<AppState>();
-                state
-                    .iroh()
-                    .blobs
-                    .delete_blob(hash)
-                    .await
-                    .map_err(|e| format!("Failed to delete blob: {}", e))?;
+                storage::release_share_file(&state.iroh().blobs, &file.name).await?;
 
                 handle
                     .emit(
@@ -237,7 +402,7 @@ pub async fn remove_all_files(state: State<'_>, handle: AppHandle) -> Result<(),
                     )
                     .ok();
 
-                info!("File {} removed successfully", hash);
+                info!("File {} removed successfully", file.name);
                 sleep(Duration::from_secs(2)).await;
 
                 Ok::<(), String>(())
@@ -245,25 +410,93 @@ pub async fn remove_all_files(state: State<'_>, handle: AppHandle) -> Result<(),
         })
         .collect::<Vec<_>>();
 
+    // Keep going on errors, stopping halfway would leave the remaining tags behind
     for task in tasks {
-        let result = task.await.map_err(|e| format!("Task error: {}", e))?;
-        if let Err(e) = result {
-            error!("Error removing file: {}", e);
+        match task.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Error removing file: {}", e),
+            Err(e) => error!("Task error: {}", e),
+        }
+    }
+
+    // Release all generated header collections
+    let mut tickets = state.header_tickets.lock().await;
+    for ticket in tickets.iter() {
+        match storage::delete_header_tags(&state.iroh().blobs, ticket.hash()).await {
+            Ok(()) => info!("Ticket {} removed successfully", ticket),
+            Err(e) => error!("Error removing ticket {}: {}", ticket, e),
         }
     }
+    tickets.clear();
+    drop(tickets);
 
-    // Remove all generated header files
-    for ticket in state.header_tickets.lock().await.iter() {
-        state
-            .iroh()
-            .blobs
-            .delete_blob(ticket.hash())
-            .await
-            .map_err(|e| format!("Failed to delete blob: {}", e))?;
-        info!("Ticket {} removed successfully", ticket);
+    // Catches whatever the steps above missed
+    if let Err(e) =
+        storage::delete_tags_with_prefix(&state.iroh().blobs, storage::SHARE_TAG_PREFIX).await
+    {
+        error!("Error removing share tags: {}", e);
     }
+    state.sources.lock().await.unwatch_all().await;
 
     files.clear();
+    drop(files);
     info!("All files removed successfully");
+
+    share::republish_named(&handle).await;
+    share::save(&handle);
     Ok(())
 }
+
+/// The files currently shared, such as the ones restored on startup.
+#[tauri::command]
+pub async fn list_shared_files(state: State<'_>) -> Result<Vec<ValidatedFile>, Error> {
+    let sources = state.sources.lock().await.sources().await;
+    let files = state.files().await.values().cloned().collect::<Vec<_>>();
+
+    let mut shared = Vec::with_capacity(files.len());
+    for file in files {
+        let icon = match file.icon_hash {
+            Some(hash) => match state.iroh().blobs.read_to_bytes(hash).await {
+                Ok(png) => utils::png_to_data_url(&png),
+                Err(e) => {
+                    warn!("Failed to read icon: {}. Using default value", e);
+                    String::new()
+                }
+            },
+            None => String::new(),
+        };
+        // Live folder files have no source of their own, snippets go by their name
+        let path = match file.kind.is_file() {
+            true => sources
+                .iter()
+                .find(|source| source.name == file.name)
+                .map(|source| source.path.display().to_string())
+                .unwrap_or_default(),
+            false => file.name.clone(),
+        };
+
+        shared.push(ValidatedFile {
+            name: file.name,
+            icon,
+            size: file.size,
+            path,
+        });
+    }
+
+    Ok(shared)
+}
+
+/// Replace a stale file with the current contents of its source, or of
+/// `new_path` when the source was renamed.
+#[tauri::command]
+pub async fn reimport_file(
+    path: String,
+    new_path: Option<String>,
+    handle: AppHandle,
+) -> Result<(), Error> {
+    info!("Re-importing file: {}", path);
+    let new_path = new_path.unwrap_or_else(|| path.clone());
+
+    remove_file(path, handle.clone()).await?;
+    add_file(handle.state::<AppState>(), new_path, handle.clone()).await
+}
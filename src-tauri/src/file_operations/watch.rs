@@ -0,0 +1,269 @@
+//! Watches the source files of shared files.
+//!
+//! Files are imported in place, so the blob store only references the original
+//! file on disk. Once it changes the blob no longer matches its hash, so its
+//! file tag is dropped and the file is marked stale until it is re-imported or
+//! removed from the share. Tickets already handed out keep the blob alive
+//! through their own tags.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::Metadata,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use log::{error, warn};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::{events, state::AppState, storage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StaleReason {
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+/// Size and modification time of a file, enough to tell it changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    pub fn of(metadata: &Metadata) -> Self {
+        Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Source {
+    name: String,
+    stamp: Stamp,
+    stale: Option<StaleReason>,
+}
+
+impl Source {
+    /// Why the file at `path` no longer matches the imported blob, if it doesn't.
+    fn check(&self, path: &Path) -> Option<StaleReason> {
+        match path.metadata() {
+            Err(_) => Some(StaleReason::Deleted),
+            Ok(meta) if Stamp::of(&meta) != self.stamp => Some(StaleReason::Modified),
+            Ok(_) => None,
+        }
+    }
+}
+
+/// A watched source that still matches its blob.
+#[derive(Debug, Clone)]
+pub struct WatchedSource {
+    pub name: String,
+    pub path: PathBuf,
+    pub stamp: Stamp,
+}
+
+type Sources = Arc<Mutex<HashMap<PathBuf, Source>>>;
+
+pub struct SourceWatcher {
+    watcher: RecommendedWatcher,
+    sources: Sources,
+    /// Watched parent directories and how many sources live in each.
+    dirs: HashMap<PathBuf, usize>,
+}
+
+impl fmt::Debug for SourceWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceWatcher")
+            .field("dirs", &self.dirs)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SourceWatcher {
+    pub fn new(handle: AppHandle) -> notify::Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+            Ok(event) => {
+                tx.send(event).ok();
+            }
+            Err(e) => error!("File watcher error: {}", e),
+        })?;
+
+        let sources = Sources::default();
+        tokio::spawn(handle_events(handle, Arc::clone(&sources), rx));
+
+        Ok(Self {
+            watcher,
+            sources,
+            dirs: HashMap::new(),
+        })
+    }
+
+    /// Start watching `path`, imported as `name`.
+    ///
+    /// `stamp` must be taken before the import started. If the file changed
+    /// since, the import may have hashed a mix of both versions and is rejected.
+    pub async fn watch(&mut self, path: PathBuf, name: String, stamp: Stamp) -> Result<(), String> {
+        let source = Source {
+            name,
+            stamp,
+            stale: None,
+        };
+        if source.check(&path).is_some() {
+            return Err(format!("{} changed while it was being added", source.name));
+        }
+
+        // Watch the directory rather than the file so renames report the new name
+        let dir = path
+            .parent()
+            .ok_or_else(|| format!("Failed to watch {}", path.display()))?
+            .to_path_buf();
+        let count = self.dirs.entry(dir.clone()).or_default();
+        if *count == 0 {
+            self.watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .map_err(|e| format!("Failed to watch {}: {}", dir.display(), e))?;
+        }
+        *count += 1;
+
+        self.sources.lock().await.insert(path, source);
+        Ok(())
+    }
+
+    /// Stop watching the source of the shared file `name`.
+    pub async fn unwatch(&mut self, name: &str) {
+        let path = {
+            let mut sources = self.sources.lock().await;
+            let Some(path) = sources
+                .iter()
+                .find(|(_, source)| source.name == name)
+                .map(|(path, _)| path.clone())
+            else {
+                return;
+            };
+            sources.remove(&path);
+            path
+        };
+
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        if let Some(count) = self.dirs.get_mut(dir) {
+            *count -= 1;
+            if *count == 0 {
+                self.dirs.remove(dir);
+                if let Err(e) = self.watcher.unwatch(dir) {
+                    warn!("Failed to unwatch {}: {}", dir.display(), e);
+                }
+            }
+        }
+    }
+
+    pub async fn unwatch_all(&mut self) {
+        self.sources.lock().await.clear();
+        for dir in self.dirs.drain().map(|(dir, _)| dir) {
+            if let Err(e) = self.watcher.unwatch(&dir) {
+                warn!("Failed to unwatch {}: {}", dir.display(), e);
+            }
+        }
+    }
+
+    /// Names of the shared files whose source changed since they were added.
+    pub async fn stale_files(&self) -> Vec<String> {
+        self.sources
+            .lock()
+            .await
+            .values()
+            .filter(|source| source.stale.is_some())
+            .map(|source| source.name.clone())
+            .collect()
+    }
+
+    pub async fn sources(&self) -> Vec<WatchedSource> {
+        self.sources
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, source)| source.stale.is_none())
+            .map(|(path, source)| WatchedSource {
+                name: source.name.clone(),
+                path: path.clone(),
+                stamp: source.stamp,
+            })
+            .collect()
+    }
+}
+
+async fn handle_events(
+    handle: AppHandle,
+    sources: Sources,
+    mut rx: mpsc::UnboundedReceiver<Event>,
+) {
+    while let Some(event) = rx.recv().await {
+        // Renames within the directory report the old and the new path
+        let renamed_to = match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => event.paths.get(1).cloned(),
+            _ => None,
+        };
+
+        let stale = {
+            let mut sources = sources.lock().await;
+            event
+                .paths
+                .iter()
+                .filter_map(|path| {
+                    let source = sources.get_mut(path).filter(|s| s.stale.is_none())?;
+                    let reason = match source.check(path)? {
+                        StaleReason::Deleted if renamed_to.is_some() => StaleReason::Renamed,
+                        reason => reason,
+                    };
+                    source.stale = Some(reason);
+                    Some((path.clone(), source.name.clone(), reason))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (path, name, reason) in stale {
+            mark_stale(&handle, path, name, reason, renamed_to.clone()).await;
+        }
+    }
+}
+
+async fn mark_stale(
+    handle: &AppHandle,
+    path: PathBuf,
+    name: String,
+    reason: StaleReason,
+    renamed_to: Option<PathBuf>,
+) {
+    warn!("Shared file {} is stale: {:?}", path.display(), reason);
+
+    // New shares must not offer the old hash, tickets already handed out keep their tags
+    let state = handle.state::<AppState>();
+    let tag = storage::share_file_tag(&name);
+    if let Err(e) = storage::delete_tag(&state.iroh().blobs, tag).await {
+        error!("{}", e);
+    }
+
+    let payload = events::UploadFileStale {
+        name,
+        path: path.display().to_string(),
+        reason,
+        renamed_to: renamed_to.map(|path| path.display().to_string()),
+    };
+    handle.emit(events::UPLOAD_FILE_STALE, payload).ok();
+}
@@ -0,0 +1,100 @@
+//! Shares as iroh-blobs collections.
+//!
+//! A share is published as a [`Collection`] whose first entry is the manifest
+//! blob, followed by every file, icon and thumbnail blob it references. The
+//! ticket points at the collection root with [`BlobFormat::HashSeq`], so the
+//! whole share can be fetched, verified and tagged as one unit. Receivers can
+//! still fetch the manifest first and then pick individual files.
+
+use std::collections::HashSet;
+
+use bytes::Bytes;
+use iroh_blobs::{
+    format::collection::Collection, hashseq::HashSeq, ticket::BlobTicket, BlobFormat, Hash,
+};
+
+use super::{File, Files};
+use crate::{error::Error, iroh::BlobsClient, storage, utils};
+
+/// Name of the manifest entry inside a share collection.
+pub const MANIFEST_NAME: &str = ".sendit/manifest";
+const ICON_PREFIX: &str = ".sendit/icon/";
+const THUMBNAIL_PREFIX: &str = ".sendit/thumbnail/";
+
+/// Build the collection for `files`, whose encoded manifest is stored under `manifest`.
+pub fn build(files: &Files, manifest: Hash) -> Collection {
+    let mut collection = Collection::default();
+    collection.push(MANIFEST_NAME.to_string(), manifest);
+
+    let mut icons = HashSet::new();
+    for file in files.values() {
+        collection.push(file.name.clone(), file.hash);
+        if let Some(icon) = file.icon_hash {
+            if icons.insert(icon) {
+                collection.push(format!("{ICON_PREFIX}{icon}"), icon);
+            }
+        }
+        if let Some(thumbnail) = file.thumbnail_hash {
+            collection.push(format!("{THUMBNAIL_PREFIX}{}", file.name), thumbnail);
+        }
+    }
+
+    collection
+}
+
+/// Fetch and parse the manifest behind `ticket`.
+///
+/// Tickets with [`BlobFormat::Raw`] point straight at a manifest blob, as
+/// generated before shares were collections.
+pub async fn fetch_manifest(blobs: &BlobsClient, ticket: BlobTicket) -> Result<Files, Error> {
+    let invalid = |message: String| Error::Protocol { message };
+
+    if ticket.format() == BlobFormat::Raw {
+        let header = utils::download_and_read_header(blobs, ticket).await?;
+        return Files::from_bytes(&header).map_err(invalid);
+    }
+
+    let (node_addr, root, _) = ticket.into_parts();
+
+    // Only fetch the root and the collection metadata, files are fetched on demand
+    let tag = |hash| storage::download_tag(root, hash);
+    let root_bytes = utils::download_blob_bytes(blobs, root, node_addr.clone(), tag(root)).await?;
+    let hash_seq = HashSeq::try_from(Bytes::from(root_bytes))
+        .map_err(|e| invalid(format!("Invalid share collection: {}", e)))?;
+    let meta = hash_seq
+        .iter()
+        .next()
+        .ok_or_else(|| invalid("Invalid share collection: empty".to_string()))?;
+    utils::download_blob_bytes(blobs, meta, node_addr.clone(), tag(meta)).await?;
+
+    let collection = blobs
+        .get_collection(root)
+        .await
+        .map_err(|e| invalid(format!("Failed to read share collection: {}", e)))?;
+
+    let manifest = collection
+        .iter()
+        .find(|(name, _)| name == MANIFEST_NAME)
+        .map(|(_, hash)| *hash)
+        .ok_or_else(|| invalid("Share collection has no manifest".to_string()))?;
+
+    let header = utils::download_blob_bytes(blobs, manifest, node_addr, tag(manifest)).await?;
+    let files = Files::from_bytes(&header).map_err(invalid)?;
+
+    // The root hash covers the collection, make sure the manifest doesn't point outside of it
+    let members: HashSet<Hash> = collection.iter().map(|(_, hash)| *hash).collect();
+    let outside = |file: &&File| {
+        [Some(file.hash), file.icon_hash, file.thumbnail_hash]
+            .into_iter()
+            .flatten()
+            .any(|hash| !members.contains(&hash))
+    };
+    if let Some(file) = files.values().find(outside) {
+        return Err(invalid(format!(
+            "File {} is not part of the share",
+            file.name
+        )));
+    }
+
+    Ok(files)
+}
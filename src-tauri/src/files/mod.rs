@@ -0,0 +1,554 @@
+//! The share manifest, also called the header.
+//!
+//! The header is stored as a blob and the ticket handed to receivers points at it.
+//!
+//! # Schema
+//!
+//! | field            | meaning                                                      |
+//! |------------------|--------------------------------------------------------------|
+//! | `version`        | oldest manifest version able to read the header               |
+//! | `writer_version` | manifest version of the app that wrote the header             |
+//! | `capabilities`   | optional features the header relies on                        |
+//! | `files`          | file name to [`File`] (`name`, `icon`, `icon_hash`, `size`, `hash`, `kind`, `thumbnail_hash`, `compression`) |
+//! | `gossip_ticket`  | topic the sender announces updates on                         |
+//!
+//! - Headers are written with the lowest version their content needs, see
+//!   [`Files::to_bytes`].
+//! - Readers accept any header with `version <= VERSION`.
+//! - A reader rejects headers that need a capability it doesn't know, with a
+//!   message telling the user to update.
+//! - Every version can be extended without a bump: fields a reader doesn't know
+//!   are skipped, fields a writer didn't know take their default. Fields only
+//!   go in that way if ignoring them is harmless, like icons and thumbnails.
+//!   Anything else is a capability.
+//!
+//! # Capabilities
+//!
+//! - `snippets`: entries with a [`ContentKind`] other than file.
+//! - `zstd`: files whose blob is compressed, see [`Compression`].
+//!
+//! # Versions
+//!
+//! - v1: base32 encoded JSON with `version`, `files` and `gossip_ticket`.
+//!   v1 readers require `version == 1` and ignore unknown fields, see [`v1`].
+//!   Headers without capabilities are still written this way.
+//! - v2: JSON, adds `writer_version` and `capabilities`. Read, never written.
+//! - v3: binary, [`MAGIC`] followed by postcard encoded fields: `version`,
+//!   `writer_version`, `capabilities`, `gossip_ticket` and the files. Each file
+//!   is a length-prefixed record of its fields in the order `name`, `size`,
+//!   `hash`, `icon_hash`, `kind`, `thumbnail_hash`, `compression`. New fields
+//!   are appended to the record, new header fields after the files. Written
+//!   for headers with capabilities, which v1 and v2 readers can't honor anyway.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::{Deref, DerefMut},
+};
+
+use iroh_blobs::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::iroh::GossipTicket;
+
+pub mod collection;
+mod v1;
+
+/// The newest manifest version this build reads and writes.
+pub const VERSION: u32 = 3;
+
+/// The first version with the binary layout, written for headers v1 can't carry.
+const BINARY_VERSION: u32 = 3;
+
+/// Prefix of binary (v3+) headers.
+pub const MAGIC: &[u8; 4] = b"SNDT";
+
+/// Text and links next to files, see [`ContentKind`]. Older readers would
+/// write them to the download folder.
+pub const SNIPPETS: &str = "snippets";
+
+/// Files compressed with zstd, see [`Compression`]. Older readers would export
+/// the compressed bytes.
+pub const ZSTD: &str = "zstd";
+
+/// Capabilities this build understands.
+pub const CAPABILITIES: &[&str] = &[SNIPPETS, ZSTD];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct File {
+    pub name: String,
+    /// Inline icon data URL. Only set in headers written by v1 apps.
+    #[serde(default)]
+    pub icon: String,
+    /// Blob holding the PNG icon.
+    #[serde(default)]
+    pub icon_hash: Option<Hash>,
+    pub size: u64,
+    pub hash: Hash,
+    #[serde(default)]
+    pub kind: ContentKind,
+    /// Blob holding a JPEG preview, for images. Optional data, older readers
+    /// skip it and show the icon, so it needs no capability.
+    #[serde(default)]
+    pub thumbnail_hash: Option<Hash>,
+    /// How the blob is compressed, if it is.
+    #[serde(default)]
+    pub compression: Option<Compression>,
+}
+
+impl File {
+    /// Whether `hash` is the file's contents, icon or thumbnail.
+    pub fn uses(&self, hash: Hash) -> bool {
+        self.hash == hash || self.icon_hash == Some(hash) || self.thumbnail_hash == Some(hash)
+    }
+
+    /// Hash of the file once exported, which differs from `hash` for compressed files.
+    pub fn content_hash(&self) -> Hash {
+        match self.compression {
+            Some(Compression::Zstd { content, .. }) => content,
+            None => self.hash,
+        }
+    }
+
+    /// Size of the blob that is sent, which differs from `size` for compressed
+    /// files. Transfer progress counts these bytes.
+    pub fn blob_size(&self) -> u64 {
+        match self.compression {
+            Some(Compression::Zstd { size, .. }) => size,
+            None => self.size,
+        }
+    }
+}
+
+/// Compression applied to a file's blob. Receivers decompress it on export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// A zstd frame. `content` is the hash of the decompressed file, `size` the
+    /// size of the frame.
+    Zstd { content: Hash, size: u64 },
+}
+
+/// What a shared entry holds. Everything but files is shown to the receiver
+/// instead of being written to the download folder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentKind {
+    #[default]
+    File,
+    Text,
+    Link,
+}
+
+impl ContentKind {
+    pub fn is_file(self) -> bool {
+        self == Self::File
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Files {
+    pub version: u32,
+    #[serde(default = "v1::writer_version")]
+    pub writer_version: u32,
+    #[serde(default)]
+    pub capabilities: BTreeSet<String>,
+    pub files: HashMap<String, File>,
+    gossip_ticket: GossipTicket,
+}
+
+/// The fields every manifest version agrees on, read before picking a parser.
+#[derive(Debug, Deserialize)]
+struct Probe {
+    version: u32,
+    #[serde(default = "v1::writer_version")]
+    writer_version: u32,
+    #[serde(default)]
+    capabilities: Vec<String>,
+}
+
+impl Deref for Files {
+    type Target = HashMap<String, File>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.files
+    }
+}
+
+impl DerefMut for Files {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.files
+    }
+}
+
+impl Files {
+    pub fn new(ticket: GossipTicket) -> Self {
+        Self {
+            version: 1,
+            writer_version: VERSION,
+            capabilities: BTreeSet::new(),
+            gossip_ticket: ticket,
+            files: HashMap::new(),
+        }
+    }
+
+    pub fn add_file(&mut self, file: File) {
+        self.files.insert(file.name.clone(), file);
+    }
+
+    pub fn remove_file(&mut self, name: &str) {
+        self.files.remove(name);
+    }
+
+    pub fn has_file(&self, name: &str) -> bool {
+        self.files.contains_key(name)
+    }
+
+    /// The topic the sender announces new revisions of the share on.
+    pub fn gossip_ticket(&self) -> &GossipTicket {
+        &self.gossip_ticket
+    }
+
+    /// Mark the header as relying on `capability`. Readers that don't know it
+    /// will refuse the header instead of misreading it.
+    pub fn require(&mut self, capability: &str) {
+        debug_assert!(CAPABILITIES.contains(&capability));
+        self.capabilities.insert(capability.to_string());
+    }
+
+    /// Require the capabilities of the features the files use.
+    fn require_features(&mut self) {
+        if self.files.values().any(|file| !file.kind.is_file()) {
+            self.require(SNIPPETS);
+        }
+        if self.files.values().any(|file| file.compression.is_some()) {
+            self.require(ZSTD);
+        }
+    }
+
+    /// Encode the header with the lowest version able to carry it: v1 JSON
+    /// when it needs no capability, so every release reads it, otherwise v3.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header = self.clone();
+        header.require_features();
+
+        if header.capabilities.is_empty() {
+            header.version = 1;
+            header.writer_version = VERSION;
+            let json = serde_json::to_vec(&header).expect("Infallible");
+            return data_encoding::BASE32.encode(&json).into_bytes();
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        let records = header
+            .files
+            .values()
+            .map(|file| postcard::to_stdvec(&Record::from(file)).expect("Infallible"))
+            .collect::<Vec<_>>();
+        let fields = (
+            BINARY_VERSION,
+            VERSION,
+            &header.capabilities,
+            &header.gossip_ticket,
+            records,
+        );
+        bytes.extend(postcard::to_stdvec(&fields).expect("Infallible"));
+        bytes
+    }
+
+    /// Parse a header blob written by any manifest version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if let Some(body) = bytes.strip_prefix(MAGIC.as_slice()) {
+            return Self::from_binary(body);
+        }
+
+        match bytes.first() {
+            Some(b'{') => Self::from_json(bytes),
+            _ => {
+                let json = data_encoding::BASE32
+                    .decode(bytes)
+                    .map_err(|e| format!("Failed to decode base32: {}", e))?;
+                Self::from_json(&json)
+            }
+        }
+    }
+
+    fn from_binary(body: &[u8]) -> Result<Self, String> {
+        let mut fields = Fields(body);
+        let probe = Probe {
+            version: fields.required()?,
+            writer_version: fields.required()?,
+            capabilities: fields.required()?,
+        };
+        probe.check()?;
+
+        let gossip_ticket = fields.required()?;
+        let records: Vec<Vec<u8>> = fields.required()?;
+        let files = records
+            .iter()
+            .map(|record| File::from_record(record).map(|file| (file.name.clone(), file)))
+            .collect::<Result<_, _>>()?;
+
+        // Whatever follows was added by newer writers
+        Ok(Self {
+            version: probe.version,
+            writer_version: probe.writer_version,
+            capabilities: probe.capabilities.into_iter().collect(),
+            files,
+            gossip_ticket,
+        })
+    }
+
+    fn from_json(bytes: &[u8]) -> Result<Self, String> {
+        let probe: Probe =
+            serde_json::from_slice(bytes).map_err(|e| format!("Failed to parse header: {}", e))?;
+        probe.check()?;
+
+        // v1 has its own layout, later ones only add fields with defaults
+        match probe.writer_version {
+            0 => Err("Invalid header version 0".to_string()),
+            1 => v1::Files::from_bytes(bytes).map(Into::into),
+            _ => serde_json::from_slice(bytes).map_err(|e| format!("Failed to parse bytes: {}", e)),
+        }
+    }
+}
+
+/// A [`File`] as laid out in a v3 record.
+#[derive(Serialize)]
+struct Record<'a> {
+    name: &'a str,
+    size: u64,
+    hash: Hash,
+    icon_hash: Option<Hash>,
+    kind: ContentKind,
+    thumbnail_hash: Option<Hash>,
+    compression: Option<Compression>,
+}
+
+impl<'a> From<&'a File> for Record<'a> {
+    fn from(file: &'a File) -> Self {
+        Self {
+            name: &file.name,
+            size: file.size,
+            hash: file.hash,
+            icon_hash: file.icon_hash,
+            kind: file.kind,
+            thumbnail_hash: file.thumbnail_hash,
+            compression: file.compression,
+        }
+    }
+}
+
+impl File {
+    fn from_record(record: &[u8]) -> Result<Self, String> {
+        let mut fields = Fields(record);
+        Ok(Self {
+            name: fields.required()?,
+            size: fields.required()?,
+            hash: fields.required()?,
+            icon: String::new(),
+            icon_hash: fields.optional()?,
+            kind: fields.optional()?,
+            thumbnail_hash: fields.optional()?,
+            compression: fields.optional()?,
+        })
+    }
+}
+
+/// Reads postcard encoded fields one after the other.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn required<T: Deserialize<'a>>(&mut self) -> Result<T, String> {
+        let (value, rest) = postcard::take_from_bytes(self.0)
+            .map_err(|e| format!("Failed to parse header: {}", e))?;
+        self.0 = rest;
+        Ok(value)
+    }
+
+    /// A field older writers didn't know, its default when it's missing.
+    fn optional<T: Deserialize<'a> + Default>(&mut self) -> Result<T, String> {
+        match self.0.is_empty() {
+            true => Ok(T::default()),
+            false => self.required(),
+        }
+    }
+}
+
+impl Probe {
+    fn check(&self) -> Result<(), String> {
+        if self.version > VERSION {
+            return Err(format!(
+                "This share needs a newer version of sendit (header version {}, supported up to {})",
+                self.version, VERSION
+            ));
+        }
+
+        let unsupported: Vec<&str> = self
+            .capabilities
+            .iter()
+            .map(String::as_str)
+            .filter(|c| !CAPABILITIES.contains(c))
+            .collect();
+        if !unsupported.is_empty() {
+            return Err(format!(
+                "This share needs a newer version of sendit (unsupported: {})",
+                unsupported.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+    use iroh_gossip::proto::TopicId;
+
+    use super::*;
+
+    pub fn ticket() -> GossipTicket {
+        let node_id = SecretKey::from_bytes(&[7; 32]).public();
+        GossipTicket::new(TopicId::from_bytes([1; 32]), node_id)
+    }
+
+    pub fn file(name: &str) -> File {
+        File {
+            name: name.to_string(),
+            icon: String::new(),
+            icon_hash: Some(Hash::new(b"icon")),
+            size: 4,
+            hash: Hash::new(name),
+            kind: ContentKind::File,
+            thumbnail_hash: None,
+            compression: None,
+        }
+    }
+
+    fn binary(capabilities: &[&str], records: Vec<Vec<u8>>, trailer: &[u8]) -> Vec<u8> {
+        let fields = (BINARY_VERSION, VERSION, capabilities, ticket(), records);
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(postcard::to_stdvec(&fields).unwrap());
+        bytes.extend(trailer);
+        bytes
+    }
+
+    #[test]
+    fn plain_header_is_written_as_v1() {
+        let mut files = Files::new(ticket());
+        let mut photo = file("photo.jpg");
+        photo.thumbnail_hash = Some(Hash::new(b"thumbnail"));
+        files.add_file(photo.clone());
+        files.add_file(file("notes.txt"));
+
+        let bytes = files.to_bytes();
+        assert!(!bytes.starts_with(MAGIC));
+
+        let read = Files::from_bytes(&bytes).unwrap();
+        assert_eq!(read.version, 1);
+        assert_eq!(read.writer_version, VERSION);
+        assert!(read.capabilities.is_empty());
+        assert_eq!(read.files, files.files);
+        assert_eq!(read["photo.jpg"], photo);
+    }
+
+    #[test]
+    fn header_with_capabilities_is_written_as_v3() {
+        let mut files = Files::new(ticket());
+        let mut snippet = file("snippet.txt");
+        snippet.kind = ContentKind::Link;
+        let mut log = file("server.log");
+        log.compression = Some(Compression::Zstd {
+            content: Hash::new(b"content"),
+            size: 512,
+        });
+        files.add_file(snippet);
+        files.add_file(log);
+        files.add_file(file("plain.bin"));
+
+        let bytes = files.to_bytes();
+        assert!(bytes.starts_with(MAGIC));
+
+        let read = Files::from_bytes(&bytes).unwrap();
+        assert_eq!(read.version, BINARY_VERSION);
+        assert_eq!(read.writer_version, VERSION);
+        assert_eq!(
+            read.capabilities,
+            BTreeSet::from([SNIPPETS.to_string(), ZSTD.to_string()])
+        );
+        assert_eq!(read.files, files.files);
+        assert_eq!(read.gossip_ticket.node_id, ticket().node_id);
+    }
+
+    #[test]
+    fn v2_header_is_read() {
+        let hash = Hash::new(b"a");
+        let json = serde_json::json!({
+            "version": 1,
+            "writer_version": 2,
+            "capabilities": [],
+            "files": {
+                "a.txt": { "name": "a.txt", "icon": "data:image/png;base64,", "size": 1, "hash": hash },
+            },
+            "gossip_ticket": ticket(),
+        });
+
+        let read = Files::from_bytes(&serde_json::to_vec(&json).unwrap()).unwrap();
+        assert_eq!(read.writer_version, 2);
+        assert_eq!(read["a.txt"].icon, "data:image/png;base64,");
+        assert_eq!(read["a.txt"].hash, hash);
+        assert_eq!(read["a.txt"].kind, ContentKind::File);
+        assert_eq!(read["a.txt"].compression, None);
+    }
+
+    #[test]
+    fn missing_record_fields_take_their_default() {
+        let hash = Hash::new(b"old");
+        let record = postcard::to_stdvec(&("old.txt", 3u64, hash)).unwrap();
+
+        let read = Files::from_bytes(&binary(&[], vec![record], &[])).unwrap();
+        let old = &read["old.txt"];
+        assert_eq!((old.size, old.hash), (3, hash));
+        assert_eq!(old.icon_hash, None);
+        assert_eq!(old.kind, ContentKind::File);
+        assert_eq!(old.thumbnail_hash, None);
+        assert_eq!(old.compression, None);
+    }
+
+    #[test]
+    fn unknown_fields_are_skipped() {
+        let mut record = postcard::to_stdvec(&Record::from(&file("new.txt"))).unwrap();
+        record.extend(postcard::to_stdvec(&("a field from the future", 42u64)).unwrap());
+
+        let read = Files::from_bytes(&binary(&[], vec![record], b"trailing header field")).unwrap();
+        assert_eq!(read["new.txt"], file("new.txt"));
+    }
+
+    #[test]
+    fn unknown_capability_is_rejected() {
+        let record = postcard::to_stdvec(&Record::from(&file("a.txt"))).unwrap();
+        let err = Files::from_bytes(&binary(&["teleport"], vec![record], &[])).unwrap_err();
+        assert!(err.contains("teleport"), "{err}");
+
+        let json = serde_json::json!({
+            "version": 1,
+            "writer_version": 2,
+            "capabilities": ["teleport"],
+            "files": {},
+            "gossip_ticket": ticket(),
+        });
+        assert!(Files::from_bytes(&serde_json::to_vec(&json).unwrap()).is_err());
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let json = serde_json::json!({
+            "version": VERSION + 1,
+            "writer_version": VERSION + 1,
+            "files": {},
+            "gossip_ticket": ticket(),
+        });
+        let err = Files::from_bytes(&serde_json::to_vec(&json).unwrap()).unwrap_err();
+        assert!(err.contains("newer version"), "{err}");
+    }
+}
@@ -0,0 +1,116 @@
+//! Manifest version 1, as written by sendit up to 0.4.
+
+use std::collections::{BTreeSet, HashMap};
+
+use iroh_blobs::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::iroh::GossipTicket;
+
+/// Headers without a `writer_version` were written by a v1 app.
+pub fn writer_version() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct File {
+    pub name: String,
+    pub icon: String,
+    pub size: u64,
+    pub hash: Hash,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Files {
+    pub version: u32,
+    pub files: HashMap<String, File>,
+    pub gossip_ticket: GossipTicket,
+}
+
+impl Files {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(bytes).map_err(|e| format!("Failed to parse v1 header: {}", e))
+    }
+}
+
+impl From<File> for super::File {
+    fn from(file: File) -> Self {
+        Self {
+            name: file.name,
+            icon: file.icon,
+            icon_hash: None,
+            size: file.size,
+            hash: file.hash,
+            thumbnail_hash: None,
+            compression: None,
+            kind: super::ContentKind::File,
+        }
+    }
+}
+
+impl From<Files> for super::Files {
+    fn from(files: Files) -> Self {
+        Self {
+            version: files.version,
+            writer_version: writer_version(),
+            capabilities: BTreeSet::new(),
+            files: files
+                .files
+                .into_iter()
+                .map(|(name, file)| (name, file.into()))
+                .collect(),
+            gossip_ticket: files.gossip_ticket,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_blobs::Hash;
+
+    use super::*;
+    use crate::files::tests::{file, ticket};
+
+    /// A header as written by sendit 0.4.
+    fn written_by_v1() -> Vec<u8> {
+        let files = Files {
+            version: 1,
+            files: HashMap::from([(
+                "a.txt".to_string(),
+                File {
+                    name: "a.txt".to_string(),
+                    icon: "data:image/png;base64,".to_string(),
+                    size: 1,
+                    hash: Hash::new(b"a"),
+                },
+            )]),
+            gossip_ticket: ticket(),
+        };
+        let json = serde_json::to_vec(&files).unwrap();
+        data_encoding::BASE32.encode(&json).into_bytes()
+    }
+
+    #[test]
+    fn current_reader_reads_v1() {
+        let read = crate::files::Files::from_bytes(&written_by_v1()).unwrap();
+        assert_eq!(read.version, 1);
+        assert_eq!(read.writer_version, 1);
+        assert!(read.capabilities.is_empty());
+        assert_eq!(read["a.txt"].icon, "data:image/png;base64,");
+        assert_eq!(read["a.txt"].hash, Hash::new(b"a"));
+        assert_eq!(read["a.txt"].icon_hash, None);
+    }
+
+    #[test]
+    fn v1_reader_reads_current_plain_header() {
+        let mut files = crate::files::Files::new(ticket());
+        files.add_file(file("b.txt"));
+
+        // What a v1 app does with the header blob
+        let json = data_encoding::BASE32.decode(&files.to_bytes()).unwrap();
+        let read = Files::from_bytes(&json).unwrap();
+        assert_eq!(read.version, 1);
+        assert_eq!(read.files["b.txt"].hash, file("b.txt").hash);
+        assert_eq!(read.files["b.txt"].size, 4);
+    }
+}
@@ -0,0 +1,348 @@
+//! Live folder shares.
+//!
+//! A shared folder is watched recursively. Whenever it settles after a change,
+//! only the added and modified files are imported and a new revision of the
+//! share is published, see [`share::publish`], so subscribed receivers can
+//! follow along. Files are copied into the store instead of being
+//! referenced in place, so a published revision stays intact until it's released.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use iroh_blobs::Hash;
+use log::{error, info, warn};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::{
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+    time,
+};
+
+use crate::{
+    error::Error,
+    events,
+    file_operations::{self, watch::Stamp},
+    share,
+    state::{share_data::SavedFolder, AppState},
+    storage, utils,
+};
+
+/// How long the folder has to stay quiet before a new revision is published.
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Serializes syncs, the first one runs while the watcher may already report changes.
+static SYNC_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Published revisions kept alive, so receivers still fetching the previous
+/// one can finish.
+const KEPT_REVISIONS: usize = 2;
+
+pub struct LiveFolder {
+    root: PathBuf,
+    /// Shared names are `<prefix>/<path inside the folder>`.
+    prefix: String,
+    revision: u64,
+    imported: HashMap<String, Stamp>,
+    /// Collection roots of the kept revisions, newest last.
+    published: VecDeque<Hash>,
+    _watcher: RecommendedWatcher,
+    task: JoinHandle<()>,
+}
+
+impl fmt::Debug for LiveFolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LiveFolder")
+            .field("root", &self.root)
+            .field("revision", &self.revision)
+            .field("files", &self.imported.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl LiveFolder {
+    pub fn saved(&self) -> SavedFolder {
+        SavedFolder {
+            root: self.root.clone(),
+            published: self.published.iter().copied().collect(),
+        }
+    }
+}
+
+impl Drop for LiveFolder {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Every file below `root`, keyed by its shared name.
+fn scan(root: &Path, prefix: &str) -> Result<HashMap<String, (PathBuf, Stamp)>, String> {
+    let mut found = HashMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries =
+            fs::read_dir(&dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let Ok(relative) = path.strip_prefix(root) else {
+                continue;
+            };
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            found.insert(format!("{prefix}/{relative}"), (path, Stamp::of(&metadata)));
+        }
+    }
+
+    Ok(found)
+}
+
+async fn watch_changes(handle: AppHandle, mut rx: mpsc::UnboundedReceiver<()>) {
+    while rx.recv().await.is_some() {
+        // Wait for the folder to settle so a burst of writes becomes one revision
+        while let Ok(Some(())) = time::timeout(DEBOUNCE, rx.recv()).await {}
+
+        if let Err(e) = sync(&handle).await {
+            error!("Failed to update shared folder: {}", e);
+        }
+    }
+}
+
+/// Import what changed since the last revision and publish a new one.
+///
+/// The live folder is only locked to take a snapshot and to record the result,
+/// so stopping the share isn't held up by a long import.
+async fn sync(handle: &AppHandle) -> Result<(), String> {
+    let _sync = SYNC_LOCK.lock().await;
+    let state = handle.state::<AppState>();
+    let (root, prefix, imported) = {
+        let live = state.live_folder.lock().await;
+        let Some(folder) = live.as_ref() else {
+            return Ok(());
+        };
+        (
+            folder.root.clone(),
+            folder.prefix.clone(),
+            folder.imported.clone(),
+        )
+    };
+
+    let current = scan(&root, &prefix)?;
+    let removed = imported
+        .keys()
+        .filter(|name| !current.contains_key(*name))
+        .cloned()
+        .collect::<Vec<_>>();
+    let changed = current
+        .into_iter()
+        .filter(|(name, (_, stamp))| imported.get(name) != Some(stamp))
+        .collect::<Vec<_>>();
+
+    if removed.is_empty() && changed.is_empty() {
+        return Ok(());
+    }
+    info!(
+        "Shared folder {} changed: {} updated, {} removed",
+        root.display(),
+        changed.len(),
+        removed.len()
+    );
+
+    let blobs = &state.iroh().blobs;
+    for name in &removed {
+        release(handle, name).await?;
+    }
+
+    // Importing replaces the file's tag, the previous contents stay with the older revisions
+    let mut updated = Vec::with_capacity(changed.len());
+    for (name, (path, stamp)) in changed {
+        let original_path = path.display().to_string();
+        match file_operations::import_file(handle, &original_path, &path, &name, false).await {
+            Ok(file) => {
+                state.files().await.add_file(file);
+                updated.push((name, stamp));
+            }
+            Err(e) => warn!("Failed to import {}: {}", original_path, e),
+        }
+    }
+
+    let (ticket, revision) = share::publish(handle).await?;
+
+    let mut live = state.live_folder.lock().await;
+    let Some(folder) = live.as_mut().filter(|folder| folder.root == root) else {
+        // Stopped while importing, the files imported since aren't known to the stop
+        drop(live);
+        for (name, _) in &updated {
+            release(handle, name).await?;
+        }
+        storage::delete_header_tags(blobs, ticket.hash()).await?;
+        forget_ticket(&state, ticket.hash()).await;
+        return Ok(());
+    };
+
+    for name in &removed {
+        folder.imported.remove(name);
+    }
+    folder.imported.extend(updated);
+    folder.revision = revision;
+    folder.published.push_back(ticket.hash());
+
+    let mut released = Vec::new();
+    while folder.published.len() > KEPT_REVISIONS {
+        let Some(oldest) = folder.published.pop_front() else {
+            break;
+        };
+        // A revert can publish the same collection again
+        if !folder.published.contains(&oldest) {
+            released.push(oldest);
+        }
+    }
+    drop(live);
+
+    for root in released {
+        storage::delete_header_tags(blobs, root).await?;
+        forget_ticket(&state, root).await;
+    }
+
+    Ok(())
+}
+
+/// Remove the shared file `name` of the folder from the share.
+async fn release(handle: &AppHandle, name: &str) -> Result<(), String> {
+    let state = handle.state::<AppState>();
+    let blobs = &state.iroh().blobs;
+    storage::delete_tag(blobs, storage::share_file_tag(name)).await?;
+    storage::delete_tag(blobs, storage::share_icon_tag(name)).await?;
+    storage::delete_tag(blobs, storage::share_thumbnail_tag(name)).await?;
+    state.files().await.remove_file(name);
+
+    let payload = events::UploadFileRemoved {
+        name: name.to_string(),
+    };
+    handle.emit(events::UPLOAD_FILE_REMOVED, payload).ok();
+    Ok(())
+}
+
+async fn forget_ticket(state: &AppState, root: Hash) {
+    state
+        .header_tickets
+        .lock()
+        .await
+        .retain(|t| t.hash() != root);
+}
+
+/// Share the folder at `path` and keep the share up to date as it changes.
+#[tauri::command]
+pub async fn share_folder(path: String, handle: AppHandle) -> Result<(), Error> {
+    info!("Sharing folder: {}", path);
+    start(&handle, PathBuf::from(path), VecDeque::new()).await
+}
+
+/// Share a folder again after a restart. Its kept revisions are released as usual
+/// once newer ones are published.
+pub async fn resume(handle: &AppHandle, saved: SavedFolder) -> Result<(), Error> {
+    info!("Resuming folder share: {}", saved.root.display());
+    start(handle, saved.root, saved.published.into()).await
+}
+
+async fn start(handle: &AppHandle, path: PathBuf, published: VecDeque<Hash>) -> Result<(), Error> {
+    let state = handle.state::<AppState>();
+
+    let root = path
+        .canonicalize()
+        .map_err(|e| format!("Failed to canonicalize path: {:?}", e))?;
+    if !root.is_dir() {
+        return Err(Error::InvalidInput {
+            what: "folder",
+            reason: format!("{} is not a folder", path.display()),
+        });
+    }
+    let prefix = utils::file_name_from_path(&root)?;
+
+    {
+        let files = state.files().await;
+        let folder_prefix = format!("{prefix}/");
+        if files
+            .keys()
+            .any(|name| *name == prefix || name.starts_with(&folder_prefix))
+        {
+            return Err(Error::DuplicateName { name: prefix });
+        }
+    }
+
+    let mut live = state.live_folder.lock().await;
+    if live.is_some() {
+        return Err(Error::InvalidInput {
+            what: "folder",
+            reason: "A folder is already being shared".to_string(),
+        });
+    }
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) if !event.kind.is_access() => {
+            tx.send(()).ok();
+        }
+        Ok(_) => {}
+        Err(e) => error!("Folder watcher error: {}", e),
+    })
+    .map_err(|e| format!("Failed to watch folder: {}", e))?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch folder: {}", e))?;
+
+    *live = Some(LiveFolder {
+        root,
+        prefix,
+        revision: 0,
+        imported: HashMap::new(),
+        published,
+        _watcher: watcher,
+        task: tokio::spawn(watch_changes(handle.clone(), rx)),
+    });
+    drop(live);
+
+    // The first sync imports everything and publishes the first revision
+    Ok(sync(handle).await?)
+}
+
+/// Stop sharing the live folder and remove its files from the share.
+#[tauri::command]
+pub async fn stop_folder_share(handle: AppHandle) -> Result<(), Error> {
+    let state = handle.state::<AppState>();
+    let Some(folder) = state.live_folder.lock().await.take() else {
+        return Ok(());
+    };
+    info!("Stopped sharing folder: {}", folder.root.display());
+
+    for name in folder.imported.keys() {
+        release(&handle, name).await?;
+    }
+
+    for root in &folder.published {
+        storage::delete_header_tags(&state.iroh().blobs, *root).await?;
+        forget_ticket(&state, *root).await;
+    }
+
+    share::save(&handle);
+    Ok(())
+}
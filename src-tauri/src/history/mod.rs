@@ -0,0 +1,108 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::{error::Error, offer::rules::Action, utils};
+
+pub const HISTORY_FILE_NAME: &str = "history.jsonl";
+
+// Serializes appends so concurrent transfers don't interleave lines.
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HistoryEvent {
+    OfferDecision {
+        sender: String,
+        action: Action,
+        rule: Option<String>,
+        file_count: usize,
+        total_size: u64,
+    },
+    /// What a receiver fetched from a share during one connection.
+    Upload {
+        receiver: String,
+        share: String,
+        files_sent: usize,
+        files_total: usize,
+        bytes_sent: u64,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub event: HistoryEvent,
+}
+
+impl HistoryEntry {
+    pub fn now(event: HistoryEvent) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self { timestamp, event }
+    }
+}
+
+/// Append an event to the history log. Failures are logged and otherwise ignored.
+pub fn record(handle: &AppHandle, event: HistoryEvent) {
+    if let Err(e) = append(handle, HistoryEntry::now(event)) {
+        error!("Failed to record history: {}", e);
+    }
+}
+
+fn append(handle: &AppHandle, entry: HistoryEntry) -> Result<(), String> {
+    let dir = utils::get_config_dir(handle)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create config dir: {}", e))?;
+
+    let mut line = serde_json::to_string(&entry)
+        .map_err(|e| format!("Failed to serialize history entry: {}", e))?;
+    line.push('\n');
+
+    let _guard = HISTORY_LOCK.lock().map_err(|e| e.to_string())?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(HISTORY_FILE_NAME))
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| format!("Failed to write history file: {}", e))
+}
+
+#[tauri::command]
+pub fn get_history(app: AppHandle) -> Result<Vec<HistoryEntry>, Error> {
+    let path = utils::get_config_dir(&app)?.join(HISTORY_FILE_NAME);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let _guard = HISTORY_LOCK.lock().map_err(|e| e.to_string())?;
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read history file: {}", e))?;
+
+    // Skip lines we can't parse, e.g. entries written by a newer version.
+    let entries = contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+
+    Ok(entries)
+}
+
+#[tauri::command]
+pub fn clear_history(app: AppHandle) -> Result<(), Error> {
+    let path = utils::get_config_dir(&app)?.join(HISTORY_FILE_NAME);
+    let _guard = HISTORY_LOCK.lock().map_err(|e| e.to_string())?;
+    if path.exists() {
+        fs::remove_file(path).map_err(|e| format!("Failed to remove history file: {}", e))?;
+    }
+    Ok(())
+}
@@ -1,18 +1,88 @@
-use std::{ops::Deref, path::PathBuf, str::FromStr};
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Result;
-use iroh::{protocol::Router, NodeAddr, NodeId};
+use iroh::{
+    protocol::Router, Endpoint, NodeAddr, NodeId, RelayMap, RelayMode, RelayNode, RelayUrl,
+    SecretKey,
+};
+use iroh_blobs::store::{fs::Store, GcConfig};
 use iroh_gossip::{
     net::{Gossip, GossipReceiver, GossipSender},
     proto::TopicId,
 };
 use quic_rpc::transport::flume::FlumeConnector;
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    offer::{self, OfferChannel, OfferProtocol},
+    share::pointer::{self, PointerProtocol, PointerStore},
+    state::settings::Settings,
+    ticket::code::Rendezvous,
+    uploads::{Gate, TrackedBlobs, UploadChannel, UploadEventSender},
+};
+
+const GC_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// The node's secret key, kept in the data dir so the node id survives restarts.
+const SECRET_KEY_FILE_NAME: &str = "node.key";
+const SHARE_TOPIC_CONTEXT: &[u8] = b"sendit/share-topic/0";
 
 pub type BlobsClient = iroh_blobs::rpc::client::blobs::Client<
     FlumeConnector<iroh_blobs::rpc::proto::Response, iroh_blobs::rpc::proto::Request>,
 >;
 
+/// Relay servers that carry traffic when no direct path is found.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "mode", content = "urls")]
+pub enum RelayConfig {
+    /// The public relays run by n0.
+    #[default]
+    Default,
+    /// Only direct connections, peers behind a strict NAT can't be reached.
+    Disabled,
+    /// Self-hosted relays. Tickets name the one the endpoint connected to, or
+    /// the first if it hadn't yet.
+    Custom(Vec<RelayUrl>),
+}
+
+impl RelayConfig {
+    fn mode(&self) -> Result<RelayMode> {
+        Ok(match self {
+            Self::Default => RelayMode::Default,
+            Self::Disabled => RelayMode::Disabled,
+            Self::Custom(urls) => {
+                let nodes = urls.iter().cloned().map(RelayNode::from);
+                RelayMode::Custom(RelayMap::from_nodes(nodes)?)
+            }
+        })
+    }
+
+    /// Make `node_addr` point at the configured relays, so receivers handed a
+    /// ticket connect through them.
+    fn apply(&self, node_addr: &mut NodeAddr) {
+        match self {
+            Self::Default => {}
+            Self::Disabled => node_addr.relay_url = None,
+            Self::Custom(urls) => {
+                if !node_addr
+                    .relay_url
+                    .as_ref()
+                    .is_some_and(|url| urls.contains(url))
+                {
+                    node_addr.relay_url = urls.first().cloned();
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct GossipTicket {
     pub topic_id: TopicId,
@@ -70,21 +140,26 @@ impl Deref for GossipClient {
 
 impl GossipClient {
     pub async fn new(gossip: Gossip, node_id: NodeId) -> Result<Self> {
-        let topic_id = TopicId::from_bytes(rand::random());
+        let topic_id = share_topic(node_id, "");
         let ticket = GossipTicket::new(topic_id, node_id);
-        let (sender, receiver) = gossip.subscribe(topic_id, vec![])?.split();
-        let gossip_chan = GossipChannel {
-            sender,
-            receiver: Some(receiver),
-        };
+        let channel = join(&gossip, topic_id)?;
 
         Ok(Self {
             client: gossip,
             ticket,
-            channel: gossip_chan,
+            channel,
         })
     }
 
+    /// Join `topic` without bootstrap nodes, receivers join through us.
+    pub fn join(&self, topic: TopicId) -> Result<GossipChannel> {
+        join(&self.client, topic)
+    }
+
+    pub fn channel(&self) -> &GossipChannel {
+        &self.channel
+    }
+
     pub fn channel_mut(&mut self) -> &mut GossipChannel {
         &mut self.channel
     }
@@ -94,6 +169,28 @@ impl GossipClient {
     }
 }
 
+/// The topic a share is announced on. Derived from the node id so mirrors
+/// find it again after a restart. The send page share has an empty `share` id.
+pub fn share_topic(node_id: NodeId, share: &str) -> TopicId {
+    let topic = iroh_blobs::Hash::new(
+        [
+            SHARE_TOPIC_CONTEXT,
+            node_id.as_bytes().as_slice(),
+            share.as_bytes(),
+        ]
+        .concat(),
+    );
+    TopicId::from_bytes(*topic.as_bytes())
+}
+
+fn join(gossip: &Gossip, topic: TopicId) -> Result<GossipChannel> {
+    let (sender, receiver) = gossip.subscribe(topic, vec![])?.split();
+    Ok(GossipChannel {
+        sender,
+        receiver: Some(receiver),
+    })
+}
+
 #[derive(Debug)]
 pub struct GossipChannel {
     sender: GossipSender,
@@ -116,49 +213,110 @@ impl GossipChannel {
     }
 }
 
+/// Read the node's secret key from `path`, creating it on first start.
+async fn load_secret_key(path: &Path) -> Result<SecretKey> {
+    if let Ok(contents) = tokio::fs::read_to_string(path).await {
+        let bytes: [u8; 32] = data_encoding::HEXLOWER
+            .decode(contents.trim().as_bytes())?
+            .as_slice()
+            .try_into()?;
+        return Ok(SecretKey::from_bytes(&bytes));
+    }
+
+    let key = SecretKey::from_bytes(&rand::random());
+    tokio::fs::write(path, data_encoding::HEXLOWER.encode(&key.to_bytes())).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).await?;
+    }
+    Ok(key)
+}
+
 #[derive(Debug)]
 pub struct Iroh {
-    #[allow(dead_code)]
     router: Router,
     pub blobs: BlobsClient,
+    /// The store behind `blobs`, for reading and writing partial blobs.
+    pub store: Store,
     pub node_addr: NodeAddr,
     pub gossip: GossipClient,
+    pub offers: OfferChannel,
+    pub uploads: UploadChannel,
+    pub rendezvous: Rendezvous,
+    pub pointers: PointerStore,
 }
 
 impl Iroh {
-    pub async fn new(path: PathBuf) -> Result<Self> {
+    pub async fn new(path: PathBuf, settings: &Settings) -> Result<Self> {
         // create dir if it doesn't already exist
         tokio::fs::create_dir_all(&path).await?;
 
-        // create endpoint
-        let endpoint = iroh::Endpoint::builder().discovery_n0().bind().await?;
+        // create endpoint, tickets handed out before a restart name this node id
+        let secret_key = load_secret_key(&path.join(SECRET_KEY_FILE_NAME)).await?;
+        let endpoint = iroh::Endpoint::builder()
+            .secret_key(secret_key)
+            .relay_mode(settings.relay.mode()?)
+            .discovery_n0()
+            .bind()
+            .await?;
 
         // build the protocol router
         let mut builder = iroh::protocol::Router::builder(endpoint);
 
-        // add iroh blobs
+        // add iroh blobs, reporting who fetches what
+        let (upload_tx, upload_rx) = mpsc::unbounded_channel();
+        let gate = Arc::new(Gate::default());
         let blobs = iroh_blobs::net_protocol::Blobs::persistent(&path)
             .await?
+            .events(UploadEventSender::new(upload_tx.clone(), Arc::clone(&gate)).into())
             .build(builder.endpoint());
-        builder = builder.accept(iroh_blobs::ALPN, blobs.clone());
+        builder = builder.accept(
+            iroh_blobs::ALPN,
+            TrackedBlobs::new(blobs.clone(), upload_tx, Arc::clone(&gate)),
+        );
+        blobs.start_gc(GcConfig {
+            period: GC_PERIOD,
+            done_callback: None,
+        })?;
 
         // add iroh gossip
         let gossip = Gossip::builder().spawn(builder.endpoint().clone()).await?;
         builder = builder.accept(iroh_gossip::ALPN, gossip.clone());
 
-        let node_addr = builder.endpoint().node_addr().await?;
+        // add sendit offers
+        let (offer_tx, offer_rx) = mpsc::channel(16);
+        builder = builder.accept(offer::ALPN, OfferProtocol::new(offer_tx));
+
+        // add named share pointers
+        let pointers = PointerStore::default();
+        builder = builder.accept(pointer::ALPN, PointerProtocol::new(pointers.clone()));
+
+        let mut node_addr = builder.endpoint().node_addr().await?;
+        settings.relay.apply(&mut node_addr);
         let router = builder.spawn().await?;
+        let store = blobs.store().clone();
         let blobs = blobs.client().clone();
+        let rendezvous = Rendezvous::join(&gossip, &settings.rendezvous_nodes)?;
         let gossip = GossipClient::new(gossip, node_addr.node_id).await?;
 
         Ok(Self {
             node_addr,
             router,
             blobs,
+            store,
             gossip,
+            offers: OfferChannel::new(offer_rx),
+            uploads: UploadChannel::new(upload_rx, gate),
+            rendezvous,
+            pointers,
         })
     }
 
+    pub fn endpoint(&self) -> &Endpoint {
+        self.router.endpoint()
+    }
+
     #[allow(dead_code)]
     pub async fn shutdown(&self) -> Result<(), String> {
         self.router.shutdown().await.map_err(|e| e.to_string())
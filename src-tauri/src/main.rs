@@ -1,18 +1,36 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod diagnostics;
 mod download;
+mod error;
 mod events;
 mod file_operations;
 mod files;
+mod folder;
+mod history;
 mod iroh;
+mod mirror;
+mod offer;
+mod progress;
+mod share;
+mod snippet;
 mod state;
+mod storage;
+mod stream;
 mod theme;
 mod ticket;
+mod uploads;
 mod utils;
 
+use file_operations::watch::SourceWatcher;
 use log::LevelFilter;
-use state::user_data::{self, User};
+use offer::rules::{self, Rules};
+use state::{
+    settings::{self, Settings},
+    user_data::{self, User},
+};
 use std::{fs, time::Duration};
+use storage::access::{self, AccessLog};
 use tauri::{AppHandle, Emitter, Manager};
 use tauri_plugin_log::{Target, TargetKind};
 use tokio::time;
@@ -28,17 +46,21 @@ async fn setup(handle: tauri::AppHandle) -> anyhow::Result<()> {
         false => handle.path().app_local_data_dir()?,
     };
 
+    let cfg_dir = utils::get_config_dir(&handle).map_err(|e| anyhow::anyhow!(e))?;
+    let settings =
+        Settings::from_config(cfg_dir.join(settings::SETTINGS_FILE_NAME)).unwrap_or_default();
+
     let data_dir = app_data_dir.join(DATA_DIR);
     fs::create_dir_all(&data_dir)?;
     info!("Data directory created at: {}", data_dir.display());
 
-    let mut iroh = iroh::Iroh::new(data_dir).await?;
+    let mut iroh = iroh::Iroh::new(data_dir, &settings).await?;
     let channel = &mut iroh.gossip.channel_mut();
     #[allow(unused)]
     let rx = channel.take_receiver()?;
 
     #[cfg(debug_assertions)]
-    let iroh_debug = {
+    let mut iroh_debug = {
         let data_dir = app_data_dir.join(DATA_DIR_DEBUG);
         fs::create_dir_all(&data_dir)?;
         info!(
@@ -46,15 +68,43 @@ async fn setup(handle: tauri::AppHandle) -> anyhow::Result<()> {
             data_dir.display()
         );
 
-        iroh::Iroh::new(data_dir).await?
+        iroh::Iroh::new(data_dir, &settings).await?
     };
 
-    let cfg_path = utils::get_config_dir(&handle)
-        .map_err(|e| anyhow::anyhow!(e))?
-        .join(user_data::CONFIG_FILE_NAME);
-
-    let user = User::from_config(cfg_path).ok();
-    handle.manage(state::AppState::new(user, iroh, iroh_debug));
+    // Offers are received by the same node that performs downloads
+    #[cfg(debug_assertions)]
+    let offers_rx = iroh_debug.offers.take_receiver()?;
+    #[cfg(not(debug_assertions))]
+    let offers_rx = iroh.offers.take_receiver()?;
+
+    // Uploads are served by the sending node
+    let uploads_rx = iroh.uploads.take_receiver()?;
+
+    let user = User::from_config(cfg_dir.join(user_data::CONFIG_FILE_NAME)).ok();
+    let accept_rules = Rules::from_config(cfg_dir.join(rules::RULES_FILE_NAME)).unwrap_or_default();
+    let blob_access = AccessLog::load(cfg_dir.join(access::ACCESS_FILE_NAME));
+    let sources = SourceWatcher::new(handle.clone())?;
+    handle.manage(state::AppState::new(
+        user,
+        settings,
+        accept_rules,
+        blob_access,
+        sources,
+        iroh,
+        iroh_debug,
+    ));
+    share::restore(&handle).await;
+    // Limited shares are only known once restored, requests are refused until then
+    handle
+        .state::<state::AppState>()
+        .iroh()
+        .uploads
+        .gate()
+        .serve(handle.clone());
+
+    tokio::spawn(offer::handle_incoming(handle.clone(), offers_rx));
+    tokio::spawn(uploads::handle_events(handle.clone(), uploads_rx));
+    tokio::spawn(access::flush_periodically(handle.clone()));
 
     Ok(())
 }
@@ -83,6 +133,12 @@ fn main() {
         .plugin(tauri_plugin_clipboard_manager::init()) // CLIPBOARD
         .plugin(tauri_plugin_dialog::init()) // DIALOG
         .plugin(tauri_plugin_opener::init()) // FILE OPENER
+        .register_asynchronous_uri_scheme_protocol(stream::SCHEME, |ctx, request, responder| {
+            let handle = ctx.app_handle().clone();
+            tauri::async_runtime::spawn(async move {
+                responder.respond(stream::respond(&handle, request).await);
+            });
+        })
         .setup(|app| {
             let handle = app.handle().clone();
             let window = handle.get_webview_window("main").unwrap();
@@ -114,12 +170,52 @@ fn main() {
             file_operations::add_file,
             file_operations::remove_file,
             file_operations::remove_all_files,
+            file_operations::reimport_file,
+            file_operations::list_shared_files,
+            folder::share_folder,
+            folder::stop_folder_share,
+            mirror::start_mirror,
+            mirror::stop_mirror,
+            mirror::list_mirrors,
             file_operations::validate_files,
             download::download_header,
+            download::archive::download_archive,
             ticket::generate_ticket,
+            ticket::code::create_share_code,
+            ticket::code::redeem_share_code,
+            ticket::qr::ticket_to_qr,
+            ticket::qr::decode_qr_from_file,
+            ticket::qr::decode_qr_from_clipboard,
+            offer::send_offer,
+            share::create_named_share,
+            share::get_named_share,
+            share::close_named_share,
+            share::registry::create_share,
+            share::registry::list_shares,
+            share::registry::update_share,
+            share::registry::add_share_files,
+            share::registry::remove_share_file,
+            share::registry::offer_share,
+            share::registry::close_share,
+            snippet::add_snippet,
+            snippet::add_clipboard_snippet,
+            offer::rules::get_accept_rules,
+            offer::rules::set_accept_rules,
+            history::get_history,
+            history::clear_history,
             theme::set_theme,
+            storage::storage_report,
+            storage::reclaim_storage,
+            storage::list_stored_blobs,
+            storage::cleanup_storage,
+            uploads::get_upload_stats,
+            diagnostics::get_diagnostics,
+            diagnostics::watch_diagnostics,
+            diagnostics::unwatch_diagnostics,
             state::get_user,
             state::update_user,
+            state::settings::get_settings,
+            state::settings::update_settings,
             state::user_data::is_onboarded,
             state::app_loaded
         ])
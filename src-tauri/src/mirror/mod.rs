@@ -0,0 +1,374 @@
+//! One-way folder sync.
+//!
+//! A mirror follows another node's share. It applies the current manifest to a
+//! local folder, then listens on the share's gossip topic and applies every newer
+//! revision the sender announces. Only files whose hash differs from the local
+//! copy are fetched, through the regular download and export pipeline.
+//!
+//! Announcements must be signed by the sender for the share's topic, and the
+//! last applied revision is saved, so a replayed announcement can't roll the
+//! folder back, not even after a restart.
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use iroh::NodeId;
+use iroh_blobs::{ticket::BlobTicket, Hash};
+use iroh_gossip::{
+    net::{Event, GossipEvent, GossipReceiver, GossipSender},
+    proto::TopicId,
+};
+use log::{error, info, warn};
+use n0_future::stream::StreamExt;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+use crate::{
+    download,
+    error::Error,
+    events,
+    files::{self, Files},
+    progress::Transfer,
+    share::ShareMessage,
+    state::{
+        mirror_data::{SavedMirrors, MIRRORS_FILE_NAME},
+        AppState, State,
+    },
+    storage,
+    ticket::AnyTicket,
+    utils,
+};
+
+// Serializes saves so an older snapshot never overwrites a newer one.
+static SAVE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// A running mirror, stopped when dropped.
+#[derive(Debug)]
+pub struct Mirror {
+    sender: NodeId,
+    dir: PathBuf,
+    delete_removed: bool,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MirrorInfo {
+    pub id: String,
+    pub sender: String,
+    pub dir: String,
+    pub delete_removed: bool,
+}
+
+struct MirrorJob {
+    handle: AppHandle,
+    id: String,
+    sender: NodeId,
+    topic: TopicId,
+    dir: PathBuf,
+    delete_removed: bool,
+    revision: u64,
+    /// Files this mirror wrote, the only ones it may delete.
+    synced: HashSet<String>,
+}
+
+/// Hash of the local file at `path` if it has the expected `size`.
+async fn local_hash(path: PathBuf, size: u64) -> Option<Hash> {
+    tokio::task::spawn_blocking(move || {
+        if path.metadata().ok()?.len() != size {
+            return None;
+        }
+        // iroh-blobs hashes are plain blake3 hashes of the content
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(fs::File::open(&path).ok()?).ok()?;
+        Some(Hash::from_bytes(*hasher.finalize().as_bytes()))
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+impl MirrorJob {
+    async fn run(
+        mut self,
+        ticket: BlobTicket,
+        files: Files,
+        _sender: GossipSender,
+        mut receiver: GossipReceiver,
+    ) {
+        self.apply(&ticket, files).await;
+
+        while let Some(event) = receiver.next().await {
+            let msg = match event {
+                Ok(Event::Gossip(GossipEvent::Received(msg))) => msg,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!("Mirror {} lost its share topic: {}", self.id, e);
+                    break;
+                }
+            };
+
+            let Ok(msg) = serde_json::from_slice::<ShareMessage>(&msg.content) else {
+                continue;
+            };
+            // Anyone on the topic can gossip, only follow updates the sender signed
+            let (revision, ticket) = match msg.verify(self.sender, self.topic) {
+                Ok(update) => update,
+                Err(e) => {
+                    warn!("Mirror {} ignored an update: {}", self.id, e);
+                    continue;
+                }
+            };
+            if revision <= self.revision {
+                continue;
+            }
+
+            let files = match self.fetch(&ticket).await {
+                Ok(files) => files,
+                Err(error) => {
+                    self.report_error(error);
+                    continue;
+                }
+            };
+            self.revision = revision;
+            if let Err(e) = save_revision(&self.handle, &self.id, revision).await {
+                error!("Failed to save mirror {}: {}", self.id, e);
+            }
+            self.apply(&ticket, files).await;
+        }
+    }
+
+    async fn fetch(&self, ticket: &BlobTicket) -> Result<Files, Error> {
+        let state = self.handle.state::<AppState>();
+        let iroh = match cfg!(debug_assertions) {
+            true => &state.iroh_debug,
+            false => state.iroh(),
+        };
+        files::collection::fetch_manifest(&iroh.blobs, ticket.clone()).await
+    }
+
+    /// Bring the local folder in line with `files`.
+    async fn apply(&mut self, ticket: &BlobTicket, files: Files) {
+        let state = self.handle.state::<AppState>();
+        let iroh = match cfg!(debug_assertions) {
+            true => &state.iroh_debug,
+            false => state.iroh(),
+        };
+        let keep_received = state.settings.lock().await.keep_received_blobs;
+        let root = ticket.hash();
+
+        let mut downloaded = 0;
+        let transfer = std::sync::Mutex::new(Transfer::default());
+        // Snippets are only shown to the receiver, there is nothing to sync
+        for file in files.values().filter(|file| file.kind.is_file()) {
+            let dest = match utils::join_shared_name(&self.dir, &file.name) {
+                Ok(dest) => dest,
+                Err(error) => {
+                    self.report_error(error);
+                    continue;
+                }
+            };
+            if local_hash(dest, file.size).await == Some(file.content_hash()) {
+                self.synced.insert(file.name.clone());
+                continue;
+            }
+
+            let res = download::download_file(
+                &self.handle,
+                file.clone(),
+                &self.dir,
+                ticket.node_addr().clone(),
+                root,
+                keep_received,
+                true,
+                &transfer,
+            )
+            .await;
+            match res {
+                Ok(()) => {
+                    self.synced.insert(file.name.clone());
+                    downloaded += 1;
+                }
+                Err(error) => self.report_error(error),
+            }
+        }
+
+        let mut deleted = 0;
+        if self.delete_removed {
+            let removed = self
+                .synced
+                .iter()
+                .filter(|name| !files.contains_key(*name))
+                .cloned()
+                .collect::<Vec<_>>();
+            for name in removed {
+                self.synced.remove(&name);
+                if let Err(e) = remove_synced(&self.dir, &name) {
+                    warn!("Failed to delete {}: {}", name, e);
+                    continue;
+                }
+                deleted += 1;
+            }
+        }
+
+        if !keep_received {
+            let prefix = storage::download_prefix(root);
+            if let Err(e) = storage::delete_tags_with_prefix(&iroh.blobs, &prefix).await {
+                warn!("Failed to release download: {}", e);
+            }
+        }
+
+        info!(
+            "Mirror {} at revision {}: {} downloaded, {} deleted",
+            self.id, self.revision, downloaded, deleted
+        );
+        let payload = events::MirrorUpdated {
+            id: self.id.clone(),
+            revision: self.revision,
+            downloaded,
+            deleted,
+        };
+        self.handle.emit(events::MIRROR_UPDATED, payload).ok();
+    }
+
+    fn report_error(&self, error: impl Into<Error>) {
+        let error = error.into();
+        error!("Mirror {} failed: {}", self.id, error);
+        let payload = events::MirrorError {
+            id: self.id.clone(),
+            error,
+        };
+        self.handle.emit(events::MIRROR_ERROR, payload).ok();
+    }
+}
+
+/// The last revision the mirror `id` applied, 0 if it never saw one.
+fn saved_revision(handle: &AppHandle, id: &str) -> u64 {
+    utils::get_config_dir(handle)
+        .ok()
+        .and_then(|dir| SavedMirrors::from_config(dir.join(MIRRORS_FILE_NAME)).ok())
+        .and_then(|saved| saved.revisions.get(id).copied())
+        .unwrap_or_default()
+}
+
+async fn save_revision(handle: &AppHandle, id: &str, revision: u64) -> Result<(), String> {
+    let _save = SAVE_LOCK.lock().await;
+    let path = utils::get_config_dir(handle)?.join(MIRRORS_FILE_NAME);
+    let mut saved = SavedMirrors::from_config(path.clone()).unwrap_or_default();
+    saved.revisions.insert(id.to_string(), revision);
+    saved.save(path).map_err(|e| e.to_string())
+}
+
+fn remove_synced(dir: &Path, name: &str) -> io::Result<()> {
+    let path = utils::join_shared_name(dir, name).map_err(io::Error::other)?;
+    match fs::remove_file(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// Mirror the share behind `ticket` into `dir`, or the download folder, until stopped.
+/// Returns the mirror's id.
+#[tauri::command]
+pub async fn start_mirror(
+    ticket: String,
+    dir: Option<String>,
+    delete_removed: bool,
+    handle: AppHandle,
+) -> Result<String, Error> {
+    let dir = match dir {
+        Some(dir) => PathBuf::from(dir),
+        None => utils::get_download_dir(&handle)?,
+    };
+
+    let state = handle.state::<AppState>();
+    let iroh = match cfg!(debug_assertions) {
+        true => &state.iroh_debug,
+        false => state.iroh(),
+    };
+
+    let ticket = AnyTicket::from_str(&ticket)?
+        .resolve(iroh.endpoint())
+        .await?;
+    let files = files::collection::fetch_manifest(&iroh.blobs, ticket.clone()).await?;
+    let topic = files.gossip_ticket().topic_id;
+    let id = topic.to_string();
+    let sender = ticket.node_addr().node_id;
+
+    let mut mirrors = state.mirrors.lock().await;
+    if mirrors.contains_key(&id) {
+        return Err(Error::InvalidInput {
+            what: "mirror",
+            reason: "This share is already being mirrored".to_string(),
+        });
+    }
+
+    iroh.endpoint()
+        .add_node_addr(ticket.node_addr().clone())
+        .map_err(|e| Error::Connection {
+            message: format!("Failed to add sender address: {}", e),
+        })?;
+    let (gossip_sender, receiver) = iroh
+        .gossip
+        .subscribe(topic, vec![sender])
+        .map_err(|e| Error::Connection {
+            message: format!("Failed to subscribe to share: {}", e),
+        })?
+        .split();
+
+    info!("Mirroring share {} into {}", id, dir.display());
+    let job = MirrorJob {
+        handle: handle.clone(),
+        id: id.clone(),
+        sender,
+        topic,
+        dir: dir.clone(),
+        delete_removed,
+        revision: saved_revision(&handle, &id),
+        synced: HashSet::new(),
+    };
+    let mirror = Mirror {
+        sender,
+        dir,
+        delete_removed,
+        task: tokio::spawn(job.run(ticket, files, gossip_sender, receiver)),
+    };
+    mirrors.insert(id.clone(), mirror);
+
+    Ok(id)
+}
+
+#[tauri::command]
+pub async fn stop_mirror(state: State<'_>, id: String) -> Result<(), Error> {
+    match state.mirrors.lock().await.remove(&id) {
+        Some(_) => {
+            info!("Stopped mirror {}", id);
+            Ok(())
+        }
+        None => Err(Error::NotFound { what: "mirror", id }),
+    }
+}
+
+#[tauri::command]
+pub async fn list_mirrors(state: State<'_>) -> Result<Vec<MirrorInfo>, Error> {
+    let mirrors = state.mirrors.lock().await;
+    Ok(mirrors
+        .iter()
+        .map(|(id, mirror)| MirrorInfo {
+            id: id.clone(),
+            sender: mirror.sender.to_string(),
+            dir: mirror.dir.display().to_string(),
+            delete_removed: mirror.delete_removed,
+        })
+        .collect())
+}
@@ -0,0 +1,215 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use iroh::{
+    endpoint::{get_remote_node_id, Connection},
+    protocol::ProtocolHandler,
+    Endpoint, NodeId,
+};
+use iroh_blobs::ticket::BlobTicket;
+use log::{error, info};
+use n0_future::boxed::BoxFuture;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+
+use crate::{
+    download,
+    error::Error,
+    events, files,
+    history::{self, HistoryEvent},
+    share,
+    state::{AppState, State},
+    ticket, utils,
+};
+use rules::{Action, OfferSummary};
+
+pub mod rules;
+
+pub const ALPN: &[u8] = b"sendit/offer/0";
+
+const MAX_OFFER_SIZE: usize = 4096;
+const ACK: &[u8] = b"received";
+
+/// A header ticket pushed to us by another node.
+#[derive(Debug)]
+pub struct IncomingOffer {
+    pub sender: NodeId,
+    pub ticket: BlobTicket,
+}
+
+/// Accepts offers on [`ALPN`] and forwards them to the app through a channel.
+#[derive(Debug, Clone)]
+pub struct OfferProtocol {
+    tx: mpsc::Sender<IncomingOffer>,
+}
+
+impl OfferProtocol {
+    pub fn new(tx: mpsc::Sender<IncomingOffer>) -> Self {
+        Self { tx }
+    }
+}
+
+impl ProtocolHandler for OfferProtocol {
+    fn accept(&self, connection: Connection) -> BoxFuture<Result<()>> {
+        let tx = self.tx.clone();
+        Box::pin(async move {
+            let sender = get_remote_node_id(&connection)?;
+            let (mut send, mut recv) = connection.accept_bi().await?;
+
+            let bytes = recv.read_to_end(MAX_OFFER_SIZE).await?;
+            let ticket = BlobTicket::from_str(std::str::from_utf8(&bytes)?)?;
+
+            send.write_all(ACK).await?;
+            send.finish()?;
+
+            info!("Received offer from {}", sender);
+            tx.send(IncomingOffer { sender, ticket }).await?;
+
+            connection.closed().await;
+            Ok(())
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct OfferChannel {
+    receiver: Option<mpsc::Receiver<IncomingOffer>>,
+}
+
+impl OfferChannel {
+    pub fn new(receiver: mpsc::Receiver<IncomingOffer>) -> Self {
+        Self {
+            receiver: Some(receiver),
+        }
+    }
+
+    pub fn take_receiver(&mut self) -> Result<mpsc::Receiver<IncomingOffer>> {
+        self.receiver
+            .take()
+            .ok_or(anyhow::anyhow!("Receiver already taken"))
+    }
+}
+
+/// Runs the accept rules against every incoming offer until the channel closes.
+pub async fn handle_incoming(handle: AppHandle, mut rx: mpsc::Receiver<IncomingOffer>) {
+    while let Some(offer) = rx.recv().await {
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            if let Err(e) = process_offer(&handle, offer).await {
+                error!("Failed to process offer: {}", e);
+            }
+        });
+    }
+}
+
+async fn process_offer(handle: &AppHandle, offer: IncomingOffer) -> Result<(), Error> {
+    let state = handle.state::<AppState>();
+
+    let iroh = match cfg!(debug_assertions) {
+        true => &state.iroh_debug,
+        false => state.iroh(),
+    };
+
+    // Offers rejected for their sender alone aren't fetched at all
+    let rules = state.accept_rules.lock().await.clone();
+    let (summary, decision) = match rules.evaluate_sender(offer.sender) {
+        Some(decision) if decision.action == Action::Reject => {
+            let summary = OfferSummary {
+                sender: offer.sender,
+                total_size: 0,
+                file_count: 0,
+                extensions: Vec::new(),
+            };
+            (summary, decision)
+        }
+        _ => {
+            let files =
+                files::collection::fetch_manifest(&iroh.blobs, offer.ticket.clone()).await?;
+            let summary = OfferSummary::new(offer.sender, &files);
+            let decision = rules.evaluate(&summary);
+            (summary, decision)
+        }
+    };
+    info!(
+        "Offer from {} decided as {:?} (rule: {:?})",
+        offer.sender, decision.action, decision.rule
+    );
+
+    history::record(
+        handle,
+        HistoryEvent::OfferDecision {
+            sender: offer.sender.to_string(),
+            action: decision.action,
+            rule: decision.rule.clone(),
+            file_count: summary.file_count,
+            total_size: summary.total_size,
+        },
+    );
+
+    let payload = events::OfferDecision {
+        sender: offer.sender.to_string(),
+        ticket: offer.ticket.to_string(),
+        action: decision.action,
+        rule: decision.rule.clone(),
+        file_count: summary.file_count,
+        total_size: summary.total_size,
+    };
+    handle.emit(events::OFFER_DECISION, payload).ok();
+
+    match decision.action {
+        Action::AutoAccept => {
+            let export_dir = match decision.download_dir {
+                Some(dir) => dir,
+                None => utils::get_download_dir(handle)?,
+            };
+            download::download_share(handle.clone(), offer.ticket, export_dir).await
+        }
+        // The frontend prompts the user with the ticket from the decision event.
+        Action::Prompt | Action::Reject => Ok(()),
+    }
+}
+
+#[tauri::command]
+pub async fn send_offer(state: State<'_>, node_id: String, handle: AppHandle) -> Result<(), Error> {
+    let node_id = NodeId::from_str(&node_id).map_err(|e| Error::InvalidInput {
+        what: "node id",
+        reason: e.to_string(),
+    })?;
+    info!("Sending offer to {}", node_id);
+
+    let ticket = ticket::create_header_ticket(&state).await?;
+    share::save(&handle);
+
+    offer_ticket(state.iroh().endpoint(), node_id, &ticket).await
+}
+
+/// Push `ticket` to `node_id` and wait for it to acknowledge the offer.
+pub async fn offer_ticket(
+    endpoint: &Endpoint,
+    node_id: NodeId,
+    ticket: &BlobTicket,
+) -> Result<(), Error> {
+    let failed = |message: String| Error::Connection { message };
+    let connection = endpoint
+        .connect(node_id, ALPN)
+        .await
+        .map_err(|e| failed(format!("Failed to connect to peer: {}", e)))?;
+
+    let (mut send, mut recv) = connection
+        .open_bi()
+        .await
+        .map_err(|e| failed(format!("Failed to open stream: {}", e)))?;
+
+    send.write_all(ticket.to_string().as_bytes())
+        .await
+        .map_err(|e| failed(format!("Failed to send offer: {}", e)))?;
+    send.finish()
+        .map_err(|e| failed(format!("Failed to send offer: {}", e)))?;
+
+    recv.read_to_end(ACK.len())
+        .await
+        .map_err(|e| failed(format!("Peer did not acknowledge offer: {}", e)))?;
+
+    connection.close(0u32.into(), b"done");
+    Ok(())
+}
@@ -0,0 +1,389 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use iroh::NodeId;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use crate::{error::Error, files, state::State, utils};
+
+pub const RULES_FILE_NAME: &str = "accept-rules.json";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    AutoAccept,
+    #[default]
+    Prompt,
+    Reject,
+}
+
+/// A single accept rule. Every condition that is set must match for the rule to apply.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Rule {
+    pub name: String,
+    /// Senders this rule applies to. Empty matches any sender, which auto-accept
+    /// rules aren't allowed to do.
+    #[serde(default)]
+    pub senders: Vec<NodeId>,
+    pub max_total_size: Option<u64>,
+    pub max_file_count: Option<usize>,
+    /// Allowed extensions (without the dot). Every file in the offer must match one.
+    /// Empty matches any extension.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    pub action: Action,
+    /// Where auto-accepted offers are downloaded to. Defaults to the regular download dir.
+    pub download_dir: Option<PathBuf>,
+}
+
+/// What the receiver knows about an offer before downloading any file.
+#[derive(Clone, Debug)]
+pub struct OfferSummary {
+    pub sender: NodeId,
+    pub total_size: u64,
+    pub file_count: usize,
+    pub extensions: Vec<String>,
+}
+
+impl OfferSummary {
+    pub fn new(sender: NodeId, files: &files::Files) -> Self {
+        let extensions = files
+            .values()
+            .map(|file| extension_of(&file.name))
+            .collect();
+
+        Self {
+            sender,
+            total_size: files.values().map(|file| file.size).sum(),
+            file_count: files.len(),
+            extensions,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Decision {
+    pub action: Action,
+    pub rule: Option<String>,
+    pub download_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Rules {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Used when no rule matches.
+    #[serde(default)]
+    pub default_action: Action,
+}
+
+impl Rule {
+    fn matches_sender(&self, sender: NodeId) -> bool {
+        self.senders.is_empty() || self.senders.contains(&sender)
+    }
+
+    /// Whether the rule looks at nothing but the sender.
+    fn is_sender_only(&self) -> bool {
+        self.max_total_size.is_none() && self.max_file_count.is_none() && self.extensions.is_empty()
+    }
+
+    fn matches(&self, offer: &OfferSummary) -> bool {
+        if !self.matches_sender(offer.sender) {
+            return false;
+        }
+
+        if self
+            .max_total_size
+            .is_some_and(|max| offer.total_size > max)
+        {
+            return false;
+        }
+
+        if self
+            .max_file_count
+            .is_some_and(|max| offer.file_count > max)
+        {
+            return false;
+        }
+
+        if !self.extensions.is_empty() {
+            let allowed = |ext: &String| {
+                self.extensions
+                    .iter()
+                    .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(ext))
+            };
+            if !offer.extensions.iter().all(allowed) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+impl Rule {
+    fn decision(&self) -> Decision {
+        Decision {
+            action: self.action,
+            rule: Some(self.name.clone()),
+            download_dir: self.download_dir.clone(),
+        }
+    }
+}
+
+impl Rules {
+    /// Returns the decision of the first matching rule, or the default action.
+    pub fn evaluate(&self, offer: &OfferSummary) -> Decision {
+        match self.rules.iter().find(|rule| rule.matches(offer)) {
+            Some(rule) => rule.decision(),
+            None => self.default_decision(),
+        }
+    }
+
+    /// The decision when it follows from the sender alone, so it can be made
+    /// before the offer's manifest is fetched.
+    pub fn evaluate_sender(&self, sender: NodeId) -> Option<Decision> {
+        match self.rules.iter().find(|rule| rule.matches_sender(sender)) {
+            Some(rule) if rule.is_sender_only() => Some(rule.decision()),
+            Some(_) => None,
+            None => Some(self.default_decision()),
+        }
+    }
+
+    fn default_decision(&self) -> Decision {
+        Decision {
+            action: self.default_action,
+            rule: None,
+            download_dir: None,
+        }
+    }
+
+    /// Anyone can send an offer, so auto-accept rules have to name their senders
+    /// and unknown senders can't be auto-accepted by default.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.default_action == Action::AutoAccept {
+            return Err(Error::InvalidInput {
+                what: "accept rule",
+                reason: "Offers from any sender can't be auto-accepted by default".to_string(),
+            });
+        }
+
+        let open = self
+            .rules
+            .iter()
+            .find(|rule| rule.action == Action::AutoAccept && rule.senders.is_empty());
+        match open {
+            Some(rule) => Err(Error::InvalidInput {
+                what: "accept rule",
+                reason: format!(
+                    "Rule {} auto-accepts offers without naming senders",
+                    rule.name
+                ),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn from_config(path: PathBuf) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read rules file: {}", e))?;
+
+        let rules: Rules = serde_json::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Failed to parse rules file: {}", e))?;
+        rules
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid rules file: {}", e))?;
+
+        Ok(rules)
+    }
+
+    pub fn save(&self, path: PathBuf) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize rules: {}", e))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, contents)
+            .map_err(|e| anyhow::anyhow!("Failed to write rules file: {}", e))?;
+
+        info!("Accept rules saved successfully");
+
+        Ok(())
+    }
+}
+
+fn extension_of(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub async fn get_accept_rules(state: State<'_>) -> Result<Rules, Error> {
+    Ok(state.accept_rules.lock().await.clone())
+}
+
+#[tauri::command]
+pub async fn set_accept_rules(state: State<'_>, rules: Rules, app: AppHandle) -> Result<(), Error> {
+    rules.validate()?;
+    let path = utils::get_config_dir(&app)?.join(RULES_FILE_NAME);
+
+    if let Err(e) = rules.save(path) {
+        return Err(format!("Failed to save accept rules: {}", e).into());
+    }
+
+    *state.accept_rules.lock().await = rules;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+
+    fn node(seed: u8) -> NodeId {
+        SecretKey::from_bytes(&[seed; 32]).public()
+    }
+
+    fn rule(name: &str, action: Action) -> Rule {
+        Rule {
+            name: name.to_string(),
+            senders: Vec::new(),
+            max_total_size: None,
+            max_file_count: None,
+            extensions: Vec::new(),
+            action,
+            download_dir: None,
+        }
+    }
+
+    fn offer(sender: NodeId, total_size: u64, extensions: &[&str]) -> OfferSummary {
+        OfferSummary {
+            sender,
+            total_size,
+            file_count: extensions.len(),
+            extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn conditions_must_all_match() {
+        let rule = Rule {
+            senders: vec![node(1)],
+            max_total_size: Some(100),
+            max_file_count: Some(2),
+            extensions: vec![".PDF".to_string(), "txt".to_string()],
+            ..rule("docs", Action::AutoAccept)
+        };
+
+        assert!(rule.matches(&offer(node(1), 100, &["pdf", "txt"])));
+        assert!(!rule.matches(&offer(node(2), 100, &["pdf"])));
+        assert!(!rule.matches(&offer(node(1), 101, &["pdf"])));
+        assert!(!rule.matches(&offer(node(1), 10, &["pdf", "pdf", "txt"])));
+        assert!(!rule.matches(&offer(node(1), 10, &["pdf", "exe"])));
+        assert!(!rule.matches(&offer(node(1), 10, &[""])));
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = Rules {
+            rules: vec![
+                Rule {
+                    max_total_size: Some(10),
+                    ..rule("small", Action::Prompt)
+                },
+                rule("rest", Action::Reject),
+            ],
+            default_action: Action::Prompt,
+        };
+
+        let small = rules.evaluate(&offer(node(1), 5, &["txt"]));
+        assert_eq!(small.action, Action::Prompt);
+        assert_eq!(small.rule.as_deref(), Some("small"));
+
+        let large = rules.evaluate(&offer(node(1), 50, &["txt"]));
+        assert_eq!(large.action, Action::Reject);
+        assert_eq!(large.rule.as_deref(), Some("rest"));
+    }
+
+    #[test]
+    fn default_cannot_auto_accept() {
+        let mut rules = Rules {
+            rules: vec![Rule {
+                senders: vec![node(1)],
+                ..rule("friend", Action::AutoAccept)
+            }],
+            default_action: Action::AutoAccept,
+        };
+        assert!(rules.validate().is_err());
+
+        rules.default_action = Action::Prompt;
+        assert!(rules.validate().is_ok());
+    }
+
+    #[test]
+    fn default_applies_without_match() {
+        let rules = Rules {
+            rules: vec![Rule {
+                senders: vec![node(1)],
+                ..rule("friend", Action::AutoAccept)
+            }],
+            default_action: Action::Reject,
+        };
+
+        let decision = rules.evaluate(&offer(node(2), 1, &["txt"]));
+        assert_eq!(decision.action, Action::Reject);
+        assert_eq!(decision.rule, None);
+    }
+
+    #[test]
+    fn sender_decides_only_when_nothing_else_can() {
+        let rules = Rules {
+            rules: vec![
+                Rule {
+                    senders: vec![node(1)],
+                    max_total_size: Some(10),
+                    ..rule("friend", Action::AutoAccept)
+                },
+                Rule {
+                    senders: vec![node(2)],
+                    ..rule("blocked", Action::Reject)
+                },
+            ],
+            default_action: Action::Reject,
+        };
+
+        assert!(rules.evaluate_sender(node(1)).is_none());
+
+        let blocked = rules.evaluate_sender(node(2)).unwrap();
+        assert_eq!(blocked.rule.as_deref(), Some("blocked"));
+
+        let stranger = rules.evaluate_sender(node(3)).unwrap();
+        assert_eq!(stranger.action, Action::Reject);
+        assert_eq!(stranger.rule, None);
+    }
+
+    #[test]
+    fn auto_accept_needs_senders() {
+        let mut rules = Rules {
+            rules: vec![rule("anyone", Action::AutoAccept)],
+            default_action: Action::Prompt,
+        };
+        assert!(rules.validate().is_err());
+
+        rules.rules[0].senders.push(node(1));
+        assert!(rules.validate().is_ok());
+
+        rules.rules.push(rule("anyone else", Action::Reject));
+        assert!(rules.validate().is_ok());
+    }
+}
@@ -0,0 +1,227 @@
+//! Progress of transfers as reported to the frontend.
+//!
+//! Speeds are smoothed over a few seconds, a single slow or fast throttle
+//! window barely moves them. Byte counts stay integers, the frontend works out
+//! percentages from them.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// How quickly the speed follows changes, about 63% of a change shows after this long.
+const SMOOTHING: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Progress {
+    pub done: u64,
+    pub total: u64,
+    /// Smoothed speed in bytes per second.
+    pub speed: f64,
+    /// Seconds left at the current speed, unknown while nothing is moving.
+    pub eta: Option<u64>,
+}
+
+impl Progress {
+    fn new(done: u64, total: u64, speed: f64) -> Self {
+        let left = total.saturating_sub(done);
+        let eta = match left {
+            0 => Some(0),
+            _ if speed >= 1.0 => Some((left as f64 / speed).ceil() as u64),
+            _ => None,
+        };
+        Self {
+            done,
+            total,
+            speed,
+            eta,
+        }
+    }
+}
+
+/// Turns byte counts reported at irregular intervals into [`Progress`].
+#[derive(Debug, Default)]
+pub struct Meter {
+    last: Option<(Instant, u64)>,
+    speed: f64,
+}
+
+impl Meter {
+    pub fn update(&mut self, done: u64, total: u64) -> Progress {
+        self.update_at(Instant::now(), done, total)
+    }
+
+    fn update_at(&mut self, now: Instant, done: u64, total: u64) -> Progress {
+        if let Some((at, before)) = self.last {
+            let elapsed = now.duration_since(at).as_secs_f64();
+            if elapsed > 0.0 {
+                let sample = done.saturating_sub(before) as f64 / elapsed;
+                // Weighted by time, so frequent updates don't smooth more than rare ones
+                let weight = 1.0 - (-elapsed / SMOOTHING.as_secs_f64()).exp();
+                self.speed += weight * (sample - self.speed);
+            }
+        }
+        self.last = Some((now, done));
+
+        Progress::new(done, total, self.speed)
+    }
+}
+
+/// All files of one download, summed up.
+#[derive(Debug, Default)]
+pub struct Transfer {
+    files: HashMap<String, (u64, u64)>,
+    meter: Meter,
+}
+
+impl Transfer {
+    /// Count `name` with its expected size before any of it arrives.
+    pub fn add(&mut self, name: &str, total: u64) {
+        self.files.insert(name.to_string(), (0, total));
+    }
+
+    /// Drop a file that won't finish, so the totals can still get there.
+    pub fn remove(&mut self, name: &str) -> TransferProgress {
+        self.files.remove(name);
+        self.progress()
+    }
+
+    pub fn update(&mut self, name: &str, done: u64, total: u64) -> TransferProgress {
+        self.files.insert(name.to_string(), (done, total));
+        self.progress()
+    }
+
+    pub fn finish(&mut self, name: &str) -> TransferProgress {
+        if let Some((done, total)) = self.files.get_mut(name) {
+            *done = *total;
+        }
+        self.progress()
+    }
+
+    fn progress(&mut self) -> TransferProgress {
+        let (done, total) = self
+            .files
+            .values()
+            .fold((0, 0), |(done, total), (d, t)| (done + d, total + t));
+        let files_done = self
+            .files
+            .values()
+            .filter(|(done, total)| done >= total)
+            .count();
+
+        TransferProgress {
+            progress: self.meter.update(done, total),
+            files_done,
+            files_total: self.files.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TransferProgress {
+    pub progress: Progress,
+    pub files_done: usize,
+    pub files_total: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_needs_a_speed() {
+        assert_eq!(Progress::new(0, 100, 0.0).eta, None);
+        assert_eq!(Progress::new(0, 100, 10.0).eta, Some(10));
+        assert_eq!(Progress::new(0, 100, 30.0).eta, Some(4));
+        // Nothing left is done, whatever the speed
+        assert_eq!(Progress::new(100, 100, 0.0).eta, Some(0));
+        assert_eq!(Progress::new(150, 100, 0.0).eta, Some(0));
+    }
+
+    #[test]
+    fn zero_elapsed_time_keeps_the_speed() {
+        let mut meter = Meter::default();
+        let start = Instant::now();
+
+        meter.update_at(start, 0, 1000);
+        let progress = meter.update_at(start, 500, 1000);
+        assert_eq!(progress.done, 500);
+        assert_eq!(progress.speed, 0.0);
+        assert_eq!(progress.eta, None);
+    }
+
+    #[test]
+    fn speed_follows_a_steady_rate() {
+        let mut meter = Meter::default();
+        let start = Instant::now();
+
+        let mut progress = meter.update_at(start, 0, 100_000);
+        for second in 1..=20 {
+            progress = meter.update_at(start + Duration::from_secs(second), second * 1000, 100_000);
+        }
+        assert!((progress.speed - 1000.0).abs() < 1.0, "{}", progress.speed);
+        assert!(matches!(progress.eta, Some(80..=81)), "{:?}", progress.eta);
+    }
+
+    #[test]
+    fn decreasing_counts_slow_down_without_going_negative() {
+        let mut meter = Meter::default();
+        let start = Instant::now();
+
+        meter.update_at(start, 0, 10_000);
+        let before = meter.update_at(start + Duration::from_secs(1), 5000, 10_000);
+        // A retry starts the file over
+        let after = meter.update_at(start + Duration::from_secs(2), 1000, 10_000);
+
+        assert_eq!(after.done, 1000);
+        assert!(after.speed >= 0.0);
+        assert!(after.speed < before.speed);
+    }
+
+    #[test]
+    fn transfer_sums_its_files() {
+        let mut transfer = Transfer::default();
+        transfer.add("a", 100);
+        transfer.add("b", 300);
+
+        let progress = transfer.update("a", 50, 100);
+        assert_eq!((progress.progress.done, progress.progress.total), (50, 400));
+        assert_eq!((progress.files_done, progress.files_total), (0, 2));
+
+        let progress = transfer.finish("a");
+        assert_eq!(progress.progress.done, 100);
+        assert_eq!(progress.files_done, 1);
+
+        // A file that fails no longer holds back the totals
+        let progress = transfer.remove("b");
+        assert_eq!(
+            (progress.progress.done, progress.progress.total),
+            (100, 100)
+        );
+        assert_eq!((progress.files_done, progress.files_total), (1, 1));
+    }
+
+    #[test]
+    fn transfer_follows_a_retried_file_back() {
+        let mut transfer = Transfer::default();
+        transfer.add("a", 1000);
+
+        transfer.update("a", 800, 1000);
+        let progress = transfer.update("a", 200, 1000);
+        assert_eq!(progress.progress.done, 200);
+        assert_eq!(progress.files_done, 0);
+        assert!(progress.progress.speed >= 0.0);
+    }
+
+    #[test]
+    fn finishing_an_unknown_file_changes_nothing() {
+        let mut transfer = Transfer::default();
+        transfer.add("a", 100);
+
+        let progress = transfer.finish("b");
+        assert_eq!((progress.progress.done, progress.progress.total), (0, 100));
+        assert_eq!((progress.files_done, progress.files_total), (0, 1));
+    }
+}
@@ -0,0 +1,454 @@
+//! Publishing revisions of the current share.
+//!
+//! Each time the share changes in a way receivers should follow, it is
+//! published as a new collection with a higher revision. The revision is
+//! announced on the node's gossip topic and, for a named share, signed into
+//! the share's pointer so its stable ticket resolves to the new collection.
+//!
+//! The share is saved whenever it changes and restored on the next start.
+//! Together with the node's persistent key this keeps handed out tickets working
+//! across restarts. A named share's key is derived from the node's key and a
+//! random seed, so the saved share holds nothing secret.
+
+use std::str::FromStr;
+
+use iroh::{NodeId, SecretKey};
+use iroh_blobs::{ticket::BlobTicket, BlobFormat, Hash};
+use iroh_gossip::proto::TopicId;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Mutex;
+
+use crate::{
+    error::Error,
+    events,
+    folder::{self, LiveFolder},
+    state::{
+        share_data::{SavedFile, SavedNamedShare, SavedShare, SHARE_FILE_NAME},
+        AppState, State,
+    },
+    storage, ticket, utils,
+};
+use pointer::{NamedTicket, Record};
+
+pub mod pointer;
+pub mod registry;
+
+// Serializes saves so an older snapshot never overwrites a newer one.
+static SAVE_LOCK: Mutex<()> = Mutex::const_new(());
+
+const ANNOUNCEMENT_CONTEXT: &[u8] = b"sendit/announcement/0";
+const NAMED_KEY_CONTEXT: &str = "sendit 2025-01-01 named share key";
+
+/// Messages on a sender's gossip topic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ShareMessage {
+    /// A new revision of the share was published. Signed by the sender's node
+    /// key over the topic, revision and collection, so peers on the topic can't
+    /// forge one or replay it on another of the sender's topics.
+    Updated {
+        revision: u64,
+        ticket: String,
+        signature: Vec<u8>,
+    },
+}
+
+impl ShareMessage {
+    pub fn updated(secret: &SecretKey, topic: TopicId, revision: u64, ticket: &BlobTicket) -> Self {
+        let message = Self::announcement(&topic, revision, ticket.hash());
+        Self::Updated {
+            revision,
+            ticket: ticket.to_string(),
+            signature: secret.sign(&message).to_bytes().to_vec(),
+        }
+    }
+
+    /// The revision and ticket of an update `sender` signed for `topic`.
+    pub fn verify(&self, sender: NodeId, topic: TopicId) -> Result<(u64, BlobTicket), String> {
+        let Self::Updated {
+            revision,
+            ticket,
+            signature,
+        } = self;
+
+        let ticket = BlobTicket::from_str(ticket).map_err(|e| format!("Invalid ticket: {}", e))?;
+        // The collection must be served by the sender itself
+        if ticket.node_addr().node_id != sender {
+            return Err("Update not served by the sender".to_string());
+        }
+
+        let signature = ed25519_dalek::Signature::from_slice(signature)
+            .map_err(|e| format!("Invalid update signature: {}", e))?;
+        sender
+            .verify(
+                &Self::announcement(&topic, *revision, ticket.hash()),
+                &signature,
+            )
+            .map_err(|e| format!("Invalid update signature: {}", e))?;
+
+        Ok((*revision, ticket))
+    }
+
+    fn announcement(topic: &TopicId, revision: u64, root: Hash) -> Vec<u8> {
+        let mut message = ANNOUNCEMENT_CONTEXT.to_vec();
+        message.extend_from_slice(topic.as_bytes());
+        message.extend_from_slice(&revision.to_be_bytes());
+        message.extend_from_slice(root.as_bytes());
+        message
+    }
+}
+
+#[derive(Debug)]
+pub struct NamedShare {
+    pub name: String,
+    seed: [u8; 32],
+    secret: SecretKey,
+}
+
+impl NamedShare {
+    fn new(state: &AppState, name: String, seed: [u8; 32]) -> Self {
+        Self {
+            name,
+            seed,
+            secret: named_secret(state.iroh().endpoint().secret_key(), &seed),
+        }
+    }
+}
+
+/// The key of a named share, derived from the node's key and the share's seed so
+/// only the non-secret seed is saved with the share.
+fn named_secret(node: &SecretKey, seed: &[u8; 32]) -> SecretKey {
+    let mut material = node.to_bytes().to_vec();
+    material.extend_from_slice(seed);
+    SecretKey::from_bytes(&blake3::derive_key(NAMED_KEY_CONTEXT, &material))
+}
+
+#[derive(Debug, Default)]
+pub struct Publisher {
+    revision: u64,
+    named: Option<NamedShare>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NamedShareInfo {
+    pub name: String,
+    pub ticket: String,
+    pub revision: u64,
+}
+
+/// Publish the current files as the next revision of the share.
+pub async fn publish(handle: &AppHandle) -> Result<(BlobTicket, u64), String> {
+    let state = handle.state::<AppState>();
+
+    // Held while publishing so revisions are handed out in order
+    let mut publisher = state.publisher.lock().await;
+    let ticket = ticket::create_header_ticket(&state).await?;
+    publisher.revision += 1;
+    let revision = publisher.revision;
+
+    if let Some(named) = &publisher.named {
+        let record = Record::sign(&named.secret, revision, ticket.hash());
+        state
+            .iroh()
+            .pointers
+            .write()
+            .unwrap()
+            .insert(record.key, record);
+    }
+    drop(publisher);
+
+    let gossip = &state.iroh().gossip;
+    let secret = state.iroh().endpoint().secret_key();
+    let msg = ShareMessage::updated(secret, gossip.ticket().topic_id, revision, &ticket);
+    let bytes = serde_json::to_vec(&msg).expect("Infallible");
+    let channel = gossip.channel();
+    // Our topic has no bootstrap peers, bring in the receivers so the announcement
+    // reaches them even if none joined through us since the last restart
+    let receivers = state
+        .uploads
+        .lock()
+        .await
+        .receivers(storage::OUTGOING_SHARE);
+    if !receivers.is_empty() {
+        if let Err(e) = channel.join_peers(receivers).await {
+            warn!("Failed to join receivers: {}", e);
+        }
+    }
+    if let Err(e) = channel.broadcast(bytes.into()).await {
+        warn!("Failed to announce revision {}: {}", revision, e);
+    }
+
+    let payload = events::ShareUpdated {
+        revision,
+        ticket: ticket.to_string(),
+    };
+    handle.emit(events::SHARE_UPDATED, payload).ok();
+    save(handle);
+
+    Ok((ticket, revision))
+}
+
+/// Publish a new revision if the files are shared under a named ticket.
+pub async fn republish_named(handle: &AppHandle) {
+    let state = handle.state::<AppState>();
+    if state.publisher.lock().await.named.is_none() {
+        return;
+    }
+    if let Err(e) = publish(handle).await {
+        warn!("Failed to update named share: {}", e);
+    }
+}
+
+fn named_ticket(state: &AppState, named: &NamedShare) -> NamedTicket {
+    NamedTicket {
+        key: named.secret.public(),
+        addr: state.iroh().node_addr.clone(),
+    }
+}
+
+/// Share the current files under a ticket that stays the same as they change.
+#[tauri::command]
+pub async fn create_named_share(name: String, handle: AppHandle) -> Result<String, Error> {
+    let state = handle.state::<AppState>();
+    {
+        let mut publisher = state.publisher.lock().await;
+        if publisher.named.is_some() {
+            return Err(Error::InvalidInput {
+                what: "share",
+                reason: "The files are already shared under a name".to_string(),
+            });
+        }
+        info!("Creating named share: {}", name);
+        publisher.named = Some(NamedShare::new(&state, name, rand::random()));
+    }
+
+    publish(&handle).await?;
+
+    let publisher = state.publisher.lock().await;
+    let named = publisher.named.as_ref().ok_or("Named share was closed")?;
+    Ok(named_ticket(&state, named).to_string())
+}
+
+#[tauri::command]
+pub async fn get_named_share(state: State<'_>) -> Result<Option<NamedShareInfo>, Error> {
+    let publisher = state.publisher.lock().await;
+    Ok(publisher.named.as_ref().map(|named| NamedShareInfo {
+        name: named.name.clone(),
+        ticket: named_ticket(&state, named).to_string(),
+        revision: publisher.revision,
+    }))
+}
+
+/// Stop answering for the named share. Its ticket no longer resolves afterwards.
+#[tauri::command]
+pub async fn close_named_share(state: State<'_>, handle: AppHandle) -> Result<(), Error> {
+    let Some(named) = state.publisher.lock().await.named.take() else {
+        return Ok(());
+    };
+    info!("Closed named share: {}", named.name);
+
+    state
+        .iroh()
+        .pointers
+        .write()
+        .unwrap()
+        .remove(&named.secret.public());
+    save(&handle);
+    Ok(())
+}
+
+/// Save the share in the background, once the caller released its locks.
+pub fn save(handle: &AppHandle) {
+    let handle = handle.clone();
+    tokio::spawn(async move {
+        if let Err(e) = write_saved(&handle).await {
+            error!("Failed to save share: {}", e);
+        }
+    });
+}
+
+async fn write_saved(handle: &AppHandle) -> Result<(), String> {
+    let _guard = SAVE_LOCK.lock().await;
+    let state = handle.state::<AppState>();
+
+    // Taken first, a folder sync in progress updates the tickets below
+    let folder = state
+        .live_folder
+        .lock()
+        .await
+        .as_ref()
+        .map(LiveFolder::saved);
+
+    // Only files imported in place are saved, live folder files come back with the folder
+    let sources = state.sources.lock().await.sources().await;
+    let (files, snippets) = {
+        let files = state.files().await;
+        let saved = sources
+            .into_iter()
+            .filter_map(|source| {
+                Some(SavedFile {
+                    file: files.get(&source.name)?.clone(),
+                    path: source.path,
+                    stamp: source.stamp,
+                })
+            })
+            .collect();
+        let snippets = files
+            .values()
+            .filter(|file| !file.kind.is_file())
+            .cloned()
+            .collect();
+        (saved, snippets)
+    };
+
+    let tickets = state
+        .header_tickets
+        .lock()
+        .await
+        .iter()
+        .map(|ticket| ticket.hash())
+        .collect();
+
+    let (revision, named) = {
+        let publisher = state.publisher.lock().await;
+        let named = publisher.named.as_ref().map(|named| {
+            let key = named.secret.public();
+            SavedNamedShare {
+                name: named.name.clone(),
+                seed: named.seed,
+                root: state
+                    .iroh()
+                    .pointers
+                    .read()
+                    .unwrap()
+                    .get(&key)
+                    .map(|r| r.root),
+            }
+        });
+        (publisher.revision, named)
+    };
+
+    let shares = state
+        .shares
+        .lock()
+        .await
+        .iter()
+        .map(|(id, share)| share.saved(id))
+        .collect();
+
+    let saved = SavedShare {
+        files,
+        snippets,
+        tickets,
+        revision,
+        named,
+        folder,
+        shares,
+    };
+    let path = utils::get_config_dir(handle)?.join(SHARE_FILE_NAME);
+    saved.save(path).map_err(|e| e.to_string())
+}
+
+/// Restore the share saved before the last exit.
+pub async fn restore(handle: &AppHandle) {
+    let saved = match utils::get_config_dir(handle) {
+        Ok(dir) => match SavedShare::from_config(dir.join(SHARE_FILE_NAME)) {
+            Ok(saved) => saved,
+            // Nothing was shared yet
+            Err(_) => return,
+        },
+        Err(e) => {
+            error!("Failed to restore share: {}", e);
+            return;
+        }
+    };
+
+    let state = handle.state::<AppState>();
+    let blobs = &state.iroh().blobs;
+
+    let mut restored = 0;
+    for SavedFile { file, path, stamp } in saved.files {
+        // The source may have changed while nobody was watching it
+        let watched = state
+            .sources
+            .lock()
+            .await
+            .watch(path, file.name.clone(), stamp)
+            .await;
+        if let Err(e) = watched {
+            warn!("Dropped {} from the share: {}", file.name, e);
+            if let Err(e) = storage::release_share_file(blobs, &file.name).await {
+                error!("{}", e);
+            }
+            continue;
+        }
+        state.files().await.add_file(file);
+        restored += 1;
+    }
+    for snippet in saved.snippets {
+        state.files().await.add_file(snippet);
+        restored += 1;
+    }
+
+    // The node id is stable, so tickets for the same roots match the ones handed out
+    {
+        let mut tickets = state.header_tickets.lock().await;
+        for root in saved.tickets {
+            match BlobTicket::new(state.iroh().node_addr.clone(), root, BlobFormat::HashSeq) {
+                Ok(ticket) => tickets.push(ticket),
+                Err(e) => warn!("Failed to restore ticket {}: {}", root, e),
+            }
+        }
+    }
+
+    {
+        let mut publisher = state.publisher.lock().await;
+        publisher.revision = saved.revision;
+        if let Some(named) = saved.named {
+            let restored = NamedShare::new(&state, named.name, named.seed);
+            if let Some(root) = named.root {
+                let record = Record::sign(&restored.secret, saved.revision, root);
+                state
+                    .iroh()
+                    .pointers
+                    .write()
+                    .unwrap()
+                    .insert(record.key, record);
+            }
+            info!("Restored named share: {}", restored.name);
+            publisher.named = Some(restored);
+        }
+    }
+    info!("Restored {} shared files", restored);
+    registry::restore(handle, saved.shares).await;
+
+    // Importing the folder again can take a while, don't hold up startup
+    if let Some(saved_folder) = saved.folder {
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            let published = saved_folder.published.clone();
+            if let Err(e) = folder::resume(&handle, saved_folder).await {
+                error!("Failed to resume folder share: {}", e);
+
+                // Without a live folder nothing releases its revisions anymore
+                let state = handle.state::<AppState>();
+                if state.live_folder.lock().await.is_some() {
+                    return;
+                }
+                for root in published {
+                    if let Err(e) = storage::delete_header_tags(&state.iroh().blobs, root).await {
+                        error!("{}", e);
+                    }
+                    state
+                        .header_tickets
+                        .lock()
+                        .await
+                        .retain(|t| t.hash() != root);
+                }
+                save(&handle);
+            }
+        });
+    }
+}
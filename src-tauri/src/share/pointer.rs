@@ -0,0 +1,178 @@
+//! Signed pointers behind named share tickets.
+//!
+//! A named share has its own key pair. The sender signs a [`Record`] pointing
+//! at the share's latest collection and serves it on [`ALPN`]. The ticket only
+//! holds the public key and the sender's address, so it stays the same while
+//! the share changes. Receivers verify the signature before following the pointer.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use iroh::{endpoint::Connection, protocol::ProtocolHandler, Endpoint, NodeAddr, PublicKey};
+use iroh_blobs::Hash;
+use n0_future::boxed::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+pub const ALPN: &[u8] = b"sendit/pointer/0";
+
+const TICKET_PREFIX: &str = "share";
+const SIGNATURE_CONTEXT: &[u8] = b"sendit/pointer/0";
+const MAX_RECORD_SIZE: usize = 1024;
+
+/// The latest revision of a named share, signed by the share's key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub key: PublicKey,
+    pub revision: u64,
+    pub root: Hash,
+    signature: Vec<u8>,
+}
+
+impl Record {
+    pub fn sign(secret: &iroh::SecretKey, revision: u64, root: Hash) -> Self {
+        let key = secret.public();
+        let signature = secret.sign(&Self::message(&key, revision, root));
+        Self {
+            key,
+            revision,
+            root,
+            signature: signature.to_bytes().to_vec(),
+        }
+    }
+
+    pub fn verify(&self) -> Result<(), String> {
+        let signature = ed25519_dalek::Signature::from_slice(&self.signature)
+            .map_err(|e| format!("Invalid share signature: {}", e))?;
+        self.key
+            .verify(
+                &Self::message(&self.key, self.revision, self.root),
+                &signature,
+            )
+            .map_err(|e| format!("Invalid share signature: {}", e))
+    }
+
+    fn message(key: &PublicKey, revision: u64, root: Hash) -> Vec<u8> {
+        let mut message = SIGNATURE_CONTEXT.to_vec();
+        message.extend_from_slice(key.as_bytes());
+        message.extend_from_slice(&revision.to_be_bytes());
+        message.extend_from_slice(root.as_bytes());
+        message
+    }
+}
+
+/// Records served by this node, shared between the app and the protocol handler.
+pub type PointerStore = Arc<RwLock<HashMap<PublicKey, Record>>>;
+
+/// Answers pointer requests on [`ALPN`] from the [`PointerStore`].
+#[derive(Debug, Clone)]
+pub struct PointerProtocol {
+    store: PointerStore,
+}
+
+impl PointerProtocol {
+    pub fn new(store: PointerStore) -> Self {
+        Self { store }
+    }
+}
+
+impl ProtocolHandler for PointerProtocol {
+    fn accept(&self, connection: Connection) -> BoxFuture<Result<()>> {
+        let store = self.store.clone();
+        Box::pin(async move {
+            let (mut send, mut recv) = connection.accept_bi().await?;
+
+            let bytes: [u8; 32] = recv.read_to_end(32).await?.as_slice().try_into()?;
+            let key = PublicKey::from_bytes(&bytes)?;
+
+            // An empty answer tells the receiver the share was closed
+            let record = store.read().unwrap().get(&key).cloned();
+            if let Some(record) = record {
+                send.write_all(&serde_json::to_vec(&record)?).await?;
+            }
+            send.finish()?;
+
+            connection.closed().await;
+            Ok(())
+        })
+    }
+}
+
+/// A stable ticket for a named share.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedTicket {
+    pub key: PublicKey,
+    pub addr: NodeAddr,
+}
+
+impl fmt::Display for NamedTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = serde_json::to_vec(self).expect("Infallible");
+        let mut text = data_encoding::BASE32_NOPAD.encode(&bytes);
+        text.make_ascii_lowercase();
+        write!(f, "{TICKET_PREFIX}{text}")
+    }
+}
+
+impl FromStr for NamedTicket {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s
+            .strip_prefix(TICKET_PREFIX)
+            .ok_or("Not a named share ticket")?;
+        let bytes = data_encoding::BASE32_NOPAD
+            .decode(text.to_ascii_uppercase().as_bytes())
+            .map_err(|e| format!("Invalid share ticket: {}", e))?;
+        serde_json::from_slice(&bytes).map_err(|e| format!("Invalid share ticket: {}", e))
+    }
+}
+
+/// Ask the sender for the latest record of `ticket` and verify it.
+pub async fn resolve(endpoint: &Endpoint, ticket: &NamedTicket) -> Result<Record, Error> {
+    let failed = |message: String| Error::Connection { message };
+    let invalid = |message: String| Error::Protocol { message };
+    let connection = endpoint
+        .connect(ticket.addr.clone(), ALPN)
+        .await
+        .map_err(|e| failed(format!("Failed to connect to peer: {}", e)))?;
+
+    let (mut send, mut recv) = connection
+        .open_bi()
+        .await
+        .map_err(|e| failed(format!("Failed to open stream: {}", e)))?;
+    send.write_all(ticket.key.as_bytes())
+        .await
+        .map_err(|e| failed(format!("Failed to request share: {}", e)))?;
+    send.finish()
+        .map_err(|e| failed(format!("Failed to request share: {}", e)))?;
+
+    let bytes = recv
+        .read_to_end(MAX_RECORD_SIZE)
+        .await
+        .map_err(|e| failed(format!("Failed to read share: {}", e)))?;
+    connection.close(0u32.into(), b"done");
+
+    if bytes.is_empty() {
+        return Err(Error::NotFound {
+            what: "named share",
+            id: ticket.to_string(),
+        });
+    }
+    let record: Record = serde_json::from_slice(&bytes)
+        .map_err(|e| invalid(format!("Invalid share record: {}", e)))?;
+    if record.key != ticket.key {
+        return Err(invalid(
+            "The sender answered for a different share".to_string(),
+        ));
+    }
+    record.verify().map_err(invalid)?;
+
+    Ok(record)
+}
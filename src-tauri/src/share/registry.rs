@@ -0,0 +1,554 @@
+//! Independent shares.
+//!
+//! Besides the files on the send page, any number of shares can be open at the
+//! same time, each with its own files, gossip topic, tickets and access rules.
+//! Their files are copied into the store and tagged under `sendit/shares/<id>/`,
+//! so closing a share releases exactly its blobs.
+//!
+//! Blob hashes act as capabilities, anyone holding a ticket can fetch the share.
+//! A share with recipients is therefore only offered to them and its ticket is
+//! never handed out to be copied. Its blobs are also only served to them, see
+//! [`uploads::may_fetch`](crate::uploads::may_fetch).
+
+use std::{
+    collections::HashSet,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use iroh::NodeId;
+use iroh_blobs::{ticket::BlobTicket, BlobFormat, Hash};
+use iroh_gossip::net::GossipSender;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::{sync::Mutex, task::JoinHandle, time};
+
+use super::ShareMessage;
+use crate::{
+    error::Error,
+    events, file_operations,
+    files::{self, Files},
+    iroh::{share_topic, GossipChannel, GossipTicket},
+    offer,
+    state::{share_data::SavedRegistryShare, AppState, State},
+    storage, ticket, utils,
+};
+
+// Serializes publishes, so revisions are numbered in the order their files were read.
+static PUBLISH_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShareAccess {
+    /// Nodes the share is offered to. While set, its ticket can't be copied.
+    #[serde(default)]
+    pub recipients: Vec<NodeId>,
+    /// Unix timestamp in seconds at which the share is closed.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+/// An open share, closed for good when removed from the registry.
+#[derive(Debug)]
+pub struct Share {
+    name: String,
+    files: Files,
+    access: ShareAccess,
+    revision: u64,
+    /// Every ticket handed out, kept working until the share is closed.
+    tickets: Vec<BlobTicket>,
+    created_at: u64,
+    channel: GossipChannel,
+    expiry: Option<JoinHandle<()>>,
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        if let Some(task) = &self.expiry {
+            task.abort();
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareInfo {
+    pub id: String,
+    pub name: String,
+    pub files: Vec<String>,
+    pub size: u64,
+    pub revision: u64,
+    pub access: ShareAccess,
+    pub created_at: u64,
+    /// Latest ticket, unless the share is limited to its recipients.
+    pub ticket: Option<String>,
+}
+
+impl Share {
+    fn info(&self, id: &str) -> ShareInfo {
+        let mut files = self.files.keys().cloned().collect::<Vec<_>>();
+        files.sort();
+
+        ShareInfo {
+            id: id.to_string(),
+            name: self.name.clone(),
+            files,
+            size: self.files.values().map(|file| file.size).sum(),
+            revision: self.revision,
+            access: self.access.clone(),
+            created_at: self.created_at,
+            ticket: match self.access.recipients.is_empty() {
+                true => self.tickets.last().map(|ticket| ticket.to_string()),
+                false => None,
+            },
+        }
+    }
+
+    /// Whether `node_id` may fetch the share, see [`ShareAccess::recipients`].
+    pub fn admits(&self, node_id: NodeId) -> bool {
+        self.access.recipients.is_empty() || self.access.recipients.contains(&node_id)
+    }
+
+    /// Whether `hash` is one of the share's collections, files or their icons and thumbnails.
+    pub fn serves(&self, hash: Hash) -> bool {
+        self.tickets.iter().any(|ticket| ticket.hash() == hash)
+            || self.files.values().any(|file| file.uses(hash))
+    }
+
+    pub fn file_hashes(&self) -> HashSet<Hash> {
+        self.files.values().map(|file| file.hash).collect()
+    }
+
+    pub fn saved(&self, id: &str) -> SavedRegistryShare {
+        SavedRegistryShare {
+            id: id.to_string(),
+            name: self.name.clone(),
+            files: self.files.values().cloned().collect(),
+            access: self.access.clone(),
+            revision: self.revision,
+            tickets: self.tickets.iter().map(|ticket| ticket.hash()).collect(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn schedule_expiry(handle: &AppHandle, id: &str, access: &ShareAccess) -> Option<JoinHandle<()>> {
+    let expires_at = access.expires_at?;
+    let handle = handle.clone();
+    let id = id.to_string();
+
+    Some(tokio::spawn(async move {
+        time::sleep(Duration::from_secs(expires_at.saturating_sub(now()))).await;
+
+        // Closing drops the share, which aborts this task
+        tokio::spawn(async move {
+            info!("Share {} expired", id);
+            if let Err(e) = close(&handle, &id).await {
+                error!("Failed to close expired share: {}", e);
+            }
+        });
+    }))
+}
+
+/// Join the share's gossip topic and start its expiry timer.
+fn open(handle: &AppHandle, id: &str, saved: SavedRegistryShare) -> Result<Share, String> {
+    let state = handle.state::<AppState>();
+    let node_id = state.iroh().node_addr.node_id;
+    let topic = share_topic(node_id, id);
+    let channel = state
+        .iroh()
+        .gossip
+        .join(topic)
+        .map_err(|e| format!("Failed to join share topic: {}", e))?;
+
+    let mut files = Files::new(GossipTicket::new(topic, node_id));
+    for file in saved.files {
+        files.add_file(file);
+    }
+
+    let mut tickets = Vec::with_capacity(saved.tickets.len());
+    for root in saved.tickets {
+        match BlobTicket::new(state.iroh().node_addr.clone(), root, BlobFormat::HashSeq) {
+            Ok(ticket) => tickets.push(ticket),
+            Err(e) => warn!("Failed to restore ticket {}: {}", root, e),
+        }
+    }
+
+    Ok(Share {
+        name: saved.name,
+        files,
+        expiry: schedule_expiry(handle, id, &saved.access),
+        access: saved.access,
+        revision: saved.revision,
+        tickets,
+        created_at: saved.created_at,
+        channel,
+    })
+}
+
+/// Publish the files of the registered share `id` as its next revision and
+/// announce it. The registry is only locked to read the files and to record the
+/// revision, uploads check it for every request.
+async fn publish(state: &AppState, id: &str) -> Result<ShareInfo, Error> {
+    let _publish = PUBLISH_LOCK.lock().await;
+    let (files, sender) = {
+        let shares = state.shares.lock().await;
+        let share = shares.get(id).ok_or_else(|| not_found(id))?;
+        (share.files.clone(), GossipSender::clone(&share.channel))
+    };
+
+    let ticket = store_revision(state, id, &files).await?;
+
+    let (revision, info) = {
+        let mut shares = state.shares.lock().await;
+        let Some(share) = shares.get_mut(id) else {
+            // Closed meanwhile, don't leave the new collection behind
+            drop(shares);
+            storage::delete_tags_with_prefix(&state.iroh().blobs, &storage::shares_prefix(id))
+                .await?;
+            return Err(not_found(id));
+        };
+        share.revision += 1;
+        share.tickets.push(ticket.clone());
+        (share.revision, share.info(id))
+    };
+    announce(state, id, &sender, &files, revision, &ticket).await;
+
+    Ok(info)
+}
+
+/// Store `files` as a collection of the share `id` and return its ticket.
+async fn store_revision(state: &AppState, id: &str, files: &Files) -> Result<BlobTicket, String> {
+    ticket::publish_collection(state, files, |manifest| {
+        storage::shares_header_tag(id, manifest)
+    })
+    .await
+}
+
+/// Tell the share's mirrors about `revision`.
+async fn announce(
+    state: &AppState,
+    id: &str,
+    sender: &GossipSender,
+    files: &Files,
+    revision: u64,
+    ticket: &BlobTicket,
+) {
+    let msg = ShareMessage::updated(
+        state.iroh().endpoint().secret_key(),
+        files.gossip_ticket().topic_id,
+        revision,
+        ticket,
+    );
+    let bytes = serde_json::to_vec(&msg).expect("Infallible");
+    if let Err(e) = sender.broadcast(bytes.into()).await {
+        warn!("Failed to announce revision {} of {}: {}", revision, id, e);
+    }
+}
+
+fn not_found(id: &str) -> Error {
+    Error::NotFound {
+        what: "share",
+        id: id.to_string(),
+    }
+}
+
+async fn close(handle: &AppHandle, id: &str) -> Result<(), Error> {
+    let state = handle.state::<AppState>();
+    let share = state
+        .shares
+        .lock()
+        .await
+        .remove(id)
+        .ok_or_else(|| not_found(id))?;
+    info!("Closed share {} ({})", share.name, id);
+    drop(share);
+
+    storage::delete_tags_with_prefix(&state.iroh().blobs, &storage::shares_prefix(id)).await?;
+
+    let payload = events::ShareClosed { id: id.to_string() };
+    handle.emit(events::SHARE_CLOSED, payload).ok();
+    super::save(handle);
+    Ok(())
+}
+
+/// Open the shares saved before the last exit. Expired ones are closed right away.
+pub async fn restore(handle: &AppHandle, saved: Vec<SavedRegistryShare>) {
+    let state = handle.state::<AppState>();
+    let mut shares = state.shares.lock().await;
+
+    for saved in saved {
+        let id = saved.id.clone();
+        match open(handle, &id, saved) {
+            Ok(share) => {
+                shares.insert(id, share);
+            }
+            Err(e) => error!("Failed to restore share {}: {}", id, e),
+        }
+    }
+    info!("Restored {} shares", shares.len());
+}
+
+#[tauri::command]
+pub async fn create_share(
+    name: String,
+    access: Option<ShareAccess>,
+    handle: AppHandle,
+) -> Result<ShareInfo, Error> {
+    let state = handle.state::<AppState>();
+    let id = data_encoding::HEXLOWER.encode(&rand::random::<[u8; 8]>());
+    info!("Creating share {} ({})", name, id);
+
+    let saved = SavedRegistryShare {
+        id: id.clone(),
+        name,
+        files: Vec::new(),
+        access: access.unwrap_or_default(),
+        revision: 0,
+        tickets: Vec::new(),
+        created_at: now(),
+    };
+    let mut share = open(&handle, &id, saved)?;
+
+    // The first revision is empty, so the share has a ticket from the start
+    let ticket = store_revision(&state, &id, &share.files).await?;
+    share.revision += 1;
+    share.tickets.push(ticket.clone());
+    announce(
+        &state,
+        &id,
+        &share.channel,
+        &share.files,
+        share.revision,
+        &ticket,
+    )
+    .await;
+    let info = share.info(&id);
+    state.shares.lock().await.insert(id, share);
+
+    super::save(&handle);
+    Ok(info)
+}
+
+#[tauri::command]
+pub async fn list_shares(state: State<'_>) -> Result<Vec<ShareInfo>, Error> {
+    let shares = state.shares.lock().await;
+    let mut infos = shares
+        .iter()
+        .map(|(id, share)| share.info(id))
+        .collect::<Vec<_>>();
+    infos.sort_by_key(|info| info.created_at);
+    Ok(infos)
+}
+
+/// Rename a share or replace its access rules.
+#[tauri::command]
+pub async fn update_share(
+    id: String,
+    name: Option<String>,
+    access: Option<ShareAccess>,
+    handle: AppHandle,
+) -> Result<ShareInfo, Error> {
+    let state = handle.state::<AppState>();
+    let mut shares = state.shares.lock().await;
+    let share = shares.get_mut(&id).ok_or_else(|| not_found(&id))?;
+
+    if let Some(name) = name {
+        share.name = name;
+    }
+    if let Some(access) = access {
+        if let Some(task) = share.expiry.take() {
+            task.abort();
+        }
+        share.expiry = schedule_expiry(&handle, &id, &access);
+        share.access = access;
+    }
+    let info = share.info(&id);
+    drop(shares);
+
+    super::save(&handle);
+    Ok(info)
+}
+
+async fn import(
+    state: &AppState,
+    id: &str,
+    original_path: &str,
+    path: &Path,
+    name: String,
+) -> Result<files::File, Error> {
+    info!("Adding {} to share {}", original_path, id);
+    let icon_tag = storage::shares_icon_tag(id, &name);
+    let (_, icon_hash) = file_operations::store_icon(state, original_path, icon_tag).await;
+    let thumbnail_tag = storage::shares_thumbnail_tag(id, &name);
+    let thumbnail_hash = file_operations::store_thumbnail(state, path, thumbnail_tag).await;
+    let tag = storage::shares_file_tag(id, &name);
+    let (hash, size, compression) =
+        file_operations::store_file_compressed(state, path, tag, false, |_| {}).await?;
+
+    Ok(files::File {
+        name,
+        icon: String::new(),
+        icon_hash,
+        size,
+        hash,
+        kind: files::ContentKind::File,
+        thumbnail_hash,
+        compression,
+    })
+}
+
+/// Delete the tags of the file `name` in a share, its blobs go with the next GC.
+async fn release(state: &AppState, id: &str, name: &str) {
+    let blobs = &state.iroh().blobs;
+    for tag in [
+        storage::shares_file_tag(id, name),
+        storage::shares_icon_tag(id, name),
+        storage::shares_thumbnail_tag(id, name),
+    ] {
+        if let Err(e) = storage::delete_tag(blobs, tag).await {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Add files to a share and publish its next revision.
+#[tauri::command]
+pub async fn add_share_files(
+    id: String,
+    paths: Vec<String>,
+    handle: AppHandle,
+) -> Result<ShareInfo, Error> {
+    let state = handle.state::<AppState>();
+
+    let mut names = {
+        let shares = state.shares.lock().await;
+        let share = shares.get(&id).ok_or_else(|| not_found(&id))?;
+        share.files.keys().cloned().collect::<HashSet<_>>()
+    };
+
+    // Check every path up front so a rejected one doesn't leave a partial import behind
+    let mut sources = Vec::with_capacity(paths.len());
+    for original_path in &paths {
+        let path = utils::validate_file_path(original_path)?;
+        let name = utils::file_name_from_path(&path)?;
+        if !names.insert(name.clone()) {
+            return Err(Error::DuplicateName { name });
+        }
+        sources.push((original_path, path, name));
+    }
+
+    // Imported without holding the registry, large files take a while
+    let mut added = Vec::with_capacity(sources.len());
+    for (original_path, path, name) in sources {
+        match import(&state, &id, original_path, &path, name).await {
+            Ok(file) => added.push(file),
+            Err(error) => {
+                for file in added {
+                    release(&state, &id, &file.name).await;
+                }
+                return Err(error);
+            }
+        }
+    }
+
+    let mut shares = state.shares.lock().await;
+    let Some(share) = shares.get_mut(&id) else {
+        drop(shares);
+        storage::delete_tags_with_prefix(&state.iroh().blobs, &storage::shares_prefix(&id)).await?;
+        return Err(not_found(&id));
+    };
+    // Another call may have added the same name meanwhile, its tags are left alone
+    if let Some(file) = added.iter().find(|file| share.files.has_file(&file.name)) {
+        let name = file.name.clone();
+        drop(shares);
+        for file in added.iter().filter(|file| file.name != name) {
+            release(&state, &id, &file.name).await;
+        }
+        return Err(Error::DuplicateName { name });
+    }
+    for file in added {
+        share.files.add_file(file);
+    }
+    drop(shares);
+
+    let info = publish(&state, &id).await?;
+
+    super::save(&handle);
+    Ok(info)
+}
+
+/// Remove a file from a share and publish its next revision. Tickets of earlier
+/// revisions still include it.
+#[tauri::command]
+pub async fn remove_share_file(
+    id: String,
+    name: String,
+    handle: AppHandle,
+) -> Result<ShareInfo, Error> {
+    let state = handle.state::<AppState>();
+    let mut shares = state.shares.lock().await;
+    let share = shares.get_mut(&id).ok_or_else(|| not_found(&id))?;
+
+    if !share.files.has_file(&name) {
+        return Err(Error::NotFound {
+            what: "file",
+            id: name,
+        });
+    }
+    info!("Removing {} from share {}", name, id);
+    share.files.remove_file(&name);
+    drop(shares);
+
+    release(&state, &id, &name).await;
+    let info = publish(&state, &id).await?;
+
+    super::save(&handle);
+    Ok(info)
+}
+
+/// Offer the latest revision of a share to its recipients. Returns how many accepted the offer.
+#[tauri::command]
+pub async fn offer_share(id: String, state: State<'_>) -> Result<usize, Error> {
+    let (ticket, recipients) = {
+        let shares = state.shares.lock().await;
+        let share = shares.get(&id).ok_or_else(|| not_found(&id))?;
+        let ticket = share.tickets.last().cloned().ok_or("Share has no ticket")?;
+        (ticket, share.access.recipients.clone())
+    };
+    if recipients.is_empty() {
+        return Err(Error::InvalidInput {
+            what: "share",
+            reason: "The share has no recipients".to_string(),
+        });
+    }
+
+    let mut offered = 0;
+    let mut last_error = None;
+    for node_id in recipients {
+        match offer::offer_ticket(state.iroh().endpoint(), node_id, &ticket).await {
+            Ok(()) => offered += 1,
+            Err(e) => {
+                warn!("Failed to offer share {} to {}: {}", id, node_id, e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    match (offered, last_error) {
+        (0, Some(error)) => Err(error),
+        _ => Ok(offered),
+    }
+}
+
+/// Close a share and release its blobs. Its tickets stop working.
+#[tauri::command]
+pub async fn close_share(id: String, handle: AppHandle) -> Result<(), Error> {
+    close(&handle, &id).await
+}
@@ -0,0 +1,209 @@
+//! Text and links shared next to files.
+//!
+//! A snippet is stored as a blob like any file and listed in the manifest with
+//! its [`ContentKind`]. Receivers read it into memory and show it, nothing is
+//! written to the download folder.
+
+use iroh::NodeAddr;
+use iroh_blobs::Hash;
+use log::{info, warn};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+use crate::error::Error;
+use crate::files::{self, ContentKind};
+use crate::state::AppState;
+use crate::{events, share, storage, utils};
+
+/// Snippets are held in memory on both ends, larger text should be sent as a file.
+pub const MAX_SNIPPET_SIZE: u64 = 1024 * 1024;
+
+/// Share `text` on the send page. Without a `name` one is made up from the text.
+#[tauri::command]
+pub async fn add_snippet(
+    text: String,
+    name: Option<String>,
+    handle: AppHandle,
+) -> Result<String, Error> {
+    add(&handle, text, name).await
+}
+
+/// Share the text currently in the clipboard.
+#[tauri::command]
+pub async fn add_clipboard_snippet(handle: AppHandle) -> Result<String, Error> {
+    let text = handle
+        .clipboard()
+        .read_text()
+        .map_err(|e| format!("Failed to read text from clipboard: {}", e))?;
+    add(&handle, text, None).await
+}
+
+async fn add(handle: &AppHandle, text: String, name: Option<String>) -> Result<String, Error> {
+    if text.trim().is_empty() {
+        return Err(Error::InvalidInput {
+            what: "snippet",
+            reason: "Nothing to share, the text is empty".to_string(),
+        });
+    }
+    if text.len() as u64 > MAX_SNIPPET_SIZE {
+        return Err(Error::TooLarge {
+            name: name.unwrap_or_else(|| "Text".to_string()),
+            size: text.len() as u64,
+            limit: MAX_SNIPPET_SIZE,
+        });
+    }
+
+    let state = handle.state::<AppState>();
+    let kind = kind_of(&text);
+    let name = {
+        let files = state.files().await;
+        let name = match name {
+            Some(name) => name.trim().to_string(),
+            None => default_name(&files, kind),
+        };
+        // Names end up in receivers' file lists and may be removed by name
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return Err(Error::InvalidName { name });
+        }
+        if files.has_file(&name) {
+            return Err(Error::DuplicateName { name });
+        }
+        name
+    };
+    info!("Adding {:?} snippet: {}", kind, name);
+
+    let size = text.len() as u64;
+    let tag = storage::share_file_tag(&name);
+    let hash = state
+        .iroh()
+        .blobs
+        .add_bytes_named(text, tag)
+        .await
+        .map_err(|e| format!("Failed to store snippet: {}", e))?
+        .hash;
+    state.blob_access.lock().await.touch([hash]);
+
+    state.files().await.add_file(files::File {
+        name: name.clone(),
+        icon: String::new(),
+        icon_hash: None,
+        size,
+        hash,
+        kind,
+        thumbnail_hash: None,
+        compression: None,
+    });
+
+    // Snippets have no source, their name stands in for the path on the send page
+    let payload = events::UploadFileAdded {
+        name: name.clone(),
+        icon: String::new(),
+        path: name.clone(),
+        size,
+        stored_size: size,
+    };
+    handle.emit(events::UPLOAD_FILE_ADDED, payload).ok();
+    let payload = events::UploadFileCompleted { name: name.clone() };
+    handle.emit(events::UPLOAD_FILE_COMPLETED, payload).ok();
+
+    share::republish_named(handle).await;
+    share::save(handle);
+
+    Ok(name)
+}
+
+/// Single lines that look like a web address are shared as links.
+fn kind_of(text: &str) -> ContentKind {
+    let text = text.trim();
+    let is_url = (text.starts_with("https://") || text.starts_with("http://"))
+        && !text.contains(char::is_whitespace);
+
+    match is_url {
+        true => ContentKind::Link,
+        false => ContentKind::Text,
+    }
+}
+
+fn default_name(files: &files::Files, kind: ContentKind) -> String {
+    let base = match kind {
+        ContentKind::Link => "Link",
+        _ => "Text",
+    };
+
+    (1..)
+        .map(|n| format!("{} {}", base, n))
+        .find(|name| !files.has_file(name))
+        .expect("Unbounded range")
+}
+
+/// Fetch the snippet `file` of the share `root` and hand its text to the
+/// receive page. With `copy` the text also goes to the clipboard.
+pub async fn receive(
+    handle: &AppHandle,
+    file: files::File,
+    remote_node_addr: NodeAddr,
+    root: Hash,
+    keep_received: bool,
+    copy: bool,
+) -> Result<(), Error> {
+    info!("Receiving snippet: {}", file.name);
+    if file.size > MAX_SNIPPET_SIZE {
+        return Err(Error::TooLarge {
+            name: file.name,
+            size: file.size,
+            limit: MAX_SNIPPET_SIZE,
+        });
+    }
+
+    let state = handle.state::<AppState>();
+    let iroh = match cfg!(debug_assertions) {
+        true => &state.iroh_debug,
+        false => state.iroh(),
+    };
+
+    let tag = storage::download_tag(root, file.hash);
+    let bytes = utils::download_blob_bytes(&iroh.blobs, file.hash, remote_node_addr, tag.clone())
+        .await
+        .map_err(|e| match e {
+            Error::Connection { message } => Error::Transfer {
+                name: file.name.clone(),
+                message,
+            },
+            e => e,
+        })?;
+    let text = String::from_utf8(bytes).map_err(|_| Error::Protocol {
+        message: "Snippet is not valid text".to_string(),
+    })?;
+
+    if keep_received {
+        state.blob_access.lock().await.touch([file.hash]);
+    } else {
+        storage::delete_tag(&iroh.blobs, tag).await?;
+    }
+
+    let copied = copy
+        && match handle.clipboard().write_text(text.clone()) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Failed to copy snippet: {}", e);
+                false
+            }
+        };
+
+    let payload = events::DownloadSnippet {
+        name: file.name.clone(),
+        kind: file.kind,
+        text,
+        copied,
+    };
+    handle.emit(events::DOWNLOAD_SNIPPET, payload).ok();
+
+    // Nothing was written to disk, the completion carries no path
+    let payload = events::DownloadFileCompleted {
+        name: file.name,
+        path: String::new(),
+    };
+    handle.emit(events::DOWNLOAD_FILE_COMPLETED, payload).ok();
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+pub const MIRRORS_FILE_NAME: &str = "mirrors.json";
+
+/// The last revision each mirror applied, keyed by mirror id. Kept across
+/// restarts so an announcement replayed later can't roll a mirror back.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SavedMirrors {
+    #[serde(default)]
+    pub revisions: HashMap<String, u64>,
+}
+
+impl SavedMirrors {
+    pub fn from_config(path: PathBuf) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read mirrors file: {}", e))?;
+
+        let mirrors: SavedMirrors = serde_json::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Failed to parse mirrors file: {}", e))?;
+
+        Ok(mirrors)
+    }
+
+    pub fn save(&self, path: PathBuf) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize mirrors: {}", e))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, contents)
+            .map_err(|e| anyhow::anyhow!("Failed to write mirrors file: {}", e))?;
+
+        Ok(())
+    }
+}
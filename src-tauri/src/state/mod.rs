@@ -1,12 +1,30 @@
+use std::collections::HashMap;
+
 use iroh_blobs::ticket::BlobTicket;
+use settings::Settings;
 use tauri::{AppHandle, Manager};
-use tokio::sync::{Mutex, MutexGuard};
+use tokio::{
+    sync::{Mutex, MutexGuard},
+    task::JoinHandle,
+};
 use user_data::User;
 
+pub mod mirror_data;
+pub mod settings;
+pub mod share_data;
 pub mod user_data;
 
+use crate::error::Error;
+use crate::file_operations::watch::SourceWatcher;
 use crate::files;
+use crate::folder::LiveFolder;
 use crate::iroh;
+use crate::mirror::Mirror;
+use crate::offer::rules::Rules;
+use crate::share::{registry::Share, Publisher};
+use crate::storage::access::AccessLog;
+use crate::stream::Streams;
+use crate::uploads::Tracker;
 
 #[derive(Debug)]
 pub struct AppState {
@@ -15,24 +33,51 @@ pub struct AppState {
     pub iroh_debug: iroh::Iroh,
 
     pub user: Mutex<Option<User>>,
+    pub settings: Mutex<Settings>,
     pub iroh: iroh::Iroh,
     pub files: Mutex<files::Files>,
     pub header_tickets: Mutex<Vec<BlobTicket>>,
+    pub accept_rules: Mutex<Rules>,
+    pub blob_access: Mutex<AccessLog>,
+    pub sources: Mutex<SourceWatcher>,
+    pub live_folder: Mutex<Option<LiveFolder>>,
+    pub mirrors: Mutex<HashMap<String, Mirror>>,
+    pub publisher: Mutex<Publisher>,
+    pub shares: Mutex<HashMap<String, Share>>,
+    pub uploads: Mutex<Tracker>,
+    pub streams: Mutex<Streams>,
+    /// Emits diagnostics while they're watched.
+    pub diagnostics: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl AppState {
     pub fn new(
         user: Option<User>,
+        settings: Settings,
+        accept_rules: Rules,
+        blob_access: AccessLog,
+        sources: SourceWatcher,
         iroh: iroh::Iroh,
         #[cfg(debug_assertions)] iroh_debug: iroh::Iroh,
     ) -> Self {
         let ticket = iroh.gossip.ticket().to_owned();
         Self {
             user: Mutex::new(user),
+            settings: Mutex::new(settings),
             iroh_debug,
             iroh,
             files: Mutex::new(files::Files::new(ticket)),
             header_tickets: Mutex::new(Vec::new()),
+            accept_rules: Mutex::new(accept_rules),
+            blob_access: Mutex::new(blob_access),
+            sources: Mutex::new(sources),
+            live_folder: Mutex::new(None),
+            mirrors: Mutex::new(HashMap::new()),
+            publisher: Mutex::new(Publisher::default()),
+            shares: Mutex::new(HashMap::new()),
+            uploads: Mutex::new(Tracker::default()),
+            streams: Mutex::new(Streams::default()),
+            diagnostics: Mutex::new(None),
         }
     }
 
@@ -48,17 +93,17 @@ impl AppState {
 pub type State<'a> = tauri::State<'a, AppState>;
 
 #[tauri::command]
-pub async fn get_user(state: State<'_>) -> Result<Option<User>, String> {
+pub async fn get_user(state: State<'_>) -> Result<Option<User>, Error> {
     let user = state.user.lock().await;
     Ok(user.clone())
 }
 
 #[tauri::command]
-pub async fn update_user(state: State<'_>, user: User, app: AppHandle) -> Result<(), String> {
+pub async fn update_user(state: State<'_>, user: User, app: AppHandle) -> Result<(), Error> {
     let cfg_path = crate::utils::get_config_dir(&app)?.join(user_data::CONFIG_FILE_NAME);
 
     if let Err(e) = user.save(cfg_path) {
-        return Err(format!("Failed to save user data: {}", e));
+        return Err(format!("Failed to save user data: {}", e).into());
     }
 
     let mut state_user = state.user.lock().await;
@@ -0,0 +1,92 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Result;
+use iroh::NodeId;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use super::State;
+use crate::download::retry::RetryPolicy;
+use crate::error::Error;
+use crate::file_operations::compress::CompressionMode;
+use crate::iroh::RelayConfig;
+
+pub const SETTINGS_FILE_NAME: &str = "settings.json";
+
+/// Settings that affect how the node is set up. Changes apply on the next start.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Settings {
+    /// Nodes used to join the share code rendezvous topic, next to the ones
+    /// built into the app.
+    #[serde(default)]
+    pub rendezvous_nodes: Vec<NodeId>,
+    /// Keep received blobs in the store after they are exported.
+    #[serde(default)]
+    pub keep_received_blobs: bool,
+    /// When files added to a share are compressed.
+    #[serde(default)]
+    pub compression: CompressionMode,
+    /// How downloads that broke off are retried.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Relays used when no direct path is found, also written into tickets.
+    #[serde(default)]
+    pub relay: RelayConfig,
+}
+
+impl Settings {
+    pub fn from_config(path: PathBuf) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read settings file: {}", e))?;
+
+        let settings: Settings = serde_json::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Failed to parse settings file: {}", e))?;
+
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: PathBuf) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize settings: {}", e))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, contents)
+            .map_err(|e| anyhow::anyhow!("Failed to write settings file: {}", e))?;
+
+        info!("Settings saved successfully");
+
+        Ok(())
+    }
+}
+
+#[tauri::command]
+pub async fn get_settings(state: State<'_>) -> Result<Settings, Error> {
+    Ok(state.settings.lock().await.clone())
+}
+
+#[tauri::command]
+pub async fn update_settings(
+    state: State<'_>,
+    settings: Settings,
+    app: AppHandle,
+) -> Result<(), Error> {
+    if settings.relay == RelayConfig::Custom(Vec::new()) {
+        return Err(Error::InvalidInput {
+            what: "relay",
+            reason: "At least one relay URL is needed".to_string(),
+        });
+    }
+
+    let path = crate::utils::get_config_dir(&app)?.join(SETTINGS_FILE_NAME);
+
+    if let Err(e) = settings.save(path) {
+        return Err(format!("Failed to save settings: {}", e).into());
+    }
+
+    *state.settings.lock().await = settings;
+    Ok(())
+}
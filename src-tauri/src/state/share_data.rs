@@ -0,0 +1,96 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Result;
+use iroh_blobs::Hash;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{file_operations::watch::Stamp, files, share::registry::ShareAccess};
+
+pub const SHARE_FILE_NAME: &str = "share.json";
+
+/// A file added from disk, with the source it was imported from in place.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SavedFile {
+    pub file: files::File,
+    pub path: PathBuf,
+    pub stamp: Stamp,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SavedNamedShare {
+    pub name: String,
+    /// Combined with the node key to derive the share's key.
+    pub seed: [u8; 32],
+    /// Collection the share's pointer points at.
+    pub root: Option<Hash>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SavedFolder {
+    pub root: PathBuf,
+    /// Collection roots of the kept revisions, newest last.
+    pub published: Vec<Hash>,
+}
+
+/// A share from the registry.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SavedRegistryShare {
+    pub id: String,
+    pub name: String,
+    pub files: Vec<files::File>,
+    pub access: ShareAccess,
+    pub revision: u64,
+    pub tickets: Vec<Hash>,
+    pub created_at: u64,
+}
+
+/// The sender's share, saved whenever it changes and restored on the next
+/// start so tickets handed out before keep working.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SavedShare {
+    #[serde(default)]
+    pub files: Vec<SavedFile>,
+    /// Text and links, their blobs are kept by the store.
+    #[serde(default)]
+    pub snippets: Vec<files::File>,
+    /// Collection roots of the generated tickets. Their tags are kept by the store.
+    #[serde(default)]
+    pub tickets: Vec<Hash>,
+    #[serde(default)]
+    pub revision: u64,
+    #[serde(default)]
+    pub named: Option<SavedNamedShare>,
+    #[serde(default)]
+    pub folder: Option<SavedFolder>,
+    #[serde(default)]
+    pub shares: Vec<SavedRegistryShare>,
+}
+
+impl SavedShare {
+    pub fn from_config(path: PathBuf) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read share file: {}", e))?;
+
+        let share: SavedShare = serde_json::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Failed to parse share file: {}", e))?;
+
+        Ok(share)
+    }
+
+    pub fn save(&self, path: PathBuf) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize share: {}", e))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, contents)
+            .map_err(|e| anyhow::anyhow!("Failed to write share file: {}", e))?;
+
+        info!("Share saved successfully");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use iroh_blobs::Hash;
+use log::error;
+use tauri::{AppHandle, Manager};
+use tokio::time;
+
+use crate::state::AppState;
+
+pub const ACCESS_FILE_NAME: &str = "blob-access.json";
+
+/// How often changes are written to disk.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Last time sendit added, exported or served each blob. The blob store
+/// doesn't track this itself.
+///
+/// Changes are kept in memory and written by [`flush_periodically`], so
+/// touching blobs never waits on the disk.
+#[derive(Debug, Default)]
+pub struct AccessLog {
+    path: PathBuf,
+    entries: HashMap<String, u64>,
+    dirty: bool,
+}
+
+impl AccessLog {
+    pub fn load(path: PathBuf) -> Self {
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            entries,
+            dirty: false,
+        }
+    }
+
+    pub fn touch(&mut self, hashes: impl IntoIterator<Item = Hash>) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        for hash in hashes {
+            self.entries.insert(hash.to_string(), now);
+        }
+        self.dirty = true;
+    }
+
+    pub fn forget(&mut self, hashes: impl IntoIterator<Item = Hash>) {
+        for hash in hashes {
+            self.entries.remove(&hash.to_string());
+        }
+        self.dirty = true;
+    }
+
+    pub fn last_access(&self, hash: &Hash) -> Option<u64> {
+        self.entries.get(&hash.to_string()).copied()
+    }
+
+    /// The file contents to write if anything changed since the last call.
+    fn take_changes(&mut self) -> Option<(PathBuf, String)> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+
+        match serde_json::to_string(&self.entries) {
+            Ok(contents) => Some((self.path.clone(), contents)),
+            Err(e) => {
+                error!("Failed to save blob access log: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Write the access log to disk every [`FLUSH_INTERVAL`] if it changed. The
+/// lock is only held to take a snapshot, not while writing.
+pub async fn flush_periodically(handle: AppHandle) {
+    let mut interval = time::interval(FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let state = handle.state::<AppState>();
+        let Some((path, contents)) = state.blob_access.lock().await.take_changes() else {
+            continue;
+        };
+        if let Err(e) = tokio::fs::write(&path, contents).await {
+            error!("Failed to save blob access log: {}", e);
+            // Try again with the next flush
+            state.blob_access.lock().await.dirty = true;
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! Blob tags and garbage collection.
+//!
+//! Every blob sendit keeps is protected by a named tag under [`TAG_PREFIX`]:
+//!
+//! - `sendit/share/file/<name>`, `sendit/share/icon/<name>` and
+//!   `sendit/share/thumbnail/<name>` for files being sent. Removed together
+//!   with the file.
+//! - `sendit/share/header/<manifest>` for published share collections.
+//!   Removed when the share is cleared.
+//! - `sendit/shares/<id>/file/<name>`, `.../icon/<name>`, `.../thumbnail/<name>`
+//!   and `.../header/<manifest>` for the shares in the registry. Removed when
+//!   the share is closed.
+//! - `sendit/download/<root>/<hash>` for everything fetched for the share `root`.
+//!   Unless `keep_received_blobs` is set, removed as soon as a file is exported
+//!   and when the download finishes.
+//!
+//! Untagged blobs are dropped by the iroh-blobs garbage collector. Blobs held
+//! by the temp tags of running imports and downloads are never reclaimed.
+//!
+//! Header collections are only reachable through the tickets of an open share,
+//! so the ones no ticket refers to anymore are removed by [`cleanup_storage`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use bytes::Bytes;
+use iroh_blobs::{hashseq::HashSeq, store::Store as _, BlobFormat, Hash, Tag};
+use log::info;
+use n0_future::stream::StreamExt;
+use serde::Serialize;
+
+use crate::{
+    error::Error,
+    iroh::{BlobsClient, Iroh},
+    state::{AppState, State},
+};
+
+pub mod access;
+
+pub const TAG_PREFIX: &str = "sendit/";
+pub const SHARE_TAG_PREFIX: &str = "sendit/share/";
+pub const DOWNLOAD_TAG_PREFIX: &str = "sendit/download/";
+const SHARE_HEADER_TAG_PREFIX: &str = "sendit/share/header/";
+pub const SHARES_TAG_PREFIX: &str = "sendit/shares/";
+
+/// Share reported for the blobs of the files currently being sent.
+pub const OUTGOING_SHARE: &str = "outgoing";
+
+pub fn share_file_tag(name: &str) -> Tag {
+    Tag::from(format!("{SHARE_TAG_PREFIX}file/{name}"))
+}
+
+pub fn share_icon_tag(name: &str) -> Tag {
+    Tag::from(format!("{SHARE_TAG_PREFIX}icon/{name}"))
+}
+
+pub fn share_thumbnail_tag(name: &str) -> Tag {
+    Tag::from(format!("{SHARE_TAG_PREFIX}thumbnail/{name}"))
+}
+
+pub fn share_header_tag(manifest: Hash) -> Tag {
+    Tag::from(format!("{SHARE_HEADER_TAG_PREFIX}{manifest}"))
+}
+
+pub fn shares_prefix(id: &str) -> String {
+    format!("{SHARES_TAG_PREFIX}{id}/")
+}
+
+pub fn shares_file_tag(id: &str, name: &str) -> Tag {
+    Tag::from(format!("{}file/{name}", shares_prefix(id)))
+}
+
+pub fn shares_icon_tag(id: &str, name: &str) -> Tag {
+    Tag::from(format!("{}icon/{name}", shares_prefix(id)))
+}
+
+pub fn shares_thumbnail_tag(id: &str, name: &str) -> Tag {
+    Tag::from(format!("{}thumbnail/{name}", shares_prefix(id)))
+}
+
+pub fn shares_header_tag(id: &str, manifest: Hash) -> Tag {
+    Tag::from(format!("{}header/{manifest}", shares_prefix(id)))
+}
+
+pub fn download_prefix(root: Hash) -> String {
+    format!("{DOWNLOAD_TAG_PREFIX}{root}/")
+}
+
+pub fn download_tag(root: Hash, hash: Hash) -> Tag {
+    Tag::from(format!("{}{hash}", download_prefix(root)))
+}
+
+/// Delete every tag whose name starts with `prefix`. Returns how many were deleted.
+pub async fn delete_tags_with_prefix(blobs: &BlobsClient, prefix: &str) -> Result<usize, String> {
+    let tags = list_tags(blobs).await?;
+
+    let mut deleted = 0;
+    for (name, _, _) in tags
+        .into_iter()
+        .filter(|(name, _, _)| name.starts_with(prefix))
+    {
+        blobs
+            .tags()
+            .delete(Tag::from(name.as_str()))
+            .await
+            .map_err(|e| format!("Failed to delete tag {}: {}", name, e))?;
+        deleted += 1;
+    }
+
+    Ok(deleted)
+}
+
+/// Delete the header tags of the share collection `root`.
+pub async fn delete_header_tags(blobs: &BlobsClient, root: Hash) -> Result<(), String> {
+    for (name, hash, _) in list_tags(blobs).await? {
+        if hash == root && name.starts_with(SHARE_HEADER_TAG_PREFIX) {
+            delete_tag(blobs, Tag::from(name.as_str())).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Stop sharing the file `name`: delete its tags. The blobs stay while other
+/// shares, collections or downloads still tag them, GC reclaims them after that.
+pub async fn release_share_file(blobs: &BlobsClient, name: &str) -> Result<(), String> {
+    delete_tag(blobs, share_file_tag(name)).await?;
+    delete_tag(blobs, share_icon_tag(name)).await?;
+    delete_tag(blobs, share_thumbnail_tag(name)).await
+}
+
+/// Delete a single tag.
+pub async fn delete_tag(blobs: &BlobsClient, tag: Tag) -> Result<(), String> {
+    blobs
+        .tags()
+        .delete(tag.clone())
+        .await
+        .map_err(|e| format!("Failed to delete tag {}: {}", tag, e))
+}
+
+async fn list_tags(blobs: &BlobsClient) -> Result<Vec<(String, Hash, BlobFormat)>, String> {
+    let mut stream = blobs
+        .tags()
+        .list()
+        .await
+        .map_err(|e| format!("Failed to list tags: {}", e))?;
+
+    let mut tags = Vec::new();
+    while let Some(tag) = stream.next().await {
+        let tag = tag.map_err(|e| format!("Failed to list tags: {}", e))?;
+        tags.push((tag.name.to_string(), tag.hash, tag.format));
+    }
+    Ok(tags)
+}
+
+/// Names of the tags keeping each hash alive, including the children of tagged hash sequences.
+async fn tag_index(blobs: &BlobsClient) -> Result<HashMap<Hash, Vec<String>>, String> {
+    let mut index: HashMap<Hash, Vec<String>> = HashMap::new();
+
+    for (name, hash, format) in list_tags(blobs).await? {
+        index.entry(hash).or_default().push(name.clone());
+        for child in children(blobs, hash, format).await {
+            index.entry(child).or_default().push(name.clone());
+        }
+    }
+
+    Ok(index)
+}
+
+/// The children of `hash` if it is a hash sequence that is in the store.
+async fn children(blobs: &BlobsClient, hash: Hash, format: BlobFormat) -> Vec<Hash> {
+    if format != BlobFormat::HashSeq {
+        return Vec::new();
+    }
+    match blobs.read_to_bytes(hash).await {
+        Ok(bytes) => HashSeq::try_from(Bytes::from(bytes))
+            .map(|seq| seq.iter().collect())
+            .unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Hashes held by the temp tags of running operations: an import or download
+/// whose named tag isn't set yet, or a partial download between retries.
+async fn held_hashes(iroh: &Iroh) -> HashSet<Hash> {
+    let mut held = HashSet::new();
+    for content in iroh.store.temp_tags() {
+        held.insert(content.hash);
+        held.extend(children(&iroh.blobs, content.hash, content.format).await);
+    }
+    held
+}
+
+/// The nodes whose stores sendit manages, debug builds receive on a second one.
+fn nodes(state: &AppState) -> Vec<&Iroh> {
+    #[allow(unused_mut)]
+    let mut nodes = vec![state.iroh()];
+    #[cfg(debug_assertions)]
+    nodes.push(&state.iroh_debug);
+    nodes
+}
+
+/// Hashes kept alive by tags, including the children of tagged hash sequences.
+async fn protected_hashes(blobs: &BlobsClient) -> Result<HashSet<Hash>, String> {
+    Ok(tag_index(blobs).await?.into_keys().collect())
+}
+
+/// The share a tag belongs to: [`OUTGOING_SHARE`] for the files being sent, the
+/// share id for shares in the registry and the collection root for downloads.
+fn tag_share(tag: &str) -> Option<String> {
+    if let Some(rest) = tag
+        .strip_prefix(DOWNLOAD_TAG_PREFIX)
+        .or_else(|| tag.strip_prefix(SHARES_TAG_PREFIX))
+    {
+        return rest.split('/').next().map(str::to_string);
+    }
+    tag.starts_with(SHARE_TAG_PREFIX)
+        .then(|| OUTGOING_SHARE.to_string())
+}
+
+/// Header collection tags no ticket of the current share refers to, and tags
+/// of registry shares that are no longer open.
+async fn orphaned_header_tags(state: &AppState) -> Result<Vec<String>, String> {
+    let live: HashSet<Hash> = state
+        .header_tickets
+        .lock()
+        .await
+        .iter()
+        .map(|ticket| ticket.hash())
+        .collect();
+    let open: HashSet<String> = state.shares.lock().await.keys().cloned().collect();
+
+    Ok(list_tags(&state.iroh().blobs)
+        .await?
+        .into_iter()
+        .filter(|(name, hash, _)| {
+            if name.starts_with(SHARE_HEADER_TAG_PREFIX) {
+                return !live.contains(hash);
+            }
+            name.starts_with(SHARES_TAG_PREFIX)
+                && tag_share(name).is_some_and(|id| !open.contains(&id))
+        })
+        .map(|(name, _, _)| name)
+        .collect())
+}
+
+/// Sizes of every blob in the store and whether it is complete.
+async fn blob_sizes(blobs: &BlobsClient) -> Result<HashMap<Hash, (u64, bool)>, String> {
+    let mut sizes = HashMap::new();
+
+    let mut complete = blobs
+        .list()
+        .await
+        .map_err(|e| format!("Failed to list blobs: {}", e))?;
+    while let Some(blob) = complete.next().await {
+        let blob = blob.map_err(|e| format!("Failed to list blobs: {}", e))?;
+        sizes.insert(blob.hash, (blob.size, true));
+    }
+
+    let mut incomplete = blobs
+        .list_incomplete()
+        .await
+        .map_err(|e| format!("Failed to list blobs: {}", e))?;
+    while let Some(blob) = incomplete.next().await {
+        let blob = blob.map_err(|e| format!("Failed to list blobs: {}", e))?;
+        sizes.insert(blob.hash, (blob.size, false));
+    }
+
+    Ok(sizes)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct StorageReport {
+    pub blob_count: usize,
+    pub total_size: u64,
+    pub incomplete_count: usize,
+    pub incomplete_size: u64,
+    /// Size of complete blobs no tag refers to.
+    pub reclaimable_size: u64,
+    pub tag_count: usize,
+    /// Header collections no open share refers to.
+    pub orphaned_headers: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StoredBlob {
+    pub hash: String,
+    pub size: u64,
+    pub complete: bool,
+    pub tags: Vec<String>,
+    /// Shares the blob belongs to, see [`OUTGOING_SHARE`].
+    pub shares: Vec<String>,
+    /// Unix timestamp in seconds, if sendit has touched the blob since access tracking was added.
+    pub last_access: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReclaimReport {
+    pub deleted_tags: usize,
+    pub deleted_blobs: usize,
+    pub reclaimed_size: u64,
+}
+
+#[tauri::command]
+pub async fn storage_report(state: State<'_>) -> Result<StorageReport, Error> {
+    let blobs = &state.iroh().blobs;
+    let protected = protected_hashes(blobs).await?;
+    let mut report = StorageReport {
+        tag_count: list_tags(blobs).await?.len(),
+        orphaned_headers: orphaned_header_tags(&state).await?.len(),
+        ..Default::default()
+    };
+
+    for (hash, (size, complete)) in blob_sizes(blobs).await? {
+        if !complete {
+            report.incomplete_count += 1;
+            report.incomplete_size += size;
+            continue;
+        }
+        report.blob_count += 1;
+        report.total_size += size;
+        if !protected.contains(&hash) {
+            report.reclaimable_size += size;
+        }
+    }
+
+    Ok(report)
+}
+
+/// List every blob in the store, largest first.
+#[tauri::command]
+pub async fn list_stored_blobs(state: State<'_>) -> Result<Vec<StoredBlob>, Error> {
+    let blobs = &state.iroh().blobs;
+    let index = tag_index(blobs).await?;
+    let access = state.blob_access.lock().await;
+
+    let mut stored = blob_sizes(blobs)
+        .await?
+        .into_iter()
+        .map(|(hash, (size, complete))| {
+            let tags = index.get(&hash).cloned().unwrap_or_default();
+            let mut shares: Vec<String> = tags.iter().filter_map(|tag| tag_share(tag)).collect();
+            shares.sort();
+            shares.dedup();
+
+            StoredBlob {
+                hash: hash.to_string(),
+                size,
+                complete,
+                tags,
+                shares,
+                last_access: access.last_access(&hash),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    stored.sort_by(|a, b| b.size.cmp(&a.size));
+    Ok(stored)
+}
+
+/// Drop tags left behind by older versions and delete every complete blob no tag refers to.
+#[tauri::command]
+pub async fn reclaim_storage(state: State<'_>) -> Result<ReclaimReport, Error> {
+    let mut report = ReclaimReport::default();
+    reclaim(&state, &mut report).await?;
+    Ok(report)
+}
+
+/// Delete stored blobs the current share doesn't need.
+///
+/// With `hashes`, only those blobs and the tags keeping them are deleted. Without,
+/// every received blob is dropped and the store is reclaimed. Header collections
+/// no open share refers to are removed in both cases.
+#[tauri::command]
+pub async fn cleanup_storage(
+    state: State<'_>,
+    hashes: Option<Vec<String>>,
+) -> Result<ReclaimReport, Error> {
+    let blobs = &state.iroh().blobs;
+    let mut report = ReclaimReport::default();
+
+    for name in orphaned_header_tags(&state).await? {
+        delete_tag(blobs, Tag::from(name.as_str())).await?;
+        report.deleted_tags += 1;
+    }
+
+    let Some(hashes) = hashes else {
+        for iroh in nodes(&state) {
+            report.deleted_tags +=
+                delete_tags_with_prefix(&iroh.blobs, DOWNLOAD_TAG_PREFIX).await?;
+        }
+        reclaim(&state, &mut report).await?;
+        return Ok(report);
+    };
+
+    let hashes = hashes
+        .iter()
+        .map(|hash| {
+            Hash::from_str(hash).map_err(|e| Error::InvalidInput {
+                what: "hash",
+                reason: format!("{}: {}", hash, e),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let index = tag_index(blobs).await?;
+    let sizes = blob_sizes(blobs).await?;
+
+    // Check everything up front so a rejected hash doesn't leave a partial cleanup behind
+    if let Some(hash) = hashes.iter().find(|hash| {
+        index.get(hash).is_some_and(|tags| {
+            tags.iter()
+                .any(|tag| tag.starts_with(SHARE_TAG_PREFIX) || tag.starts_with(SHARES_TAG_PREFIX))
+        })
+    }) {
+        return Err(Error::InvalidInput {
+            what: "hash",
+            reason: format!(
+                "Blob {} is part of an open share, remove the file first",
+                hash
+            ),
+        });
+    }
+
+    let mut deleted_tags = HashSet::new();
+    for hash in &hashes {
+        for name in index.get(hash).into_iter().flatten() {
+            if deleted_tags.insert(name.clone()) {
+                delete_tag(blobs, Tag::from(name.as_str())).await?;
+            }
+        }
+
+        blobs
+            .delete_blob(*hash)
+            .await
+            .map_err(|e| format!("Failed to delete blob: {}", e))?;
+        report.deleted_blobs += 1;
+        report.reclaimed_size += sizes.get(hash).map(|(size, _)| *size).unwrap_or_default();
+    }
+    report.deleted_tags += deleted_tags.len();
+
+    state.blob_access.lock().await.forget(hashes);
+
+    info!(
+        "Cleaned up {} bytes from {} blobs",
+        report.reclaimed_size, report.deleted_blobs
+    );
+    Ok(report)
+}
+
+async fn reclaim(state: &AppState, report: &mut ReclaimReport) -> Result<(), String> {
+    for iroh in nodes(state) {
+        reclaim_node(state, iroh, report).await?;
+    }
+
+    info!(
+        "Reclaimed {} bytes from {} blobs",
+        report.reclaimed_size, report.deleted_blobs
+    );
+    Ok(())
+}
+
+async fn reclaim_node(
+    state: &AppState,
+    iroh: &Iroh,
+    report: &mut ReclaimReport,
+) -> Result<(), String> {
+    let blobs = &iroh.blobs;
+
+    // Older versions tagged everything with auto tags and never removed them
+    for (name, _, _) in list_tags(blobs).await? {
+        if name.starts_with(TAG_PREFIX) {
+            continue;
+        }
+        delete_tag(blobs, Tag::from(name.as_str())).await?;
+        report.deleted_tags += 1;
+    }
+
+    let mut protected = protected_hashes(blobs).await?;
+    // Blobs of running imports and downloads aren't tagged yet, or only while they run
+    protected.extend(held_hashes(iroh).await);
+
+    // Incomplete blobs are left alone, they may belong to a running download
+    let unprotected = blob_sizes(blobs)
+        .await?
+        .into_iter()
+        .filter(|(hash, (_, complete))| *complete && !protected.contains(hash))
+        .map(|(hash, (size, _))| (hash, size))
+        .collect::<Vec<_>>();
+
+    for (hash, size) in &unprotected {
+        blobs
+            .delete_blob(*hash)
+            .await
+            .map_err(|e| format!("Failed to delete blob: {}", e))?;
+        report.deleted_blobs += 1;
+        report.reclaimed_size += size;
+    }
+
+    state
+        .blob_access
+        .lock()
+        .await
+        .forget(unprotected.into_iter().map(|(hash, _)| hash));
+    Ok(())
+}
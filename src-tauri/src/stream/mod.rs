@@ -0,0 +1,338 @@
+//! Playing received media while it downloads.
+//!
+//! Files of a share being received are served on the [`SCHEME`] URI scheme as
+//! `stream://localhost/<hash>`, with HTTP range requests answered straight from
+//! the partial blob in the store. A range that hasn't arrived yet is fetched
+//! from the sender ahead of the running download, and the request waits until
+//! it is verified.
+
+use std::{collections::HashMap, ops::Range, str::FromStr, time::Duration};
+
+use bao_tree::{ChunkNum, ChunkRanges};
+use iroh::{Endpoint, NodeAddr};
+use iroh_blobs::{
+    get::{
+        db::valid_ranges,
+        fsm::{self, ConnectedNext, EndBlobNext},
+    },
+    protocol::{GetRequest, RangeSpecSeq},
+    store::{fs::Store, EntryStatus, MapEntry, MapEntryMut, MapMut},
+    Hash,
+};
+use iroh_io::AsyncSliceReader;
+use log::{info, warn};
+use tauri::{
+    http::{header, Request, Response, StatusCode},
+    AppHandle, Manager,
+};
+use tokio::time::{sleep, Instant};
+
+use crate::state::AppState;
+
+pub const SCHEME: &str = "stream";
+
+/// Most bytes sent per response, players ask for the rest as they go.
+const MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+/// How long a request waits for its range before giving up.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A file that can be streamed, and where to fetch it from.
+#[derive(Debug, Clone)]
+struct Source {
+    name: String,
+    size: u64,
+    node: NodeAddr,
+}
+
+/// Files of the shares being received, by hash.
+#[derive(Debug, Default)]
+pub struct Streams {
+    sources: HashMap<Hash, Source>,
+}
+
+impl Streams {
+    pub fn register(&mut self, hash: Hash, name: &str, size: u64, node: NodeAddr) {
+        self.sources.insert(
+            hash,
+            Source {
+                name: name.to_string(),
+                size,
+                node,
+            },
+        );
+    }
+}
+
+type Error = (StatusCode, String);
+
+/// Answer a request on the [`SCHEME`] URI scheme.
+pub async fn respond(handle: &AppHandle, request: Request<Vec<u8>>) -> Response<Vec<u8>> {
+    match serve(handle, &request).await {
+        Ok(response) => response,
+        Err((status, error)) => {
+            warn!("Failed to stream {}: {}", request.uri(), error);
+            Response::builder()
+                .status(status)
+                .body(error.into_bytes())
+                .expect("Valid response")
+        }
+    }
+}
+
+async fn serve(handle: &AppHandle, request: &Request<Vec<u8>>) -> Result<Response<Vec<u8>>, Error> {
+    // Requests may come in before the app finished loading
+    let state = handle
+        .try_state::<AppState>()
+        .ok_or((StatusCode::SERVICE_UNAVAILABLE, "Not ready".to_string()))?;
+    let iroh = match cfg!(debug_assertions) {
+        true => &state.iroh_debug,
+        false => state.iroh(),
+    };
+
+    let hash = Hash::from_str(request.uri().path().trim_start_matches('/'))
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid hash: {}", e)))?;
+    let source = state
+        .streams
+        .lock()
+        .await
+        .sources
+        .get(&hash)
+        .cloned()
+        .ok_or((StatusCode::NOT_FOUND, "Unknown file".to_string()))?;
+
+    let range = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| parse_range(value, source.size))
+        .unwrap_or(Some(0..source.size))
+        .filter(|range| range.start < range.end)
+        .ok_or((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            format!("Invalid range for {} bytes", source.size),
+        ))?;
+    let range = range.start..range.end.min(range.start + MAX_RESPONSE_SIZE);
+
+    wait_for(iroh.endpoint(), &iroh.store, &source, hash, range.clone())
+        .await
+        .map_err(|e| (StatusCode::GATEWAY_TIMEOUT, e))?;
+    let bytes = read(&iroh.store, hash, range.clone())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_TYPE, content_type(&source.name))
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", range.start, range.end - 1, source.size),
+        )
+        .header(header::CONTENT_LENGTH, bytes.len())
+        .body(bytes)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Parse a single `bytes=` range into a half open byte range of a `size` byte file.
+/// Ranges with no bytes in the file are `None`.
+fn parse_range(value: &str, size: u64) -> Option<Range<u64>> {
+    let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        // The last `end` bytes
+        ("", end) => (size.saturating_sub(end.parse().ok()?), size),
+        (start, "") => (start.parse().ok()?, size),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<u64>().ok()?.saturating_add(1),
+        ),
+    };
+    Some(start..end.min(size)).filter(|range| range.start < range.end)
+}
+
+fn content_type(name: &str) -> &'static str {
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("mp4" | "m4v") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mkv") => "video/x-matroska",
+        Some("mov") => "video/quicktime",
+        Some("ogv") => "video/ogg",
+        Some("mp3") => "audio/mpeg",
+        Some("m4a") => "audio/mp4",
+        Some("ogg" | "oga" | "opus") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        Some("flac") => "audio/flac",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Chunks of `range` that are not in the store yet.
+async fn missing(store: &Store, hash: Hash, range: &Range<u64>) -> Result<ChunkRanges, String> {
+    let wanted = ChunkRanges::from(ChunkNum::full_chunks(range.start)..ChunkNum::chunks(range.end));
+    missing_chunks(store, hash, wanted).await
+}
+
+/// Chunks of `wanted` that are not in the store yet.
+pub(crate) async fn missing_chunks(
+    store: &Store,
+    hash: Hash,
+    wanted: ChunkRanges,
+) -> Result<ChunkRanges, String> {
+    let status = store
+        .entry_status(&hash)
+        .await
+        .map_err(|e| format!("Failed to read blob status: {}", e))?;
+    let entry = match status {
+        EntryStatus::Complete => return Ok(ChunkRanges::empty()),
+        EntryStatus::NotFound => return Ok(wanted),
+        EntryStatus::Partial => store
+            .get_mut(&hash)
+            .await
+            .map_err(|e| format!("Failed to open blob: {}", e))?,
+    };
+    let Some(entry) = entry else {
+        return Ok(wanted);
+    };
+
+    let valid = valid_ranges::<Store>(&entry)
+        .await
+        .map_err(|e| format!("Failed to read blob ranges: {}", e))?;
+    Ok(wanted.difference(&valid))
+}
+
+/// Wait until `range` of the file is in the store, fetching the missing part
+/// from the sender right away instead of when the download gets to it.
+async fn wait_for(
+    endpoint: &Endpoint,
+    store: &Store,
+    source: &Source,
+    hash: Hash,
+    range: Range<u64>,
+) -> Result<(), String> {
+    let deadline = Instant::now() + WAIT_TIMEOUT;
+    let mut fetched = false;
+
+    loop {
+        let missing = missing(store, hash, &range).await?;
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        // A failed fetch leaves it to the running download
+        if !fetched {
+            fetched = true;
+            info!(
+                "Fetching {:?} of {} ahead of the download",
+                missing, source.name
+            );
+            if let Err(e) = fetch(endpoint, store, source.node.clone(), hash, missing).await {
+                warn!("Failed to fetch range of {}: {}", source.name, e);
+            }
+            continue;
+        }
+
+        if Instant::now() > deadline {
+            return Err(format!("Timed out waiting for {}", source.name));
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Fetch `ranges` of the blob `hash` from `node` into the store.
+async fn fetch(
+    endpoint: &Endpoint,
+    store: &Store,
+    node: NodeAddr,
+    hash: Hash,
+    ranges: ChunkRanges,
+) -> anyhow::Result<()> {
+    let connection = endpoint.connect(node, iroh_blobs::ALPN).await?;
+    let request = GetRequest::new(hash, RangeSpecSeq::from_ranges([ranges]));
+
+    let connected = fsm::start(connection, request).next().await?;
+    let ConnectedNext::StartRoot(start) = connected.next().await? else {
+        anyhow::bail!("Sender returned no data");
+    };
+    let (content, size) = start.next().next().await?;
+
+    // Shares the partial entry with the running download
+    let entry = store.get_or_create(hash, size).await?;
+    let end = content.write_all_batch(entry.batch_writer().await?).await?;
+    if let EndBlobNext::Closing(closing) = end.next() {
+        closing.next().await?;
+    }
+
+    Ok(())
+}
+
+async fn read(store: &Store, hash: Hash, range: Range<u64>) -> Result<Vec<u8>, String> {
+    let entry = store
+        .get_mut(&hash)
+        .await
+        .map_err(|e| format!("Failed to open blob: {}", e))?
+        .ok_or("Blob was removed")?;
+    let mut reader = entry
+        .data_reader()
+        .await
+        .map_err(|e| format!("Failed to open blob: {}", e))?;
+    let bytes = reader
+        .read_at(range.start, (range.end - range.start) as usize)
+        .await
+        .map_err(|e| format!("Failed to read blob: {}", e))?;
+
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_ranges_include_their_end() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some(0..100));
+        assert_eq!(parse_range("bytes= 10 - 19 ", 1000), Some(10..20));
+        assert_eq!(parse_range("bytes=999-999", 1000), Some(999..1000));
+    }
+
+    #[test]
+    fn open_ranges_run_to_the_end() {
+        assert_eq!(parse_range("bytes=500-", 1000), Some(500..1000));
+        assert_eq!(parse_range("bytes=0-", 1000), Some(0..1000));
+    }
+
+    #[test]
+    fn suffix_ranges_take_the_last_bytes() {
+        assert_eq!(parse_range("bytes=-100", 1000), Some(900..1000));
+        // A suffix longer than the file is the whole file
+        assert_eq!(parse_range("bytes=-5000", 1000), Some(0..1000));
+        assert_eq!(parse_range("bytes=-0", 1000), None);
+    }
+
+    #[test]
+    fn ends_are_clamped_to_the_size() {
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some(900..1000));
+        assert_eq!(
+            parse_range(&format!("bytes=0-{}", u64::MAX), 1000),
+            Some(0..1000)
+        );
+    }
+
+    #[test]
+    fn ranges_outside_the_file_are_rejected() {
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=2000-3000", 1000), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("bytes=50-10", 1000), None);
+    }
+
+    #[test]
+    fn malformed_ranges_are_rejected() {
+        assert_eq!(parse_range("items=0-99", 1000), None);
+        assert_eq!(parse_range("bytes=0", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+        assert_eq!(parse_range("bytes=-", 1000), None);
+    }
+}
@@ -1,8 +1,10 @@
 use tauri::{AppHandle, Manager};
 
+use crate::error::Error;
+
 #[tauri::command]
 #[allow(unused)]
-pub fn set_theme(theme: String, handle: AppHandle) -> Result<(), String> {
+pub fn set_theme(theme: String, handle: AppHandle) -> Result<(), Error> {
     #[cfg(target_os = "windows")]
     {
         let window = handle
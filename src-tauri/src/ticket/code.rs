@@ -0,0 +1,635 @@
+//! Short, human friendly share codes such as `7-purple-sausage`.
+//!
+//! A code never contains the ticket itself. The sender waits on a well-known
+//! gossip topic for a receiver that claims the code's nameplate (the number),
+//! and keeps announcing the nameplate so other senders pick a free one. Both
+//! sides then run a SPAKE2 exchange using the full code as the password. The
+//! receiver proves it derived the same key and only then gets the ticket,
+//! sealed with that key. A wrong guess learns nothing, and a code survives
+//! [`MAX_FAILED_ATTEMPTS`] of them before it is burned.
+//!
+//! Anyone on the topic can answer a receiver, so the receiver keeps every
+//! answer it gets as a candidate key and confirms each of them. A rejected
+//! candidate is dropped, only the sender's own answer being rejected means the
+//! code is wrong.
+//!
+//! The topic is joined through the nodes baked into the build with
+//! `SENDIT_RENDEZVOUS_NODES` (comma separated node ids) and the ones in the
+//! settings.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use iroh::NodeId;
+use iroh_blobs::{ticket::BlobTicket, Hash};
+use iroh_gossip::{
+    net::{Event, Gossip, GossipEvent, GossipSender},
+    proto::TopicId,
+};
+use log::{info, warn};
+use n0_future::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::{
+    sync::{broadcast, watch, Mutex},
+    time::{self, Instant},
+};
+
+use super::{create_header_ticket, words::WORDS};
+use crate::{
+    error::Error,
+    events, share,
+    state::{AppState, State},
+};
+
+const RENDEZVOUS_TOPIC: &[u8] = b"sendit/rendezvous/0";
+const PAKE_IDENTITY: &[u8] = b"sendit/share-code/0";
+const CONFIRM_CONTEXT: &[u8] = b"sendit/share-code/confirm";
+const MAX_NAMEPLATE: u16 = 999;
+
+/// Rendezvous nodes set at build time, joined next to the ones in the settings.
+const BOOTSTRAP_NODES: Option<&str> = option_env!("SENDIT_RENDEZVOUS_NODES");
+
+/// How long a sender keeps a code alive.
+const CODE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How long a receiver waits for the sender to answer.
+const REDEEM_TIMEOUT: Duration = Duration::from_secs(60);
+const RESEND_INTERVAL: Duration = Duration::from_secs(2);
+/// A nameplate counts as free once its sender stopped announcing it for this long.
+const CLAIM_TTL: Duration = Duration::from_secs(10);
+/// How long to wait for a rendezvous node before giving up.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+/// Exchanges a sender answers per code, more are ignored.
+const MAX_ATTEMPTS: usize = 16;
+/// Failed exchanges, wrong code or garbage, after which a code is burned.
+const MAX_FAILED_ATTEMPTS: usize = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShareCode {
+    nameplate: u16,
+    words: [&'static str; 2],
+}
+
+impl ShareCode {
+    pub fn generate(nameplate: u16) -> Self {
+        let words = [
+            WORDS[rand::random::<u8>() as usize],
+            WORDS[rand::random::<u8>() as usize],
+        ];
+        Self { nameplate, words }
+    }
+
+    fn pake(&self) -> (Spake2<Ed25519Group>, Vec<u8>) {
+        Spake2::<Ed25519Group>::start_symmetric(
+            &Password::new(self.to_string().as_bytes()),
+            &Identity::new(PAKE_IDENTITY),
+        )
+    }
+}
+
+impl fmt::Display for ShareCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.nameplate, self.words[0], self.words[1])
+    }
+}
+
+impl FromStr for ShareCode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.trim().to_lowercase();
+        let parts: Vec<&str> = normalized.split('-').collect();
+        let [nameplate, first, second] = parts.as_slice() else {
+            return Err("Share code must look like 7-purple-sausage".to_string());
+        };
+
+        let nameplate = nameplate
+            .parse::<u16>()
+            .ok()
+            .filter(|n| (1..=MAX_NAMEPLATE).contains(n))
+            .ok_or_else(|| format!("Invalid share code number: {}", nameplate))?;
+
+        let word = |w: &str| {
+            WORDS
+                .iter()
+                .find(|candidate| **candidate == w)
+                .copied()
+                .ok_or_else(|| format!("Unknown share code word: {}", w))
+        };
+
+        Ok(Self {
+            nameplate,
+            words: [word(first)?, word(second)?],
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+enum Side {
+    Sender,
+    Receiver,
+}
+
+// Gossip drops duplicate payloads, so messages that are resent carry a `seq`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+enum RendezvousMessage {
+    /// A sender waiting on `nameplate`, resent while the code is valid.
+    Claim { nameplate: u16, seq: u32 },
+    /// One side of the exchange `attempt`, picked at random by the receiver.
+    Pake {
+        nameplate: u16,
+        side: Side,
+        attempt: u64,
+        message: Vec<u8>,
+        seq: u32,
+    },
+    /// The receiver's proof that it derived the sender's key.
+    Confirm {
+        nameplate: u16,
+        attempt: u64,
+        proof: [u8; 32],
+        seq: u32,
+    },
+    /// The `proof` didn't match, it belongs to a key the sender didn't derive.
+    Rejected {
+        nameplate: u16,
+        attempt: u64,
+        proof: [u8; 32],
+    },
+    Ticket {
+        nameplate: u16,
+        attempt: u64,
+        nonce: [u8; 12],
+        ciphertext: Vec<u8>,
+    },
+}
+
+/// Membership in the rendezvous topic, shared by all code exchanges of a node.
+#[derive(Debug)]
+pub struct Rendezvous {
+    sender: GossipSender,
+    messages: broadcast::Sender<RendezvousMessage>,
+    /// Nameplates in use by any sender, with when they were last announced.
+    claimed: Arc<Mutex<HashMap<u16, Instant>>>,
+    neighbors: watch::Receiver<usize>,
+}
+
+/// The built-in rendezvous nodes followed by `extra`.
+fn bootstrap_nodes(extra: &[NodeId]) -> Vec<NodeId> {
+    let mut nodes: Vec<NodeId> = BOOTSTRAP_NODES
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| NodeId::from_str(id.trim()).ok())
+        .collect();
+    for node in extra {
+        if !nodes.contains(node) {
+            nodes.push(*node);
+        }
+    }
+    nodes
+}
+
+impl Rendezvous {
+    pub fn join(gossip: &Gossip, extra: &[NodeId]) -> Result<Self> {
+        let topic_id = TopicId::from_bytes(*Hash::new(RENDEZVOUS_TOPIC).as_bytes());
+        let bootstrap = bootstrap_nodes(extra);
+        if bootstrap.is_empty() {
+            warn!("No rendezvous nodes configured, share codes only work through peers joining us");
+        }
+        let (sender, mut receiver) = gossip.subscribe(topic_id, bootstrap)?.split();
+        let (messages, _) = broadcast::channel(64);
+        let claimed = Arc::new(Mutex::new(HashMap::new()));
+        let (neighbors_tx, neighbors) = watch::channel(0);
+
+        let tx = messages.clone();
+        let seen = Arc::clone(&claimed);
+        tokio::spawn(async move {
+            while let Some(event) = receiver.next().await {
+                match event {
+                    Ok(Event::Gossip(GossipEvent::Received(msg))) => {
+                        match serde_json::from_slice(&msg.content) {
+                            Ok(RendezvousMessage::Claim { nameplate, .. }) => {
+                                seen.lock().await.insert(nameplate, Instant::now());
+                            }
+                            Ok(msg) => {
+                                tx.send(msg).ok();
+                            }
+                            Err(_) => {}
+                        }
+                    }
+                    Ok(Event::Gossip(GossipEvent::NeighborUp(_))) => {
+                        neighbors_tx.send_modify(|n| *n += 1);
+                    }
+                    Ok(Event::Gossip(GossipEvent::NeighborDown(_))) => {
+                        neighbors_tx.send_modify(|n| *n = n.saturating_sub(1));
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("Rendezvous topic closed: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            sender,
+            messages,
+            claimed,
+            neighbors,
+        })
+    }
+
+    async fn broadcast(&self, msg: &RendezvousMessage) -> Result<()> {
+        let bytes = serde_json::to_vec(msg)?;
+        self.sender.broadcast(bytes.into()).await
+    }
+
+    /// Wait until at least one other node is on the topic, codes can't
+    /// travel otherwise.
+    async fn connected(&self) -> Result<(), String> {
+        let mut neighbors = self.neighbors.clone();
+        time::timeout(JOIN_TIMEOUT, neighbors.wait_for(|n| *n > 0))
+            .await
+            .map_err(|_| {
+                "Not connected to a rendezvous node, check the rendezvous nodes in the settings"
+                    .to_string()
+            })?
+            .map_err(|_| "Rendezvous closed".to_string())?;
+        Ok(())
+    }
+
+    /// Pick a nameplate no sender announced lately and claim it.
+    async fn claim_free_nameplate(&self) -> Option<u16> {
+        let mut claimed = self.claimed.lock().await;
+        claimed.retain(|_, seen| seen.elapsed() < CLAIM_TTL);
+
+        let free: Vec<u16> = (1..=MAX_NAMEPLATE)
+            .filter(|n| !claimed.contains_key(n))
+            .collect();
+        if free.is_empty() {
+            return None;
+        }
+        let nameplate = free[rand::random_range(0..free.len())];
+        claimed.insert(nameplate, Instant::now());
+        Some(nameplate)
+    }
+
+    /// Announce that `nameplate` is still in use.
+    async fn claim(&self, nameplate: u16, seq: u32) {
+        self.claimed.lock().await.insert(nameplate, Instant::now());
+        if let Err(e) = self
+            .broadcast(&RendezvousMessage::Claim { nameplate, seq })
+            .await
+        {
+            warn!("Failed to announce share code: {}", e);
+        }
+    }
+}
+
+fn cipher(key: &[u8]) -> ChaCha20Poly1305 {
+    // Derive a dedicated key instead of using the PAKE output directly
+    let mut material = key.to_vec();
+    material.extend_from_slice(b"sendit/share-code/ticket");
+    let key = Hash::new(material);
+    ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()))
+}
+
+fn seal(key: &[u8], plaintext: &[u8]) -> Result<([u8; 12], Vec<u8>), String> {
+    let nonce: [u8; 12] = rand::random();
+    let ciphertext = cipher(key)
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|e| format!("Failed to encrypt ticket: {}", e))?;
+    Ok((nonce, ciphertext))
+}
+
+fn open(key: &[u8], nonce: &[u8; 12], ciphertext: &[u8]) -> Result<Vec<u8>, String> {
+    cipher(key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Wrong share code".to_string())
+}
+
+/// What the receiver sends to prove it has the sender's key, without revealing it.
+fn confirmation(key: &[u8]) -> [u8; 32] {
+    let mut material = key.to_vec();
+    material.extend_from_slice(CONFIRM_CONTEXT);
+    *Hash::new(material).as_bytes()
+}
+
+/// An exchange the sender answered and waits on a confirmation for.
+struct Attempt {
+    key: Vec<u8>,
+    outbound: Vec<u8>,
+}
+
+/// Wait for a receiver to claim `code` and hand it the sealed ticket.
+async fn serve_code(
+    rendezvous: &Rendezvous,
+    code: &ShareCode,
+    ticket: &BlobTicket,
+) -> Result<(), String> {
+    let mut rx = rendezvous.messages.subscribe();
+    let mut announce = time::interval(RESEND_INTERVAL);
+    let mut attempts: HashMap<u64, Attempt> = HashMap::new();
+    let mut started = HashSet::new();
+    // An exchange is one guess at the code, however many wrong proofs it sees
+    let mut rejected = HashSet::new();
+    let mut failed = 0;
+    let mut seq = 0;
+
+    loop {
+        let msg = tokio::select! {
+            _ = announce.tick() => {
+                seq += 1;
+                rendezvous.claim(code.nameplate, seq).await;
+                continue;
+            }
+            msg = rx.recv() => match msg {
+                Ok(msg) => msg,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(e) => return Err(format!("Rendezvous closed: {}", e)),
+            },
+        };
+
+        let reply = match msg {
+            RendezvousMessage::Pake {
+                nameplate,
+                side: Side::Receiver,
+                attempt,
+                message,
+                ..
+            } if nameplate == code.nameplate => {
+                if !attempts.contains_key(&attempt) {
+                    // Answered attempts stay in `started`, a replay doesn't get a new key
+                    if started.len() >= MAX_ATTEMPTS || !started.insert(attempt) {
+                        continue;
+                    }
+                    let (pake, outbound) = code.pake();
+                    match pake.finish(&message) {
+                        Ok(key) => {
+                            attempts.insert(attempt, Attempt { key, outbound });
+                        }
+                        Err(e) => {
+                            warn!("Ignoring invalid share code exchange: {}", e);
+                            failed += 1;
+                        }
+                    }
+                }
+
+                // The receiver resends until it hears back, so answer every time
+                attempts.get(&attempt).map(|a| {
+                    seq += 1;
+                    RendezvousMessage::Pake {
+                        nameplate,
+                        side: Side::Sender,
+                        attempt,
+                        message: a.outbound.clone(),
+                        seq,
+                    }
+                })
+            }
+
+            RendezvousMessage::Confirm {
+                nameplate,
+                attempt,
+                proof,
+                ..
+            } if nameplate == code.nameplate => {
+                // A wrong proof may be for a key someone else injected, the
+                // receiver can still confirm the right one
+                let Some(a) = attempts.get(&attempt) else {
+                    continue;
+                };
+
+                if proof == confirmation(&a.key) {
+                    let (nonce, ciphertext) = seal(&a.key, ticket.to_string().as_bytes())?;
+                    let msg = RendezvousMessage::Ticket {
+                        nameplate,
+                        attempt,
+                        nonce,
+                        ciphertext,
+                    };
+                    rendezvous
+                        .broadcast(&msg)
+                        .await
+                        .map_err(|e| format!("Failed to send to rendezvous: {}", e))?;
+                    return Ok(());
+                }
+
+                if rejected.insert(attempt) {
+                    warn!("Share code claimed with the wrong code");
+                    failed += 1;
+                }
+                Some(RendezvousMessage::Rejected {
+                    nameplate,
+                    attempt,
+                    proof,
+                })
+            }
+
+            _ => None,
+        };
+
+        if failed >= MAX_FAILED_ATTEMPTS {
+            return Err("Too many wrong attempts for this share code".to_string());
+        }
+
+        if let Some(reply) = reply {
+            if let Err(e) = rendezvous.broadcast(&reply).await {
+                warn!("Failed to send to rendezvous: {}", e);
+            }
+        }
+    }
+}
+
+/// A key derived from one answer to the receiver's exchange, which may not
+/// come from the sender.
+struct Candidate {
+    /// The answer the key was derived from.
+    inbound: Vec<u8>,
+    key: Vec<u8>,
+}
+
+/// Claim `code` on the rendezvous topic and return the ticket it stands for.
+async fn redeem_code(rendezvous: &Rendezvous, code: &ShareCode) -> Result<BlobTicket, String> {
+    let mut rx = rendezvous.messages.subscribe();
+    let attempt: u64 = rand::random();
+    let (pake, outbound) = code.pake();
+
+    // The exchange is finished against a copy for each answer, until one is confirmed
+    let mut candidates: Vec<Candidate> = Vec::new();
+    let mut rejected: HashSet<Vec<u8>> = HashSet::new();
+    let mut resend = time::interval(RESEND_INTERVAL);
+    let mut seq = 0;
+
+    loop {
+        tokio::select! {
+            _ = resend.tick() => {
+                let msgs: Vec<_> = match candidates.is_empty() {
+                    true => {
+                        seq += 1;
+                        vec![RendezvousMessage::Pake {
+                            nameplate: code.nameplate,
+                            side: Side::Receiver,
+                            attempt,
+                            message: outbound.clone(),
+                            seq,
+                        }]
+                    }
+                    false => candidates
+                        .iter()
+                        .map(|candidate| {
+                            seq += 1;
+                            RendezvousMessage::Confirm {
+                                nameplate: code.nameplate,
+                                attempt,
+                                proof: confirmation(&candidate.key),
+                                seq,
+                            }
+                        })
+                        .collect(),
+                };
+                for msg in msgs {
+                    if let Err(e) = rendezvous.broadcast(&msg).await {
+                        warn!("Failed to send to rendezvous: {}", e);
+                    }
+                }
+            }
+
+            msg = rx.recv() => match msg {
+                Ok(RendezvousMessage::Pake { nameplate, side: Side::Sender, attempt: a, message, .. })
+                    if nameplate == code.nameplate && a == attempt =>
+                {
+                    // The sender answers every resend with the same message, so an
+                    // answer that was rejected before comes from the sender itself
+                    if rejected.contains(&message) {
+                        return Err("Wrong share code".to_string());
+                    }
+                    if candidates.len() >= MAX_FAILED_ATTEMPTS
+                        || candidates.iter().any(|c| c.inbound == message)
+                    {
+                        continue;
+                    }
+                    match pake.clone().finish(&message) {
+                        Ok(key) => {
+                            candidates.push(Candidate { inbound: message, key });
+                            // Confirm right away instead of on the next tick
+                            resend.reset_immediately();
+                        }
+                        Err(e) => warn!("Ignoring invalid key exchange message: {}", e),
+                    }
+                }
+                Ok(RendezvousMessage::Rejected { nameplate, attempt: a, proof })
+                    if nameplate == code.nameplate && a == attempt =>
+                {
+                    // Ask again once every candidate is gone, the sender's answer
+                    // tells whether the code itself is wrong
+                    let position = candidates.iter().position(|c| confirmation(&c.key) == proof);
+                    if let Some(i) = position {
+                        rejected.insert(candidates.swap_remove(i).inbound);
+                    }
+                }
+                Ok(RendezvousMessage::Ticket { nameplate, attempt: a, nonce, ciphertext })
+                    if nameplate == code.nameplate && a == attempt =>
+                {
+                    let Some(bytes) = candidates
+                        .iter()
+                        .find_map(|c| open(&c.key, &nonce, &ciphertext).ok())
+                    else {
+                        warn!("Ignoring a share code ticket sealed with another key");
+                        continue;
+                    };
+                    let ticket = String::from_utf8(bytes)
+                        .map_err(|e| format!("Failed to read ticket: {}", e))?;
+                    return BlobTicket::from_str(&ticket)
+                        .map_err(|e| format!("Failed to parse ticket: {}", e));
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(e) => return Err(format!("Rendezvous closed: {}", e)),
+            }
+        }
+    }
+}
+
+/// Create a share code for the current files. The code stays valid until it is
+/// claimed once or times out.
+#[tauri::command]
+pub async fn create_share_code(state: State<'_>, handle: AppHandle) -> Result<String, Error> {
+    let rendezvous = &state.iroh().rendezvous;
+    rendezvous
+        .connected()
+        .await
+        .map_err(|message| Error::Connection { message })?;
+
+    let ticket = create_header_ticket(&state).await?;
+    share::save(&handle);
+    let nameplate = rendezvous
+        .claim_free_nameplate()
+        .await
+        .ok_or_else(|| Error::Connection {
+            message: "Every share code is in use, try again in a moment".to_string(),
+        })?;
+    let code = ShareCode::generate(nameplate);
+    info!("Created share code with nameplate {}", code.nameplate);
+
+    let code_str = code.to_string();
+    tokio::spawn(async move {
+        let state = handle.state::<AppState>();
+        let rendezvous = &state.iroh().rendezvous;
+
+        let res = time::timeout(CODE_TIMEOUT, serve_code(rendezvous, &code, &ticket)).await;
+        let (event, payload) = match res {
+            Ok(Ok(())) => (events::SHARE_CODE_CLAIMED, None),
+            Ok(Err(e)) => (events::SHARE_CODE_EXPIRED, Some(e)),
+            Err(_) => (
+                events::SHARE_CODE_EXPIRED,
+                Some("Share code timed out".to_string()),
+            ),
+        };
+
+        let payload = events::ShareCodeStatus {
+            code: code.to_string(),
+            reason: payload,
+        };
+        handle.emit(event, payload).ok();
+    });
+
+    Ok(code_str)
+}
+
+/// Resolve a share code to the ticket it stands for.
+#[tauri::command]
+pub async fn redeem_share_code(state: State<'_>, code: String) -> Result<String, Error> {
+    let code = ShareCode::from_str(&code).map_err(|reason| Error::InvalidInput {
+        what: "share code",
+        reason,
+    })?;
+
+    let iroh = match cfg!(debug_assertions) {
+        true => &state.iroh_debug,
+        false => state.iroh(),
+    };
+
+    iroh.rendezvous
+        .connected()
+        .await
+        .map_err(|message| Error::Connection { message })?;
+
+    let ticket = time::timeout(REDEEM_TIMEOUT, redeem_code(&iroh.rendezvous, &code))
+        .await
+        .map_err(|_| Error::Connection {
+            message: "No sender answered for this share code".to_string(),
+        })??;
+
+    Ok(ticket.to_string())
+}
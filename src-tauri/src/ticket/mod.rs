@@ -1,24 +1,127 @@
-use crate::state::State;
+use std::{fmt, str::FromStr};
+
+use crate::error::Error;
+use crate::files::{collection, Files};
+use crate::share::{
+    self,
+    pointer::{self, NamedTicket},
+};
+use crate::state::{AppState, State};
+use crate::storage;
+use iroh::Endpoint;
+use iroh_blobs::{ticket::BlobTicket, util::SetTagOption, BlobFormat, Hash, Tag};
 use log::info;
+use tauri::AppHandle;
+
+pub mod code;
+pub mod qr;
+mod words;
+
+/// Any ticket a user can paste: a snapshot of a share or a named share.
+#[derive(Debug, Clone)]
+pub enum AnyTicket {
+    Blob(BlobTicket),
+    Named(NamedTicket),
+}
+
+impl FromStr for AnyTicket {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ticket) = NamedTicket::from_str(s) {
+            return Ok(Self::Named(ticket));
+        }
+        BlobTicket::from_str(s)
+            .map(Self::Blob)
+            .map_err(|e| Error::InvalidTicket {
+                reason: e.to_string(),
+            })
+    }
+}
+
+impl fmt::Display for AnyTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Blob(ticket) => write!(f, "{}", ticket),
+            Self::Named(ticket) => write!(f, "{}", ticket),
+        }
+    }
+}
+
+impl AnyTicket {
+    /// The collection the ticket currently points at. Named shares are asked for
+    /// their latest revision.
+    pub async fn resolve(self, endpoint: &Endpoint) -> Result<BlobTicket, Error> {
+        match self {
+            Self::Blob(ticket) => Ok(ticket),
+            Self::Named(ticket) => {
+                let record = pointer::resolve(endpoint, &ticket).await?;
+                info!("Resolved named share to revision {}", record.revision);
+                BlobTicket::new(ticket.addr, record.root, BlobFormat::HashSeq).map_err(|e| {
+                    Error::InvalidTicket {
+                        reason: e.to_string(),
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Publish the current files as a collection and return a ticket pointing at it.
+pub async fn create_header_ticket(state: &AppState) -> Result<BlobTicket, String> {
+    let stale = state.sources.lock().await.stale_files().await;
+    if !stale.is_empty() {
+        return Err(format!(
+            "Files changed since they were added, re-import or remove them: {}",
+            stale.join(", ")
+        ));
+    }
 
-#[tauri::command]
-pub async fn generate_ticket(state: State<'_>) -> Result<String, String> {
-    info!("Generating ticket");
     let files = state.files().await;
-    let header_str = files.to_string();
+    let ticket = publish_collection(state, &files, storage::share_header_tag).await?;
+
+    let mut tickets = state.header_tickets.lock().await;
+    tickets.push(ticket.clone());
+
+    Ok(ticket)
+}
 
-    let res = state
-        .iroh()
-        .blobs
-        .add_bytes(header_str)
+/// Store `files` as a share collection tagged with `header_tag(manifest)` and
+/// return a ticket pointing at it.
+pub async fn publish_collection(
+    state: &AppState,
+    files: &Files,
+    header_tag: impl Fn(Hash) -> Tag,
+) -> Result<BlobTicket, String> {
+    let blobs = &state.iroh().blobs;
+
+    let manifest = files.to_bytes();
+    let manifest_tag = header_tag(Hash::new(&manifest));
+    let res = blobs
+        .add_bytes_named(manifest, manifest_tag.clone())
         .await
         .map_err(|e| format!("Failed to add header file: {}", e))?;
 
-    let ticket = iroh_blobs::ticket::BlobTicket::new(state.iroh().node_addr.clone(), res.hash, res.format)
+    // The collection protects the manifest from here on, so it takes over its tag.
+    // Setting the tag replaces it, deleting it afterwards would leave the root untagged.
+    let collection = collection::build(files, res.hash);
+    let (root, _) = blobs
+        .create_collection(collection, SetTagOption::Named(manifest_tag), vec![])
+        .await
+        .map_err(|e| format!("Failed to create share collection: {}", e))?;
+
+    let ticket = BlobTicket::new(state.iroh().node_addr.clone(), root, BlobFormat::HashSeq)
         .map_err(|e| format!("Failed to create ticket: {}", e))?;
 
-    let mut tickets = state.header_tickets.lock().await;
-    tickets.push(ticket.clone());
+    state.blob_access.lock().await.touch([res.hash, root]);
+
+    Ok(ticket)
+}
 
+#[tauri::command]
+pub async fn generate_ticket(state: State<'_>, handle: AppHandle) -> Result<String, Error> {
+    info!("Generating ticket");
+    let ticket = create_header_ticket(&state).await?;
+    share::save(&handle);
     Ok(ticket.to_string())
-}
\ No newline at end of file
+}
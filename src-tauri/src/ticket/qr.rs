@@ -0,0 +1,112 @@
+use std::str::FromStr;
+
+use image::{DynamicImage, GrayImage, Luma};
+use log::info;
+use qrcode::{Color, QrCode};
+use tauri::AppHandle;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+use super::AnyTicket;
+use crate::{error::Error, utils};
+
+/// Pixels per QR module.
+const MODULE_SIZE: u32 = 4;
+/// Quiet zone around the code, in modules.
+const QUIET_ZONE: u32 = 4;
+
+fn render(text: &str) -> Result<DynamicImage, String> {
+    let code =
+        QrCode::new(text.as_bytes()).map_err(|e| format!("Failed to encode QR code: {}", e))?;
+
+    let width = code.width() as u32;
+    let size = (width + QUIET_ZONE * 2) * MODULE_SIZE;
+    let colors = code.to_colors();
+
+    let image = GrayImage::from_fn(size, size, |x, y| {
+        let (mx, my) = (x / MODULE_SIZE, y / MODULE_SIZE);
+        let inside = (QUIET_ZONE..QUIET_ZONE + width).contains(&mx)
+            && (QUIET_ZONE..QUIET_ZONE + width).contains(&my);
+
+        let dark = inside
+            && colors[((my - QUIET_ZONE) * width + (mx - QUIET_ZONE)) as usize] == Color::Dark;
+
+        match dark {
+            true => Luma([0]),
+            false => Luma([255]),
+        }
+    });
+
+    Ok(DynamicImage::ImageLuma8(image))
+}
+
+fn decode(image: &GrayImage) -> Result<AnyTicket, Error> {
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        image.width() as usize,
+        image.height() as usize,
+        |x, y| image.get_pixel(x as u32, y as u32).0[0],
+    );
+
+    // Use the first grid that holds a valid ticket, there may be other codes in the image
+    for grid in prepared.detect_grids() {
+        let Ok((_, content)) = grid.decode() else {
+            continue;
+        };
+
+        if let Ok(ticket) = AnyTicket::from_str(content.trim().to_lowercase().as_str()) {
+            return Ok(ticket);
+        }
+    }
+
+    Err(Error::InvalidTicket {
+        reason: "No ticket QR code found in image".to_string(),
+    })
+}
+
+/// Run image work on a blocking thread so it doesn't hold up the app.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| format!("QR code task failed: {}", e))?
+}
+
+#[tauri::command]
+pub async fn ticket_to_qr(ticket: String) -> Result<String, Error> {
+    let ticket = AnyTicket::from_str(&ticket)?;
+
+    // Tickets are lowercase base32. Uppercase fits the denser alphanumeric QR mode.
+    let text = ticket.to_string().to_uppercase();
+    blocking(move || Ok(utils::png_data_url(&render(&text)?)?)).await
+}
+
+#[tauri::command]
+pub async fn decode_qr_from_file(path: String) -> Result<String, Error> {
+    info!("Decoding QR code from: {}", path);
+    blocking(move || {
+        let image = image::open(&path)
+            .map_err(|e| format!("Failed to open image: {}", e))?
+            .to_luma8();
+
+        decode(&image).map(|ticket| ticket.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn decode_qr_from_clipboard(app: AppHandle) -> Result<String, Error> {
+    let clip = app
+        .clipboard()
+        .read_image()
+        .map_err(|e| format!("Failed to read image from clipboard: {}", e))?;
+    let (width, height, rgba) = (clip.width(), clip.height(), clip.rgba().to_vec());
+
+    blocking(move || {
+        let image = image::RgbaImage::from_raw(width, height, rgba)
+            .map(|image| DynamicImage::ImageRgba8(image).to_luma8())
+            .ok_or("Failed to convert clipboard image")?;
+
+        decode(&image).map(|ticket| ticket.to_string())
+    })
+    .await
+}
@@ -0,0 +1,259 @@
+/// Words used for share codes. 256 entries, so each word carries one byte.
+pub const WORDS: [&str; 256] = [
+    "acid",
+    "acorn",
+    "actor",
+    "adobe",
+    "agent",
+    "album",
+    "alert",
+    "alien",
+    "alpha",
+    "amber",
+    "anchor",
+    "angel",
+    "apple",
+    "apron",
+    "arena",
+    "arrow",
+    "aspen",
+    "atlas",
+    "attic",
+    "autumn",
+    "avocado",
+    "bacon",
+    "badge",
+    "bagel",
+    "bamboo",
+    "banana",
+    "banjo",
+    "barrel",
+    "basil",
+    "basket",
+    "beacon",
+    "beaver",
+    "berry",
+    "bicycle",
+    "bison",
+    "blanket",
+    "blossom",
+    "border",
+    "bottle",
+    "bounce",
+    "bracket",
+    "breeze",
+    "brick",
+    "bridge",
+    "broccoli",
+    "bubble",
+    "bucket",
+    "buffalo",
+    "bunny",
+    "butter",
+    "cabin",
+    "cactus",
+    "camel",
+    "canal",
+    "candle",
+    "canoe",
+    "canyon",
+    "carbon",
+    "carpet",
+    "carrot",
+    "castle",
+    "cedar",
+    "cello",
+    "cement",
+    "cherry",
+    "chimney",
+    "circus",
+    "citrus",
+    "clover",
+    "cobalt",
+    "coconut",
+    "comet",
+    "copper",
+    "coral",
+    "cotton",
+    "cousin",
+    "coyote",
+    "crayon",
+    "cricket",
+    "crystal",
+    "cupcake",
+    "dagger",
+    "daisy",
+    "delta",
+    "denim",
+    "desert",
+    "diesel",
+    "dinner",
+    "dolphin",
+    "donkey",
+    "dragon",
+    "drum",
+    "eagle",
+    "echo",
+    "eclipse",
+    "elbow",
+    "ember",
+    "emerald",
+    "engine",
+    "falcon",
+    "feather",
+    "fennel",
+    "ferry",
+    "fiddle",
+    "finch",
+    "flannel",
+    "flute",
+    "forest",
+    "fossil",
+    "fountain",
+    "fox",
+    "galaxy",
+    "garden",
+    "garlic",
+    "gecko",
+    "ginger",
+    "glacier",
+    "goblin",
+    "gopher",
+    "granite",
+    "grape",
+    "gravel",
+    "guitar",
+    "hammer",
+    "harbor",
+    "hazel",
+    "helmet",
+    "hermit",
+    "hickory",
+    "honey",
+    "hornet",
+    "iceberg",
+    "igloo",
+    "indigo",
+    "iris",
+    "island",
+    "ivory",
+    "jacket",
+    "jaguar",
+    "jasmine",
+    "jelly",
+    "jigsaw",
+    "jungle",
+    "kayak",
+    "kernel",
+    "kettle",
+    "kiwi",
+    "koala",
+    "ladder",
+    "lagoon",
+    "lantern",
+    "lemon",
+    "lily",
+    "lizard",
+    "llama",
+    "lobster",
+    "locket",
+    "magnet",
+    "mango",
+    "maple",
+    "marble",
+    "meadow",
+    "melon",
+    "meteor",
+    "mint",
+    "mirror",
+    "mocha",
+    "monkey",
+    "moose",
+    "mosaic",
+    "muffin",
+    "mustard",
+    "napkin",
+    "nectar",
+    "nickel",
+    "noodle",
+    "nutmeg",
+    "oasis",
+    "ocean",
+    "olive",
+    "onion",
+    "orbit",
+    "orchid",
+    "otter",
+    "oyster",
+    "paddle",
+    "panda",
+    "papaya",
+    "parrot",
+    "peanut",
+    "pebble",
+    "pelican",
+    "pepper",
+    "pickle",
+    "pigeon",
+    "pillow",
+    "pine",
+    "pirate",
+    "pistachio",
+    "planet",
+    "plum",
+    "pocket",
+    "polar",
+    "poppy",
+    "potato",
+    "pretzel",
+    "puffin",
+    "pumpkin",
+    "purple",
+    "quartz",
+    "quill",
+    "rabbit",
+    "radish",
+    "raven",
+    "ribbon",
+    "river",
+    "robin",
+    "rocket",
+    "saddle",
+    "salmon",
+    "sausage",
+    "scarlet",
+    "shadow",
+    "sierra",
+    "silver",
+    "spruce",
+    "squid",
+    "summit",
+    "sunset",
+    "tango",
+    "teapot",
+    "thunder",
+    "tiger",
+    "timber",
+    "tomato",
+    "topaz",
+    "tulip",
+    "tundra",
+    "turnip",
+    "turtle",
+    "umbrella",
+    "velvet",
+    "violet",
+    "waffle",
+    "walnut",
+    "walrus",
+    "wasabi",
+    "whistle",
+    "willow",
+    "winter",
+    "wizard",
+    "yarn",
+    "yogurt",
+    "zebra",
+    "zephyr",
+    "zigzag",
+];
@@ -0,0 +1,663 @@
+//! Who fetched what from this node.
+//!
+//! The blobs protocol is wrapped so every connection is known by the
+//! receiver's node id, and the provider's events are forwarded to
+//! [`handle_events`]. Each request is attributed to the open share whose ticket,
+//! file or icon it asks for, requests for anything else are not counted.
+//!
+//! Requests for blobs of a share limited to its recipients are checked before
+//! the provider answers, and the connection of any other node is closed.
+//!
+//! Stats are kept per receiver and share since the app started and emitted as
+//! they change. When a receiver disconnects, what it fetched during that
+//! connection is written to the history.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use iroh::{
+    endpoint::{get_remote_node_id, Connection},
+    protocol::ProtocolHandler,
+    NodeId,
+};
+use iroh_blobs::{
+    net_protocol::Blobs,
+    provider::{CustomEventSender, Event},
+    store::fs::Store,
+    Hash,
+};
+use log::warn;
+use n0_future::boxed::BoxFuture;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::mpsc;
+
+use crate::{
+    error::Error,
+    events,
+    history::{self, HistoryEvent},
+    state::{AppState, State},
+    storage, utils,
+};
+
+/// How often progress alone is reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+pub enum UploadEvent {
+    Connected { connection_id: u64, node_id: NodeId },
+    Provider(Event),
+    Closed { connection_id: u64 },
+}
+
+/// Decides which node may fetch what, shared by the protocol and the event sender.
+#[derive(Debug, Default)]
+pub struct Gate {
+    /// Set once the shares are restored, nothing is served before.
+    handle: OnceLock<AppHandle>,
+    connections: Mutex<HashMap<u64, Connection>>,
+}
+
+impl Gate {
+    pub fn serve(&self, handle: AppHandle) {
+        self.handle.set(handle).ok();
+    }
+
+    /// Whether the request for `hash` on `connection_id` may be answered. Closes
+    /// the connection if it may not. Fails closed: unknown connections and
+    /// requests before the shares are restored are refused.
+    async fn check(&self, connection_id: u64, hash: Hash) -> bool {
+        let Some(connection) = self
+            .connections
+            .lock()
+            .ok()
+            .and_then(|c| c.get(&connection_id).cloned())
+        else {
+            warn!("Refused {} on unknown connection {}", hash, connection_id);
+            return false;
+        };
+        let Some(state) = self.handle.get().and_then(|h| h.try_state::<AppState>()) else {
+            connection.close(1u32.into(), b"not ready");
+            return false;
+        };
+        let Ok(node_id) = get_remote_node_id(&connection) else {
+            connection.close(1u32.into(), b"unknown node");
+            return false;
+        };
+
+        if may_fetch(&state, node_id, hash).await {
+            return true;
+        }
+        warn!("Refused {} to {}, it isn't a recipient", hash, node_id);
+        connection.close(1u32.into(), b"not a recipient");
+        false
+    }
+}
+
+/// Forwards the blobs provider's events to [`handle_events`].
+#[derive(Debug, Clone)]
+pub struct UploadEventSender {
+    tx: mpsc::UnboundedSender<UploadEvent>,
+    gate: Arc<Gate>,
+}
+
+impl UploadEventSender {
+    pub fn new(tx: mpsc::UnboundedSender<UploadEvent>, gate: Arc<Gate>) -> Self {
+        Self { tx, gate }
+    }
+}
+
+impl CustomEventSender for UploadEventSender {
+    fn send(&self, event: Event) -> BoxFuture<()> {
+        let tx = self.tx.clone();
+        let gate = Arc::clone(&self.gate);
+        // The provider waits for this before it answers the request
+        Box::pin(async move {
+            if let Event::GetRequestReceived {
+                connection_id,
+                hash,
+                ..
+            } = &event
+            {
+                if !gate.check(*connection_id, *hash).await {
+                    return;
+                }
+            }
+            tx.send(UploadEvent::Provider(event)).ok();
+        })
+    }
+
+    fn try_send(&self, event: Event) {
+        if let Event::GetRequestReceived { .. } = &event {
+            tokio::spawn(self.send(event));
+            return;
+        }
+        self.tx.send(UploadEvent::Provider(event)).ok();
+    }
+}
+
+/// The blobs protocol, reporting which node each connection belongs to.
+#[derive(Debug, Clone)]
+pub struct TrackedBlobs {
+    inner: Blobs<Store>,
+    tx: mpsc::UnboundedSender<UploadEvent>,
+    gate: Arc<Gate>,
+}
+
+impl TrackedBlobs {
+    pub fn new(
+        inner: Blobs<Store>,
+        tx: mpsc::UnboundedSender<UploadEvent>,
+        gate: Arc<Gate>,
+    ) -> Self {
+        Self { inner, tx, gate }
+    }
+}
+
+impl ProtocolHandler for TrackedBlobs {
+    fn accept(&self, connection: Connection) -> BoxFuture<Result<()>> {
+        let inner = self.inner.clone();
+        let tx = self.tx.clone();
+        let gate = Arc::clone(&self.gate);
+        Box::pin(async move {
+            // The provider identifies connections by their stable id
+            let connection_id = connection.stable_id() as u64;
+            let node_id = get_remote_node_id(&connection)?;
+            tx.send(UploadEvent::Connected {
+                connection_id,
+                node_id,
+            })
+            .ok();
+
+            if let Ok(mut connections) = gate.connections.lock() {
+                connections.insert(connection_id, connection.clone());
+            }
+            let res = inner.accept(connection).await;
+            if let Ok(mut connections) = gate.connections.lock() {
+                connections.remove(&connection_id);
+            }
+            tx.send(UploadEvent::Closed { connection_id }).ok();
+            res
+        })
+    }
+
+    fn shutdown(&self) -> BoxFuture<()> {
+        self.inner.shutdown()
+    }
+}
+
+#[derive(Debug)]
+pub struct UploadChannel {
+    receiver: Option<mpsc::UnboundedReceiver<UploadEvent>>,
+    gate: Arc<Gate>,
+}
+
+impl UploadChannel {
+    pub fn new(receiver: mpsc::UnboundedReceiver<UploadEvent>, gate: Arc<Gate>) -> Self {
+        Self {
+            receiver: Some(receiver),
+            gate,
+        }
+    }
+
+    pub fn gate(&self) -> &Gate {
+        &self.gate
+    }
+
+    pub fn take_receiver(&mut self) -> Result<mpsc::UnboundedReceiver<UploadEvent>> {
+        self.receiver
+            .take()
+            .ok_or(anyhow::anyhow!("Receiver already taken"))
+    }
+}
+
+/// What a receiver fetched from one share.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiverStats {
+    pub node_id: String,
+    /// [`storage::OUTGOING_SHARE`] for the send page, otherwise the share id.
+    pub share: String,
+    /// Files of the share the receiver fetched completely.
+    pub files_sent: usize,
+    pub files_total: usize,
+    pub bytes_sent: u64,
+    pub transfers_active: usize,
+    pub transfers_completed: usize,
+    pub transfers_aborted: usize,
+    pub connected: bool,
+    /// Unix timestamp in seconds.
+    pub last_seen: u64,
+    #[serde(skip)]
+    sent: HashSet<Hash>,
+}
+
+#[derive(Debug, Default)]
+struct Session {
+    files: HashSet<Hash>,
+    files_total: usize,
+    bytes: u64,
+}
+
+#[derive(Debug)]
+struct Peer {
+    node_id: NodeId,
+    /// What this connection fetched, per share.
+    sessions: HashMap<String, Session>,
+}
+
+#[derive(Debug)]
+struct Request {
+    share: String,
+    /// File blobs of the share when the request came in.
+    files: HashSet<Hash>,
+    /// Blob being sent and how far.
+    hash: Hash,
+    offset: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Tracker {
+    peers: HashMap<u64, Peer>,
+    requests: HashMap<(u64, u64), Request>,
+    stats: HashMap<(NodeId, String), ReceiverStats>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl Tracker {
+    pub fn stats(&self) -> Vec<ReceiverStats> {
+        let mut stats = self.stats.values().cloned().collect::<Vec<_>>();
+        stats.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        stats
+    }
+
+    /// Nodes that fetched from `share`.
+    pub fn receivers(&self, share: &str) -> Vec<NodeId> {
+        self.stats
+            .keys()
+            .filter(|(_, s)| s == share)
+            .map(|(node_id, _)| *node_id)
+            .collect()
+    }
+
+    fn entry(&mut self, node_id: NodeId, share: &str) -> &mut ReceiverStats {
+        let entry = self
+            .stats
+            .entry((node_id, share.to_string()))
+            .or_insert_with(|| ReceiverStats {
+                node_id: node_id.to_string(),
+                share: share.to_string(),
+                files_sent: 0,
+                files_total: 0,
+                bytes_sent: 0,
+                transfers_active: 0,
+                transfers_completed: 0,
+                transfers_aborted: 0,
+                connected: true,
+                last_seen: 0,
+                sent: HashSet::new(),
+            });
+        entry.last_seen = now();
+        entry
+    }
+
+    /// Count `bytes` of `hash` as sent for a request. Returns the updated stats.
+    fn sent(
+        &mut self,
+        key: (u64, u64),
+        hash: Hash,
+        bytes: u64,
+        completed: bool,
+    ) -> Option<ReceiverStats> {
+        let request = self.requests.get_mut(&key)?;
+        let peer = self.peers.get_mut(&key.0)?;
+
+        if request.hash != hash {
+            request.hash = hash;
+            request.offset = 0;
+        }
+        let delta = bytes.saturating_sub(request.offset);
+        request.offset = if completed { 0 } else { bytes };
+        let is_file = completed && request.files.contains(&hash);
+
+        let session = peer.sessions.entry(request.share.clone()).or_default();
+        session.bytes += delta;
+        if is_file {
+            session.files.insert(hash);
+        }
+
+        let (node_id, share) = (peer.node_id, request.share.clone());
+        let stats = self.entry(node_id, &share);
+        stats.bytes_sent += delta;
+        if is_file {
+            stats.sent.insert(hash);
+            stats.files_sent = stats.sent.len();
+        }
+        Some(stats.clone())
+    }
+
+    fn finished(&mut self, key: (u64, u64), completed: bool) -> Option<ReceiverStats> {
+        let request = self.requests.remove(&key)?;
+        let node_id = self.peers.get(&key.0)?.node_id;
+
+        let stats = self.entry(node_id, &request.share);
+        stats.transfers_active = stats.transfers_active.saturating_sub(1);
+        match completed {
+            true => stats.transfers_completed += 1,
+            false => stats.transfers_aborted += 1,
+        }
+        Some(stats.clone())
+    }
+}
+
+/// Whether the send page serves `hash`, to anyone.
+async fn outgoing_serves(state: &AppState, hash: Hash) -> bool {
+    state.files().await.values().any(|file| file.uses(hash))
+        || state
+            .header_tickets
+            .lock()
+            .await
+            .iter()
+            .any(|ticket| ticket.hash() == hash)
+}
+
+/// Whether `node_id` may fetch `hash`. Blobs of shares limited to their
+/// recipients are only served to them, unless something open to everyone
+/// serves them too. Anything no share serves is left to the provider.
+pub async fn may_fetch(state: &AppState, node_id: NodeId, hash: Hash) -> bool {
+    let restricted = {
+        let shares = state.shares.lock().await;
+        let mut restricted = false;
+        for share in shares.values().filter(|share| share.serves(hash)) {
+            if share.admits(node_id) {
+                return true;
+            }
+            restricted = true;
+        }
+        restricted
+    };
+    !restricted || outgoing_serves(state, hash).await
+}
+
+/// The open share serving `hash`, with the hashes of its files.
+async fn share_of(state: &AppState, hash: Hash) -> Option<(String, HashSet<Hash>)> {
+    if outgoing_serves(state, hash).await {
+        let files = state.files().await;
+        let hashes = files.values().map(|file| file.hash).collect();
+        return Some((storage::OUTGOING_SHARE.to_string(), hashes));
+    }
+
+    let shares = state.shares.lock().await;
+    shares
+        .iter()
+        .find(|(_, share)| share.serves(hash))
+        .map(|(id, share)| (id.clone(), share.file_hashes()))
+}
+
+/// Keeps the upload stats up to date until the channel closes.
+pub async fn handle_events(handle: AppHandle, mut rx: mpsc::UnboundedReceiver<UploadEvent>) {
+    let mut throttle = utils::Throttle::new(PROGRESS_INTERVAL);
+
+    while let Some(event) = rx.recv().await {
+        let state = handle.state::<AppState>();
+
+        let updated = match event {
+            UploadEvent::Connected {
+                connection_id,
+                node_id,
+            } => {
+                state.uploads.lock().await.peers.insert(
+                    connection_id,
+                    Peer {
+                        node_id,
+                        sessions: HashMap::new(),
+                    },
+                );
+                None
+            }
+            UploadEvent::Closed { connection_id } => {
+                closed(&handle, connection_id).await;
+                continue;
+            }
+            UploadEvent::Provider(Event::GetRequestReceived {
+                connection_id,
+                request_id,
+                hash,
+                ..
+            }) => {
+                let Some((share, files)) = share_of(&state, hash).await else {
+                    continue;
+                };
+                let mut tracker = state.uploads.lock().await;
+                let Some(peer) = tracker.peers.get_mut(&connection_id) else {
+                    continue;
+                };
+                let node_id = peer.node_id;
+                peer.sessions.entry(share.clone()).or_default().files_total = files.len();
+
+                let stats = tracker.entry(node_id, &share);
+                stats.files_total = files.len();
+                stats.transfers_active += 1;
+                stats.connected = true;
+                let stats = stats.clone();
+
+                let request = Request {
+                    share,
+                    files,
+                    hash,
+                    offset: 0,
+                };
+                tracker
+                    .requests
+                    .insert((connection_id, request_id), request);
+                Some(stats)
+            }
+            UploadEvent::Provider(Event::TransferProgress {
+                connection_id,
+                request_id,
+                hash,
+                end_offset,
+                ..
+            }) => {
+                let stats = state.uploads.lock().await.sent(
+                    (connection_id, request_id),
+                    hash,
+                    end_offset,
+                    false,
+                );
+                stats.filter(|_| throttle.is_free())
+            }
+            UploadEvent::Provider(Event::TransferBlobCompleted {
+                connection_id,
+                request_id,
+                hash,
+                size,
+                ..
+            }) => {
+                state.blob_access.lock().await.touch([hash]);
+                state
+                    .uploads
+                    .lock()
+                    .await
+                    .sent((connection_id, request_id), hash, size, true)
+            }
+            UploadEvent::Provider(Event::TransferCompleted {
+                connection_id,
+                request_id,
+                ..
+            }) => state
+                .uploads
+                .lock()
+                .await
+                .finished((connection_id, request_id), true),
+            UploadEvent::Provider(Event::TransferAborted {
+                connection_id,
+                request_id,
+                ..
+            }) => state
+                .uploads
+                .lock()
+                .await
+                .finished((connection_id, request_id), false),
+            UploadEvent::Provider(_) => None,
+        };
+
+        if let Some(stats) = updated {
+            handle.emit(events::UPLOAD_STATS, stats).ok();
+        }
+    }
+}
+
+/// Record what the connection fetched and mark the receiver as gone if it has
+/// no other connection.
+async fn closed(handle: &AppHandle, connection_id: u64) {
+    let state = handle.state::<AppState>();
+    let mut tracker = state.uploads.lock().await;
+    let Some(peer) = tracker.peers.remove(&connection_id) else {
+        return;
+    };
+    tracker.requests.retain(|(id, _), _| *id != connection_id);
+
+    let still_connected = tracker
+        .peers
+        .values()
+        .any(|other| other.node_id == peer.node_id);
+
+    let mut updated = Vec::new();
+    for (share, session) in peer.sessions {
+        history::record(
+            handle,
+            HistoryEvent::Upload {
+                receiver: peer.node_id.to_string(),
+                share: share.clone(),
+                files_sent: session.files.len(),
+                files_total: session.files_total,
+                bytes_sent: session.bytes,
+            },
+        );
+
+        let stats = tracker.entry(peer.node_id, &share);
+        stats.connected = still_connected;
+        // Requests cut off with the connection never report their end
+        if !still_connected {
+            stats.transfers_active = 0;
+        }
+        updated.push(stats.clone());
+    }
+    drop(tracker);
+
+    for stats in updated {
+        handle.emit(events::UPLOAD_STATS, stats).ok();
+    }
+}
+
+/// Upload stats of every receiver since the app started, most recent first.
+#[tauri::command]
+pub async fn get_upload_stats(state: State<'_>) -> Result<Vec<ReceiverStats>, Error> {
+    Ok(state.uploads.lock().await.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+
+    const KEY: (u64, u64) = (1, 1);
+    const SHARE: &str = "share";
+
+    /// A tracker with one request for `root`, whose files are `files`.
+    fn tracker(root: Hash, files: &[Hash]) -> (Tracker, NodeId) {
+        let node_id = SecretKey::from_bytes(&[1; 32]).public();
+        let mut tracker = Tracker::default();
+        tracker.peers.insert(
+            KEY.0,
+            Peer {
+                node_id,
+                sessions: HashMap::new(),
+            },
+        );
+        tracker.entry(node_id, SHARE).transfers_active += 1;
+        tracker.requests.insert(
+            KEY,
+            Request {
+                share: SHARE.to_string(),
+                files: files.iter().copied().collect(),
+                hash: root,
+                offset: 0,
+            },
+        );
+        (tracker, node_id)
+    }
+
+    #[test]
+    fn switching_hashes_restarts_the_offset() {
+        let (root, a, b) = (Hash::new(b"root"), Hash::new(b"a"), Hash::new(b"b"));
+        let (mut tracker, _) = tracker(root, &[a, b]);
+
+        tracker.sent(KEY, root, 64, true);
+        tracker.sent(KEY, a, 400, false);
+        tracker.sent(KEY, a, 1000, true);
+        // The next child reports its offsets from its own start
+        tracker.sent(KEY, b, 300, false);
+        let stats = tracker.sent(KEY, b, 500, false).unwrap();
+
+        assert_eq!(stats.bytes_sent, 64 + 1000 + 500);
+        assert_eq!(stats.files_sent, 1);
+    }
+
+    #[test]
+    fn only_completed_files_count() {
+        let (root, a) = (Hash::new(b"root"), Hash::new(b"a"));
+        let (mut tracker, _) = tracker(root, &[a]);
+
+        // The collection itself isn't a file of the share
+        let stats = tracker.sent(KEY, root, 64, true).unwrap();
+        assert_eq!(stats.files_sent, 0);
+
+        let stats = tracker.sent(KEY, a, 10, false).unwrap();
+        assert_eq!(stats.files_sent, 0);
+        let stats = tracker.sent(KEY, a, 20, true).unwrap();
+        assert_eq!(stats.files_sent, 1);
+
+        // Fetching the same file again doesn't count it twice
+        tracker.sent(KEY, root, 64, true);
+        let stats = tracker.sent(KEY, a, 20, true).unwrap();
+        assert_eq!(stats.files_sent, 1);
+        assert_eq!(stats.bytes_sent, 64 + 20 + 64 + 20);
+    }
+
+    #[test]
+    fn finished_requests_are_counted_once() {
+        let root = Hash::new(b"root");
+        let (mut tracker, node_id) = tracker(root, &[]);
+
+        let stats = tracker.finished(KEY, true).unwrap();
+        assert_eq!(stats.transfers_active, 0);
+        assert_eq!(stats.transfers_completed, 1);
+        assert_eq!(stats.transfers_aborted, 0);
+
+        // The request is gone, a late abort or progress is ignored
+        assert!(tracker.finished(KEY, false).is_none());
+        assert!(tracker.sent(KEY, root, 10, false).is_none());
+        assert_eq!(tracker.receivers(SHARE), vec![node_id]);
+    }
+
+    #[test]
+    fn aborted_requests_are_counted() {
+        let (mut tracker, _) = tracker(Hash::new(b"root"), &[]);
+
+        let stats = tracker.finished(KEY, false).unwrap();
+        assert_eq!(stats.transfers_active, 0);
+        assert_eq!(stats.transfers_aborted, 1);
+    }
+}
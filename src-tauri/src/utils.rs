@@ -5,11 +5,17 @@ use std::{
 
 use data_encoding::BASE64;
 use file_icon_provider::get_file_icon as get_file_icon_pkg;
-use image::{DynamicImage, RgbaImage};
-use iroh_blobs::ticket::BlobTicket;
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageReader, RgbaImage};
+use iroh::NodeAddr;
+use iroh_blobs::{
+    rpc::client::blobs::{DownloadMode, DownloadOptions},
+    ticket::BlobTicket,
+    util::SetTagOption,
+    BlobFormat, Hash, Tag,
+};
 use tauri::{AppHandle, Manager};
 
-use crate::iroh::BlobsClient;
+use crate::{error::Error, iroh::BlobsClient, storage};
 
 pub fn file_name_from_path(path: impl AsRef<Path>) -> Result<String, String> {
     let name = path
@@ -62,22 +68,65 @@ impl Throttle {
 }
 
 pub fn get_file_icon(path: impl AsRef<Path>) -> Result<String, String> {
+    get_file_icon_png(path).map(|png| png_to_data_url(&png))
+}
+
+/// The OS file-type icon for `path`, encoded as PNG.
+pub fn get_file_icon_png(path: impl AsRef<Path>) -> Result<Vec<u8>, String> {
     let icon = get_file_icon_pkg(path, 64).map_err(|e| format!("{}", e))?;
     let image = RgbaImage::from_raw(icon.width, icon.height, icon.pixels)
         .map(DynamicImage::ImageRgba8)
         .expect("Failed to convert icon to image");
 
+    png_bytes(&image)
+}
+
+/// Longest side of generated thumbnails, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// A JPEG preview of the image at `path`. Fails for anything that isn't an image.
+pub fn get_thumbnail_jpeg(path: impl AsRef<Path>) -> Result<Vec<u8>, String> {
+    let reader = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| format!("Failed to open image: {}", e))?;
+    if reader.format().is_none() {
+        return Err("Not an image".to_string());
+    }
+
+    let image = reader
+        .decode()
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    // JPEG has no alpha channel
+    let thumbnail =
+        DynamicImage::ImageRgb8(image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgb8());
+
+    let mut jpeg = Vec::new();
+    thumbnail
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, 80))
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    Ok(jpeg)
+}
+
+pub fn png_bytes(image: &DynamicImage) -> Result<Vec<u8>, String> {
     let mut png_data: Vec<u8> = Vec::new();
     let mut cursor = std::io::Cursor::new(&mut png_data);
     image
         .write_to(&mut cursor, image::ImageFormat::Png)
         .map_err(|e| format!("Failed to encode image to PNG: {}", e))?;
+    Ok(png_data)
+}
+
+pub fn png_to_data_url(png: &[u8]) -> String {
+    format!("data:image/png;base64,{}", BASE64.encode(png))
+}
+
+pub fn jpeg_to_data_url(jpeg: &[u8]) -> String {
+    format!("data:image/jpeg;base64,{}", BASE64.encode(jpeg))
+}
 
-    let png_string = format!(
-        "data:image/png;base64,{}",
-        BASE64.encode(png_data.as_slice())
-    );
-    Ok(png_string)
+/// Encode an image as a `data:image/png;base64,...` URL.
+pub fn png_data_url(image: &DynamicImage) -> Result<String, String> {
+    png_bytes(image).map(|png| png_to_data_url(&png))
 }
 
 pub enum LogLevel {
@@ -122,31 +171,65 @@ pub fn validate_file_path(path: impl AsRef<Path>) -> Result<PathBuf, String> {
     Ok(path)
 }
 
+/// Join a file name from a manifest onto `dir`. Names may contain `/` separated
+/// folders but must stay inside `dir`.
+pub fn join_shared_name(dir: &Path, name: &str) -> Result<PathBuf, Error> {
+    let relative = Path::new(name);
+    let is_plain = relative
+        .components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)));
+
+    if name.is_empty() || !is_plain {
+        return Err(Error::InvalidName {
+            name: name.to_string(),
+        });
+    }
+    Ok(dir.join(relative))
+}
+
 pub async fn download_and_read_header(
     blobs: &BlobsClient,
     ticket: BlobTicket,
-) -> Result<String, String> {
+) -> Result<Vec<u8>, Error> {
     let (node_addr, hash, _) = ticket.into_parts();
+    download_blob_bytes(blobs, hash, node_addr, storage::download_tag(hash, hash)).await
+}
+
+/// Fetch a small blob from `node_addr`, protected by `tag`, and read it into memory.
+///
+/// Failing to get the blob from `node_addr` is an [`Error::Connection`], failing
+/// to read it back from the store an [`Error::Io`].
+pub async fn download_blob_bytes(
+    blobs: &BlobsClient,
+    hash: Hash,
+    node_addr: NodeAddr,
+    tag: Tag,
+) -> Result<Vec<u8>, Error> {
+    let unreachable = |message: String| Error::Connection { message };
+    let opts = DownloadOptions {
+        format: BlobFormat::Raw,
+        nodes: vec![node_addr],
+        tag: SetTagOption::Named(tag),
+        mode: DownloadMode::Queued,
+    };
+
     blobs
-        .download(hash.clone(), node_addr)
+        .download_with_opts(hash, opts)
         .await
-        .map_err(|e| format!("Failed to download header file: {}", e))?
+        .map_err(|e| unreachable(format!("Failed to download blob: {}", e)))?
         .finish()
         .await
-        .map_err(|e| format!("Failed to finish downloading header file: {}", e))?;
-
-    let bytes = blobs
-        .read_to_bytes(hash)
-        .await
-        .map_err(|e| format!("Failed to read bytes: {}", e))?;
+        .map_err(|e| unreachable(format!("Failed to finish downloading blob: {}", e)))?;
 
-    let res = String::from_utf8(bytes.to_vec())
-        .map_err(|e| format!("Failed to convert bytes to string: {}", e))?;
+    let bytes = blobs.read_to_bytes(hash).await.map_err(|e| Error::Io {
+        message: format!("Failed to read bytes: {}", e),
+    })?;
 
-    Ok(res)
+    Ok(bytes.to_vec())
 }
 
 use crate::iroh::Iroh;
+use crate::state::settings::Settings;
 #[allow(dead_code)]
 pub async fn setup_temp_iroh(suffix: &str, app: &AppHandle) -> Result<Iroh, String> {
     let data_dir = app
@@ -155,7 +238,7 @@ pub async fn setup_temp_iroh(suffix: &str, app: &AppHandle) -> Result<Iroh, Stri
         .map_err(|e| format!("Failed to get temp dir: {:?}", e))?
         .join(format!("sendit-{suffix}"));
 
-    let iroh = Iroh::new(data_dir)
+    let iroh = Iroh::new(data_dir, &Settings::default())
         .await
         .map_err(|e| format!("Failed to initialize iroh: {}", e))?;
 
@@ -1,7 +1,8 @@
+import { convertFileSrc } from '@tauri-apps/api/core'
 import { emit } from '@tauri-apps/api/event'
 import { invoke } from './utils'
 import { Theme } from '@/context/theme.context'
-import { CANCEL_DOWNLOAD } from './events'
+import { CANCEL_DOWNLOAD, Diagnostics, UploadStats } from './events'
 import { DownloadFile, ValidatedFile } from './types'
 /**
  * Clean up the database directory.
@@ -42,6 +43,55 @@ export function removeFile(path: string) {
   return invoke<void>('remove_file', { path })
 }
 
+/**
+ * Replace a file whose source changed with its current contents.
+ * @param path - The path the file was added from.
+ * @param newPath - The new path when the source was renamed.
+ */
+export function reimportFile(path: string, newPath?: string | null) {
+  return invoke<void>('reimport_file', { path, newPath: newPath ?? null })
+}
+
+/**
+ * Share text or a link next to the files.
+ * @param text - The text to share.
+ * @param name - The name shown to receivers, made up from the text when omitted.
+ * @returns The name the snippet is shared under.
+ */
+export function addSnippet(text: string, name?: string) {
+  return invoke<string>('add_snippet', { text, name: name ?? null })
+}
+
+/**
+ * Share the text currently in the clipboard.
+ * @returns The name the snippet is shared under.
+ */
+export function addClipboardSnippet() {
+  return invoke<string>('add_clipboard_snippet')
+}
+
+/**
+ * List the files currently shared, including the ones restored on startup.
+ */
+export function listSharedFiles() {
+  return invoke<ValidatedFile[]>('list_shared_files')
+}
+
+/**
+ * Share a folder and publish a new revision whenever its contents change.
+ * @param path - The folder to share.
+ */
+export function shareFolder(path: string) {
+  return invoke<void>('share_folder', { path })
+}
+
+/**
+ * Stop sharing the live folder and remove its files from the list.
+ */
+export function stopFolderShare() {
+  return invoke<void>('stop_folder_share')
+}
+
 /**
  * Remove all files from the list.
  * @param path - The path to remove all files from.
@@ -73,6 +123,26 @@ export function downloadFile(file: DownloadFile) {
   return invoke<void>('download_header', { file })
 }
 
+export type ArchiveFormat = 'zip' | 'tar' | 'tar_zst'
+
+/**
+ * Download a whole share into one archive in the download folder.
+ * @param ticket - The doc ticket to use for downloading.
+ * @param format - The kind of archive to write.
+ * @returns The path of the archive.
+ */
+export function downloadArchive(ticket: string, format: ArchiveFormat) {
+  return invoke<string>('download_archive', { ticket, format })
+}
+
+/**
+ * URL that plays a file of the share being received while it downloads.
+ * @param hash - The hash of the file.
+ */
+export function streamUrl(hash: string) {
+  return convertFileSrc(hash, 'stream')
+}
+
 export function getFileIcon(path: string) {
   return invoke<string>('get_file_icon', { path })
 }
@@ -118,3 +188,363 @@ export function isOnboarded() {
 export function appLoaded() {
   return invoke<boolean>('app_loaded')
 }
+
+/**
+ * Push the current share to another node as an offer.
+ * @param nodeId - The node id of the receiver.
+ */
+export function sendOffer(nodeId: string) {
+  return invoke<void>('send_offer', { nodeId })
+}
+
+export type AcceptAction = 'auto_accept' | 'prompt' | 'reject'
+
+export type AcceptRule = {
+  name: string
+  senders: string[]
+  max_total_size: number | null
+  max_file_count: number | null
+  extensions: string[]
+  action: AcceptAction
+  download_dir: string | null
+}
+
+export type AcceptRules = {
+  rules: AcceptRule[]
+  default_action: AcceptAction
+}
+
+/**
+ * Get the rules applied to incoming offers.
+ */
+export function getAcceptRules() {
+  return invoke<AcceptRules>('get_accept_rules')
+}
+
+/**
+ * Replace the rules applied to incoming offers.
+ */
+export function setAcceptRules(rules: AcceptRules) {
+  return invoke<void>('set_accept_rules', { rules })
+}
+
+export type HistoryEntry = {
+  timestamp: number
+  kind: string
+  [key: string]: unknown
+}
+
+/**
+ * Get the transfer history.
+ */
+export function getHistory() {
+  return invoke<HistoryEntry[]>('get_history')
+}
+
+export function clearHistory() {
+  return invoke<void>('clear_history')
+}
+
+/**
+ * Get what each receiver fetched since the app started, most recent first.
+ */
+export function getUploadStats() {
+  return invoke<UploadStats[]>('get_upload_stats')
+}
+
+/**
+ * Get how this node reaches its peers: direct or relayed, latency and addresses.
+ */
+export function getDiagnostics() {
+  return invoke<Diagnostics>('get_diagnostics')
+}
+
+/**
+ * Emit `DIAGNOSTICS` every second until `unwatchDiagnostics` is called.
+ */
+export function watchDiagnostics() {
+  return invoke<void>('watch_diagnostics')
+}
+
+export function unwatchDiagnostics() {
+  return invoke<void>('unwatch_diagnostics')
+}
+
+/**
+ * Create a short share code for the current files.
+ */
+export function createShareCode() {
+  return invoke<string>('create_share_code')
+}
+
+/**
+ * Resolve a share code into a ticket.
+ * @param code - The share code, e.g. 7-purple-sausage.
+ */
+export function redeemShareCode(code: string) {
+  return invoke<string>('redeem_share_code', { code })
+}
+
+export type CompressionMode = 'off' | 'auto' | 'always'
+
+/** Relays carry traffic when no direct path is found, 'custom' ones are written into tickets */
+export type RelayConfig =
+  | { mode: 'default' }
+  | { mode: 'disabled' }
+  | { mode: 'custom'; urls: string[] }
+
+/** How downloads that broke off are retried, only network errors are */
+export type RetryPolicy = {
+  /** 0 turns retrying off */
+  max_retries: number
+  initial_delay_ms: number
+  max_delay_ms: number
+}
+
+export type Settings = {
+  rendezvous_nodes: string[]
+  keep_received_blobs: boolean
+  /** 'auto' compresses text, 'always' everything that gets smaller */
+  compression: CompressionMode
+  retry: RetryPolicy
+  /** Applied on the next start */
+  relay: RelayConfig
+}
+
+/**
+ * Get the app settings.
+ */
+export function getSettings() {
+  return invoke<Settings>('get_settings')
+}
+
+/**
+ * Update the app settings. Changes apply on the next start.
+ */
+export function updateSettings(settings: Settings) {
+  return invoke<void>('update_settings', { settings })
+}
+
+/**
+ * Render a ticket as a QR code.
+ * @returns - PNG data URL
+ */
+export function ticketToQr(ticket: string) {
+  return invoke<string>('ticket_to_qr', { ticket })
+}
+
+/**
+ * Read a ticket from a QR code in an image file.
+ */
+export function decodeQrFromFile(path: string) {
+  return invoke<string>('decode_qr_from_file', { path })
+}
+
+/**
+ * Read a ticket from a QR code image in the clipboard.
+ */
+export function decodeQrFromClipboard() {
+  return invoke<string>('decode_qr_from_clipboard')
+}
+
+export type StorageReport = {
+  blob_count: number
+  total_size: number
+  incomplete_count: number
+  incomplete_size: number
+  reclaimable_size: number
+  tag_count: number
+  orphaned_headers: number
+}
+
+export type StoredBlob = {
+  hash: string
+  size: number
+  complete: boolean
+  tags: string[]
+  shares: string[]
+  /** Unix timestamp in seconds */
+  last_access: number | null
+}
+
+export type ReclaimReport = {
+  deleted_tags: number
+  deleted_blobs: number
+  reclaimed_size: number
+}
+
+/**
+ * Report how much of the blob store is in use and how much can be reclaimed.
+ */
+export function storageReport() {
+  return invoke<StorageReport>('storage_report')
+}
+
+/**
+ * Delete blobs that are no longer referenced by any share or download.
+ */
+export function reclaimStorage() {
+  return invoke<ReclaimReport>('reclaim_storage')
+}
+
+/**
+ * List every blob in the store with its tags, shares and last access time.
+ */
+export function listStoredBlobs() {
+  return invoke<StoredBlob[]>('list_stored_blobs')
+}
+
+/**
+ * Delete the given blobs, or everything not needed by the current share when
+ * no hashes are passed. Header collections from earlier sessions are always removed.
+ */
+export function cleanupStorage(hashes?: string[]) {
+  return invoke<ReclaimReport>('cleanup_storage', { hashes: hashes ?? null })
+}
+
+export type MirrorInfo = {
+  id: string
+  sender: string
+  dir: string
+  delete_removed: boolean
+}
+
+/**
+ * Keep a local folder in sync with another node's share until stopped.
+ * @param ticket - A ticket for the share to follow.
+ * @param dir - The folder to sync into, defaults to the download folder.
+ * @param deleteRemoved - Delete local copies of files removed from the share.
+ * @returns The id of the mirror.
+ */
+export function startMirror(
+  ticket: string,
+  dir: string | null,
+  deleteRemoved: boolean,
+) {
+  return invoke<string>('start_mirror', { ticket, dir, deleteRemoved })
+}
+
+/**
+ * Stop a running mirror.
+ */
+export function stopMirror(id: string) {
+  return invoke<void>('stop_mirror', { id })
+}
+
+/**
+ * List the running mirrors.
+ */
+export function listMirrors() {
+  return invoke<MirrorInfo[]>('list_mirrors')
+}
+
+export type NamedShareInfo = {
+  name: string
+  ticket: string
+  revision: number
+}
+
+/**
+ * Share the current files under a ticket that stays valid as they change.
+ * @returns The named share ticket.
+ */
+export function createNamedShare(name: string) {
+  return invoke<string>('create_named_share', { name })
+}
+
+/**
+ * Get the current named share, if any.
+ */
+export function getNamedShare() {
+  return invoke<NamedShareInfo | null>('get_named_share')
+}
+
+/**
+ * Stop answering for the named share, its ticket stops resolving.
+ */
+export function closeNamedShare() {
+  return invoke<void>('close_named_share')
+}
+
+export type ShareAccess = {
+  /** Node ids the share is offered to. While set, its ticket can't be copied. */
+  recipients: string[]
+  /** Unix timestamp in seconds at which the share is closed. */
+  expires_at: number | null
+}
+
+export type ShareInfo = {
+  id: string
+  name: string
+  files: string[]
+  size: number
+  revision: number
+  access: ShareAccess
+  created_at: number
+  ticket: string | null
+}
+
+/**
+ * Open a new share next to the files on the send page.
+ * @param name - Display name of the share.
+ * @param access - Who the share is for and when it closes.
+ */
+export function createShare(name: string, access?: ShareAccess) {
+  return invoke<ShareInfo>('create_share', { name, access: access ?? null })
+}
+
+/**
+ * List the open shares, oldest first.
+ */
+export function listShares() {
+  return invoke<ShareInfo[]>('list_shares')
+}
+
+/**
+ * Rename a share or replace its access rules.
+ * @param id - The share to edit.
+ * @param changes - The fields to change.
+ */
+export function updateShare(
+  id: string,
+  changes: { name?: string; access?: ShareAccess },
+) {
+  return invoke<ShareInfo>('update_share', {
+    id,
+    name: changes.name ?? null,
+    access: changes.access ?? null,
+  })
+}
+
+/**
+ * Add files to a share and publish its next revision.
+ * @param id - The share to add to.
+ * @param paths - The paths of the files to add.
+ */
+export function addShareFiles(id: string, paths: string[]) {
+  return invoke<ShareInfo>('add_share_files', { id, paths })
+}
+
+/**
+ * Remove a file from a share and publish its next revision.
+ * @param id - The share to remove from.
+ * @param name - The name of the file.
+ */
+export function removeShareFile(id: string, name: string) {
+  return invoke<ShareInfo>('remove_share_file', { id, name })
+}
+
+/**
+ * Offer the latest revision of a share to its recipients.
+ * @returns How many recipients accepted the offer.
+ */
+export function offerShare(id: string) {
+  return invoke<number>('offer_share', { id })
+}
+
+/**
+ * Close a share and release its blobs. Its tickets stop working.
+ */
+export function closeShare(id: string) {
+  return invoke<void>('close_share', { id })
+}
@@ -1,33 +1,90 @@
+import { AppError } from './types'
+
 // DOWNLOAD
 //
 export const DOWNLOAD_FILE_ADDED = 'DOWNLOAD_FILE_ADDED'
+export const DOWNLOAD_FILE_ICON = 'DOWNLOAD_FILE_ICON'
+export const DOWNLOAD_FILE_THUMBNAIL = 'DOWNLOAD_FILE_THUMBNAIL'
 export const DOWNLOAD_FILE_PROGRESS = 'DOWNLOAD_FILE_PROGRESS'
+export const DOWNLOAD_PROGRESS = 'DOWNLOAD_PROGRESS'
 export const DOWNLOAD_FILE_COMPLETED = 'DOWNLOAD_FILE_COMPLETED'
 export const DOWNLOAD_ALL_COMPLETE = 'DOWNLOAD_ALL_COMPLETE'
 export const DOWNLOAD_FILE_ERROR = 'DOWNLOAD_FILE_ERROR'
+export const DOWNLOAD_FILE_RETRYING = 'DOWNLOAD_FILE_RETRYING'
 export const DOWNLOAD_FILE_ABORTED = 'DOWNLOAD_FILE_ABORTED'
 export const CANCEL_DOWNLOAD = 'CANCEL_DOWNLOAD'
+export const DOWNLOAD_SNIPPET = 'DOWNLOAD_SNIPPET'
+
+export type ContentKind = 'file' | 'text' | 'link'
+
+export type Progress = {
+  done: number
+  total: number
+  /** Smoothed, in bytes per second */
+  speed: number
+  /** Seconds left, null while nothing is moving */
+  eta: number | null
+}
 
 export type DownloadFileAdded = {
   name: string
   icon: string
   size: number
+  kind: ContentKind
+  /** Serves the file while it downloads, see `streamUrl` */
+  hash: string
+}
+export type DownloadFileIcon = {
+  name: string
+  icon: string
+}
+/** JPEG preview of an image, sent after its icon */
+export type DownloadFileThumbnail = {
+  name: string
+  thumbnail: string
 }
 export type DownloadFileProgress = {
   name: string
-  progress: number
-  speed: number // bytes per microsecond
+  /** Bytes sent over the wire, fewer than `size` for compressed files */
+  progress: Progress
+  /** Size of the file once exported */
+  size: number
+}
+/** Totals of every file being downloaded */
+export type DownloadProgress = {
+  progress: Progress
+  files_done: number
+  files_total: number
 }
 export type DownloadFileCompleted = { name: string; path: string }
 
 export type DownloadFileAborted = {
   name: string
-  reason: string
+  error: AppError
+}
+
+/** A download broke off and is tried again after `delay_ms` */
+export type DownloadFileRetrying = {
+  name: string
+  /** Starts at 1 */
+  retry: number
+  max_retries: number
+  delay_ms: number
+  error: AppError
 }
 
 export type DownloadFileError = {
   name: string
-  error: string
+  error: AppError
+}
+
+/** Text or a link received with a share. It is not written to disk. */
+export type DownloadSnippet = {
+  name: string
+  kind: ContentKind
+  text: string
+  /** The text was also copied to the clipboard */
+  copied: boolean
 }
 
 // UPLOAD
@@ -36,20 +93,126 @@ export const UPLOAD_FILE_PROGRESS = 'UPLOAD_FILE_PROGRESS'
 export const UPLOAD_FILE_COMPLETED = 'UPLOAD_FILE_COMPLETED'
 export const UPLOAD_FILE_REMOVED = 'UPLOAD_FILE_REMOVED'
 export const UPLOAD_FILE_ERROR = 'UPLOAD_FILE_ERROR'
+export const UPLOAD_FILE_STALE = 'UPLOAD_FILE_STALE'
+export const UPLOAD_STATS = 'UPLOAD_STATS'
 
 export type UploadFileAdded = {
   name: string
   icon: string
   path: string
   size: number
+  /** Size of the stored blob, smaller than `size` for compressed files */
+  stored_size: number
 }
 export type UploadFileProgress = {
   path: string
-  progress: number
+  progress: Progress
 }
 export type UploadFileCompleted = { name: string }
 export type UploadFileRemoved = { name: string }
 export type UploadFileError = {
   name: string
-  error: string
+  error: AppError
+}
+export type UploadFileStale = {
+  name: string
+  path: string
+  reason: 'modified' | 'deleted' | 'renamed'
+  /** New location of the file when it was renamed */
+  renamed_to: string | null
+}
+
+/** What a receiver fetched from a share since the app started */
+export type UploadStats = {
+  node_id: string
+  /** 'outgoing' for the send page, otherwise the share id */
+  share: string
+  files_sent: number
+  files_total: number
+  bytes_sent: number
+  transfers_active: number
+  transfers_completed: number
+  transfers_aborted: number
+  connected: boolean
+  last_seen: number
+}
+
+// SHARE
+export const SHARE_UPDATED = 'SHARE_UPDATED'
+
+export type ShareUpdated = {
+  revision: number
+  ticket: string
+}
+
+export const SHARE_CLOSED = 'SHARE_CLOSED'
+
+export type ShareClosed = {
+  id: string
+}
+
+// MIRROR
+export const MIRROR_UPDATED = 'MIRROR_UPDATED'
+export const MIRROR_ERROR = 'MIRROR_ERROR'
+
+export type MirrorUpdated = {
+  id: string
+  revision: number
+  downloaded: number
+  deleted: number
+}
+export type MirrorError = {
+  id: string
+  error: AppError
+}
+
+// OFFER
+export const OFFER_DECISION = 'OFFER_DECISION'
+
+export type OfferDecision = {
+  sender: string
+  ticket: string
+  action: 'auto_accept' | 'prompt' | 'reject'
+  rule: string | null
+  file_count: number
+  total_size: number
+}
+
+// DIAGNOSTICS
+export const DIAGNOSTICS = 'DIAGNOSTICS'
+
+export type PeerInfo = {
+  node_id: string
+  /** 'mixed' while holepunching, data goes both ways until it settles */
+  path: 'direct' | 'relay' | 'mixed'
+  /** Round trip time in milliseconds */
+  rtt_ms: number | null
+  /** The direct address used */
+  addr: string | null
+  /** The relay used */
+  relay: string | null
+  known_addrs: string[]
+  /** Milliseconds since data was last sent */
+  last_used_ms: number | null
+}
+export type LocalAddr = {
+  addr: string
+  /** 'public' is what the NAT maps to, as seen through STUN */
+  kind: 'local' | 'public' | 'portmapped' | 'unknown'
+}
+export type Diagnostics = {
+  node_id: string
+  home_relay: string | null
+  local_addrs: LocalAddr[]
+  /** Peers with an open path, most recently used first */
+  peers: PeerInfo[]
+}
+
+// SHARE CODE
+export const SHARE_CODE_CLAIMED = 'SHARE_CODE_CLAIMED'
+export const SHARE_CODE_EXPIRED = 'SHARE_CODE_EXPIRED'
+
+export type ShareCodeStatus = {
+  code: string
+  reason: string | null
 }
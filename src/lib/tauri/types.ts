@@ -5,6 +5,38 @@ export type DownloadFile = {
   hash: string
 }
 
+export type ErrorCategory =
+  | 'network'
+  | 'io'
+  | 'validation'
+  | 'protocol'
+  | 'cancelled'
+  | 'internal'
+
+/** Error returned by every command and sent in the `*_ERROR` events */
+export type AppError = {
+  /** Stable, match on this instead of the message */
+  code:
+    | 'file_exists'
+    | 'duplicate_name'
+    | 'invalid_name'
+    | 'invalid_ticket'
+    | 'invalid_input'
+    | 'not_found'
+    | 'too_large'
+    | 'connection'
+    | 'transfer'
+    | 'protocol'
+    | 'cancelled'
+    | 'io'
+    | 'internal'
+  category: ErrorCategory
+  /** Trying again may work */
+  retryable: boolean
+  message: string
+  context: Record<string, string>
+}
+
 export type ValidatedFile = {
   name: string
   icon: string
@@ -10,6 +10,7 @@ import * as log from '@tauri-apps/plugin-log'
 import { revealItemInDir as tauri__revealItemInDir } from '@tauri-apps/plugin-opener'
 import { err, ok, Result } from 'neverthrow'
 import { toast } from 'sonner'
+import { AppError } from './types'
 
 export async function revealItemInDir(
   path: string,
@@ -34,15 +35,15 @@ export async function copyText(text: string): Promise<Result<void, string>> {
 export async function invoke<T>(
   command: string,
   args?: any,
-): Promise<Result<T, string>> {
+): Promise<Result<T, AppError>> {
   try {
     const res: T = await tauri__invoke(command, args)
     return ok(res)
   } catch (error) {
-    let e = error as string
+    let e = error as AppError
     console.log(e)
-    toast.error(e)
-    log.error(e)
+    toast.error(e.message)
+    log.error(`${command} failed (${e.code}): ${e.message}`)
     return err(e)
   }
 }
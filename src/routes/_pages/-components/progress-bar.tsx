@@ -1,4 +1,4 @@
-import { bytesToString } from '@/utils'
+import { bytesToString, secondsToString } from '@/utils'
 import { motion, MotionValue, useTransform } from 'motion/react'
 import { useEffect, useState } from 'react'
 
@@ -6,13 +6,17 @@ type ProgressBarProps = {
   /** Progress percentage */
   progress: MotionValue<number>
   showPercentage?: boolean
+  /** Bytes per second */
   speed?: number
+  /** Seconds left */
+  eta?: number | null
 }
 
 export function ProgressBar({
   progress,
   showPercentage,
   speed,
+  eta,
 }: ProgressBarProps) {
   const progressPercentageStr = useTransform(progress, (x) => `${x}%`)
   const [progressPercentage, setProgressPercentage] = useState(0)
@@ -43,7 +47,8 @@ export function ProgressBar({
 
           {!!speed && (
             <p className='text-muted-foreground mt-1 text-xs'>
-              {bytesToString(speed * 1000_000)}/s
+              {bytesToString(speed)}/s
+              {eta != null && ` · ${secondsToString(eta)} left`}
             </p>
           )}
         </div>
@@ -97,6 +97,7 @@ export function QueueItem({
           showPercentage
           progress={progress}
           speed={(item as any).speed}
+          eta={(item as any).eta}
         />
       )}
     </motion.div>
@@ -4,12 +4,17 @@ import { DropdownMenuItem } from '@/components/ui/dropdown-menu'
 import { Input } from '@/components/ui/input'
 import { api } from '@/lib/tauri'
 import * as events from '@/lib/tauri/events'
-import { listeners } from '@/lib/tauri/utils'
+import { copyText, listeners } from '@/lib/tauri/utils'
 import { AppState, DownloadQueueItem } from '@/state/appstate'
-import { Throttle } from '@/utils'
+import {
+  bytesToString,
+  percentOf,
+  secondsToString,
+  Throttle,
+} from '@/utils'
 import { createFileRoute } from '@tanstack/react-router'
 import { motion, motionValue } from 'motion/react'
-import { useEffect, useRef } from 'react'
+import { useEffect, useRef, useState } from 'react'
 import { toast } from 'sonner'
 import { QueueContainer } from './-components/queue-container'
 import { QueueItem } from './-components/queue-item'
@@ -18,11 +23,15 @@ export const Route = createFileRoute('/_pages/receive')({
   component: ReceivePage,
 })
 
+const PLAYABLE = /\.(mp4|m4v|webm|mkv|mov|ogv|mp3|m4a|ogg|oga|opus|wav|flac)$/i
+
 function ReceivePage() {
   const inputRef = useRef<HTMLInputElement>(null)
+  const [playing, setPlaying] = useState<string | null>(null)
 
   const store = AppState.use(
     'isDownloading',
+    'downloadProgress',
     'clearDownloadQueue',
     'downloadQueue',
     'updateDownloadQueueItemProgress',
@@ -46,7 +55,7 @@ function ReceivePage() {
         if (res.isOk()) return
 
         store.removeFromDownloadQueue(file.name)
-        toast.error(res.error, {
+        toast.error(res.error.message, {
           description: file.name,
         })
       })
@@ -55,6 +64,21 @@ function ReceivePage() {
     AppState.set({ isDownloading: true })
   }
 
+  async function downloadArchive() {
+    const ticket = inputRef.current?.value
+    if (!ticket) return
+
+    store.clearDownloadQueue()
+    AppState.set({ isDownloading: true })
+
+    const res = await api.downloadArchive(ticket, 'zip')
+    if (res.isErr()) {
+      AppState.set({ isDownloading: false })
+      return
+    }
+    toast.success('Saved archive', { description: res.value })
+  }
+
   return (
     <div className='flex flex-1 flex-col overflow-y-hidden'>
       <div className='mb-4 flex flex-col gap-2'>
@@ -78,7 +102,25 @@ function ReceivePage() {
             {store.isDownloading ? 'Downloading...' : 'Download'}
           </motion.span>
         </Button>
+        <Button
+          variant='secondary'
+          disabled={store.isDownloading}
+          onClick={downloadArchive}
+        >
+          Download as zip
+        </Button>
+        {store.downloadProgress && (
+          <TransferSummary totals={store.downloadProgress} />
+        )}
       </div>
+      {playing && (
+        <div className='mb-4 flex flex-col gap-2'>
+          <video className='max-h-48 w-full' src={playing} controls autoPlay />
+          <Button variant='secondary' onClick={() => setPlaying(null)}>
+            Close player
+          </Button>
+        </div>
+      )}
       <QueueContainer>
         {Object.values(store.downloadQueue).map((item) => (
           <QueueItem
@@ -86,12 +128,22 @@ function ReceivePage() {
             item={item}
             dropdownContent={
               !item.done && (
-                <DropdownMenuItem
-                  onClick={() => api.abortDownload(item.name)}
-                  className='cursor-pointer hover:bg-rose-400! dark:hover:bg-rose-600!'
-                >
-                  Cancel
-                </DropdownMenuItem>
+                <>
+                  {item.hash && PLAYABLE.test(item.name) && (
+                    <DropdownMenuItem
+                      onClick={() => setPlaying(api.streamUrl(item.hash!))}
+                      className='cursor-pointer'
+                    >
+                      Play
+                    </DropdownMenuItem>
+                  )}
+                  <DropdownMenuItem
+                    onClick={() => api.abortDownload(item.name)}
+                    className='cursor-pointer hover:bg-rose-400! dark:hover:bg-rose-600!'
+                  >
+                    Cancel
+                  </DropdownMenuItem>
+                </>
               )
             }
             doneLabel='Download complete'
@@ -102,6 +154,20 @@ function ReceivePage() {
   )
 }
 
+function TransferSummary({ totals }: { totals: events.DownloadProgress }) {
+  const { progress, files_done, files_total } = totals
+  return (
+    <p className='text-muted-foreground text-xs'>
+      {files_done}/{files_total} files ·{' '}
+      {bytesToString(progress.done)} of {bytesToString(progress.total)} (
+      {Math.floor(percentOf(progress.done, progress.total))}%)
+      {progress.eta != null &&
+        progress.done < progress.total &&
+        ` · ${bytesToString(progress.speed)}/s · ${secondsToString(progress.eta)} left`}
+    </p>
+  )
+}
+
 export function ReceivePageListeners() {
   useEffect(() => {
     const store = AppState.get()
@@ -114,28 +180,70 @@ export function ReceivePageListeners() {
         store.addToDownloadQueue(item)
       },
 
+      [events.DOWNLOAD_FILE_ICON]: (ev) => {
+        let { name, icon } = ev.payload as events.DownloadFileIcon
+        store.updateDownloadQueueItemIcon(name, icon)
+      },
+
+      [events.DOWNLOAD_FILE_THUMBNAIL]: (ev) => {
+        let { name, thumbnail } = ev.payload as events.DownloadFileThumbnail
+        store.updateDownloadQueueItemIcon(name, thumbnail)
+      },
+
       [events.DOWNLOAD_FILE_PROGRESS]: (ev) => {
-        let { name, progress, speed } =
-          ev.payload as events.DownloadFileProgress
+        let { name, progress } = ev.payload as events.DownloadFileProgress
         if (throttle.isFree(name)) {
-          store.updateDownloadQueueItemProgress(name, progress, speed)
+          store.updateDownloadQueueItemProgress(
+            name,
+            percentOf(progress.done, progress.total),
+            progress.speed,
+            progress.eta,
+          )
+        }
+      },
+
+      [events.DOWNLOAD_PROGRESS]: (ev) => {
+        if (throttle.isFree(events.DOWNLOAD_PROGRESS)) {
+          AppState.set({
+            downloadProgress: ev.payload as events.DownloadProgress,
+          })
         }
       },
 
       [events.DOWNLOAD_FILE_COMPLETED]: (ev) => {
         let { name, path } = ev.payload as events.DownloadFileCompleted
         store.updateDownloadQueueItemPath(name, path)
-        store.updateDownloadQueueItemProgress(name, 100, 0)
+        store.updateDownloadQueueItemProgress(name, 100, 0, null)
+      },
+
+      [events.DOWNLOAD_SNIPPET]: (ev) => {
+        let { name, text, copied } = ev.payload as events.DownloadSnippet
+        toast(copied ? 'Copied to clipboard' : name, {
+          description: text,
+          action: copied
+            ? undefined
+            : { label: 'Copy', onClick: () => copyText(text) },
+        })
       },
 
       [events.DOWNLOAD_ALL_COMPLETE]: () => {
-        AppState.set({ isDownloading: false })
+        AppState.set({ isDownloading: false, downloadProgress: null })
+      },
+
+      [events.DOWNLOAD_FILE_RETRYING]: (ev) => {
+        let { name, retry, max_retries, error } =
+          ev.payload as events.DownloadFileRetrying
+        toast(`Retrying (${retry}/${max_retries})`, {
+          id: `retry-${name}`,
+          description: `${name}: ${error.message}`,
+        })
       },
 
       [events.DOWNLOAD_FILE_ERROR]: (ev) => {
         let { name, error } = ev.payload as events.DownloadFileError
         store.removeFromDownloadQueue(name)
-        toast.error(error, {
+        toast.error(error.message, {
+          id: `retry-${name}`,
           description: name,
         })
       },
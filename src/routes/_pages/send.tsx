@@ -4,7 +4,7 @@ import { QueueItem } from './-components/queue-item'
 import { Button } from '@/components/ui/button'
 import { DropdownMenuItem } from '@/components/ui/dropdown-menu'
 import { events, api, copyText, listeners } from '@/lib/tauri'
-import { sleep, Throttle, ThrottledQueue } from '@/utils'
+import { percentOf, sleep, Throttle, ThrottledQueue } from '@/utils'
 import { AppState, UploadQueueItem } from '@/state/appstate'
 import { createFileRoute } from '@tanstack/react-router'
 import { open } from '@tauri-apps/plugin-dialog'
@@ -158,6 +158,18 @@ export function SendPageListeners() {
     const uploadQ = new ThrottledQueue<UploadQueueItem>(10, 100)
     uploadQ.onRelease(store.addToUploadQueue)
 
+    // Files shared before the last restart are already imported
+    api.listSharedFiles().then((res) => {
+      if (res.isErr()) return
+      store.addToUploadQueue(
+        res.value.map((file) => ({
+          ...file,
+          progress: motionValue(100),
+          done: true,
+        })),
+      )
+    })
+
     const unsub = listeners({
       [events.UPLOAD_FILE_ADDED]: (event) => {
         /*  We are clearing this here to prevent the empty message to flicker after drag-drop
@@ -172,7 +184,10 @@ export function SendPageListeners() {
       [events.UPLOAD_FILE_PROGRESS]: (event) => {
         const file = event.payload as events.UploadFileProgress
         if (throttle.isFree(file.path)) {
-          store.updateUploadQueueItemProgress(file.path, file.progress)
+          store.updateUploadQueueItemProgress(
+            file.path,
+            percentOf(file.progress.done, file.progress.total),
+          )
         }
       },
 
@@ -199,11 +214,26 @@ export function SendPageListeners() {
       [events.UPLOAD_FILE_ERROR]: (event) => {
         const { name, error } = event.payload as events.UploadFileError
         store.removeFromUploadQueue(name)
-        toast.error(error, {
+        toast.error(error.message, {
           description: name,
         })
       },
 
+      [events.UPLOAD_FILE_STALE]: (event) => {
+        const { name, path, reason, renamed_to } =
+          event.payload as events.UploadFileStale
+        const canReimport = reason != 'deleted'
+        toast.warning(`${name} was ${reason} and is no longer shared`, {
+          description: canReimport ? 'Re-import it to share the new version.' : path,
+          action: canReimport
+            ? {
+                label: 'Re-import',
+                onClick: () => api.reimportFile(path, renamed_to),
+              }
+            : undefined,
+        })
+      },
+
       'tauri://drag-enter': async (event) => {
         if (!isSendPage()) return
         let uploadQueueSet = new Set(
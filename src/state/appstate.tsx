@@ -1,5 +1,6 @@
 import { ValidatedFile } from '@/lib/tauri'
 import { User } from '@/lib/tauri/api'
+import { DownloadProgress } from '@/lib/tauri/events'
 import { createSelector } from '@/lib/zustand'
 import { MotionValue } from 'motion/react'
 import { create } from 'zustand'
@@ -14,12 +15,17 @@ export type UploadQueueItem = {
 }
 
 export type DownloadQueueItem = UploadQueueItem & {
+  /** Bytes per second */
   speed: number
+  /** Seconds left */
+  eta: number | null
+  hash?: string
 }
 
 type AppState = {
   user: User | null
   isDownloading: boolean
+  downloadProgress: DownloadProgress | null
 
   downloadQueue: Record<string, DownloadQueueItem>
   uploadQueue: Record<string, UploadQueueItem>
@@ -36,9 +42,11 @@ type AppState = {
     fileName: string,
     progress: number,
     speed: number,
+    eta: number | null,
   ) => void
 
   updateDownloadQueueItemPath: (name: string, path: string) => void
+  updateDownloadQueueItemIcon: (name: string, icon: string) => void
   clearDownloadQueue: () => void
   reorderUploadQueue: () => void
 }
@@ -46,6 +54,7 @@ type AppState = {
 const store = create<AppState>((set, get) => ({
   user: null,
   isDownloading: false,
+  downloadProgress: null,
 
   downloadQueue: {},
   uploadQueue: {},
@@ -99,6 +108,7 @@ const store = create<AppState>((set, get) => ({
     filename: string,
     progress: number,
     speed: number,
+    eta: number | null,
   ) => {
     const entry = get().downloadQueue[filename]
     entry!.progress.set(progress)
@@ -109,6 +119,7 @@ const store = create<AppState>((set, get) => ({
         [filename]: {
           ...entry,
           speed,
+          eta,
           done: progress == 100,
         },
       },
@@ -127,7 +138,20 @@ const store = create<AppState>((set, get) => ({
       },
     }))
   },
-  clearDownloadQueue: () => set({ downloadQueue: {} }),
+  updateDownloadQueueItemIcon: (filename: string, icon: string) => {
+    const entry = get().downloadQueue[filename]
+    if (!entry) return
+    return set((state) => ({
+      downloadQueue: {
+        ...state.downloadQueue,
+        [filename]: {
+          ...entry,
+          icon,
+        },
+      },
+    }))
+  },
+  clearDownloadQueue: () => set({ downloadQueue: {}, downloadProgress: null }),
 
   reorderUploadQueue: () => {
     const uploadQueue = get().uploadQueue
@@ -44,3 +44,15 @@ export function bytesToString(bytes: number) {
   const i = Math.floor(Math.log(bytes) / Math.log(1024))
   return `${(bytes / Math.pow(1024, i)).toFixed(2)} ${sizes[i]}`
 }
+
+export function percentOf(done: number, total: number) {
+  if (total === 0) return 100
+  return (done / total) * 100
+}
+
+export function secondsToString(seconds: number) {
+  if (seconds < 60) return `${seconds}s`
+  const minutes = Math.floor(seconds / 60)
+  if (minutes < 60) return `${minutes}m ${seconds % 60}s`
+  return `${Math.floor(minutes / 60)}h ${minutes % 60}m`
+}